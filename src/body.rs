@@ -0,0 +1,469 @@
+use crate::error::Error;
+use core::ops::Range;
+use embedded_io_async::Read as EmbeddedRead;
+use embedded_io_async_07 as embedded_io_async;
+
+const INVALID_CHUNK: Error = Error::InvalidResponse("Invalid chunked encoding");
+
+/// How the end of a request body is determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BodyFraming {
+    /// `Content-Length` bytes follow the header section; requests without it have no body
+    Length(usize),
+    /// `Transfer-Encoding: chunked`
+    Chunked,
+}
+
+impl BodyFraming {
+    /// Read the framing from a request's header section (without the final blank line)
+    ///
+    /// Repeated `Content-Length` headers must agree, and a request must not carry both
+    /// `Content-Length` and `Transfer-Encoding`. `chunked` is the only transfer coding
+    /// supported.
+    pub(crate) fn from_head(head: &[u8]) -> Result<Self, Error> {
+        const INVALID: Error = Error::InvalidResponse("Invalid Content-Length");
+
+        let head = core::str::from_utf8(head)
+            .map_err(|_| Error::InvalidResponse("Invalid UTF-8 in request"))?;
+        let mut length = None;
+        let mut chunked = false;
+        for line in head.split("\r\n").skip(1) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                if chunked || !value.eq_ignore_ascii_case("chunked") {
+                    return Err(Error::InvalidResponse("Unsupported Transfer-Encoding"));
+                }
+                chunked = true;
+            } else if name.eq_ignore_ascii_case("Content-Length") {
+                if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(INVALID);
+                }
+                let value: usize = value.parse().map_err(|_| Error::RequestTooLarge)?;
+                if length.is_some_and(|length| length != value) {
+                    return Err(INVALID);
+                }
+                length = Some(value);
+            }
+        }
+        match (chunked, length) {
+            (true, Some(_)) => Err(Error::InvalidResponse(
+                "Both Content-Length and Transfer-Encoding",
+            )),
+            (true, None) => Ok(Self::Chunked),
+            (false, length) => Ok(Self::Length(length.unwrap_or(0))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkState {
+    /// Chunk size digits
+    Size { value: usize, digits: usize },
+    /// Chunk extension after the size, ignored
+    Extension(usize),
+    /// LF ending the size line
+    SizeLf(usize),
+    /// Chunk data still to come
+    Data(usize),
+    /// CR after the chunk data
+    DataCr,
+    /// LF after the chunk data
+    DataLf,
+    /// Trailer section; `empty` if nothing was seen on the current line yet
+    Trailer { empty: bool },
+    /// LF ending a trailer line
+    TrailerLf { empty: bool },
+    /// The final blank line has been read
+    Done,
+}
+
+/// Incremental decoder for `Transfer-Encoding: chunked`
+///
+/// The input can be split anywhere, so the decoder works on whatever arrived from the
+/// socket. Trailer fields are skipped.
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkedDecoder {
+    state: ChunkState,
+}
+
+impl ChunkedDecoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: ChunkState::Size {
+                value: 0,
+                digits: 0,
+            },
+        }
+    }
+
+    /// Check whether the whole body, including trailers, has been decoded
+    pub(crate) fn is_done(&self) -> bool {
+        self.state == ChunkState::Done
+    }
+
+    /// Decode the start of `input`
+    ///
+    /// Returns how many bytes were consumed and where the chunk data among them is. Stops at
+    /// the end of the first run of data, so call it again with the rest.
+    fn decode(&mut self, input: &[u8]) -> Result<(usize, Range<usize>), Error> {
+        let mut i = 0;
+        while i < input.len() && !self.is_done() {
+            if let ChunkState::Data(remaining) = self.state {
+                let n = remaining.min(input.len() - i);
+                self.state = if n == remaining {
+                    ChunkState::DataCr
+                } else {
+                    ChunkState::Data(remaining - n)
+                };
+                return Ok((i + n, i..i + n));
+            }
+            self.step(input[i])?;
+            i += 1;
+        }
+        Ok((i, i..i))
+    }
+
+    fn step(&mut self, byte: u8) -> Result<(), Error> {
+        use ChunkState::{DataCr, DataLf, Done, Extension, Size, SizeLf, Trailer, TrailerLf};
+        self.state = match (self.state, byte) {
+            (Size { value, digits }, b) if b.is_ascii_hexdigit() => {
+                let digit = usize::from(match b {
+                    b'0'..=b'9' => b - b'0',
+                    b'a'..=b'f' => b - b'a' + 10,
+                    _ => b - b'A' + 10,
+                });
+                let value = value
+                    .checked_mul(16)
+                    .and_then(|v| v.checked_add(digit))
+                    .ok_or(INVALID_CHUNK)?;
+                Size {
+                    value,
+                    digits: digits + 1,
+                }
+            }
+            (Size { value, digits }, b';' | b' ' | b'\t') if digits > 0 => Extension(value),
+            (Size { value, digits }, b'\r') if digits > 0 => SizeLf(value),
+            (Extension(value), b'\r') => SizeLf(value),
+            (Extension(_) | Trailer { .. }, b'\n') => return Err(INVALID_CHUNK),
+            (state @ Extension(_), _) => state,
+            (SizeLf(0) | TrailerLf { empty: false }, b'\n') => Trailer { empty: true },
+            (SizeLf(value), b'\n') => ChunkState::Data(value),
+            (DataCr, b'\r') => DataLf,
+            (DataLf, b'\n') => Size {
+                value: 0,
+                digits: 0,
+            },
+            (Trailer { empty }, b'\r') => TrailerLf { empty },
+            (Trailer { .. }, _) => Trailer { empty: false },
+            (TrailerLf { empty: true }, b'\n') => Done,
+            _ => return Err(INVALID_CHUNK),
+        };
+        Ok(())
+    }
+
+    /// Check whether `data` holds a whole chunked body, without decoding it
+    pub(crate) fn ends_within(data: &[u8]) -> Result<bool, Error> {
+        let mut decoder = Self::new();
+        let mut rest = data;
+        while !rest.is_empty() && !decoder.is_done() {
+            let (consumed, _) = decoder.decode(rest)?;
+            rest = &rest[consumed..];
+        }
+        Ok(decoder.is_done())
+    }
+
+    /// Decode `data` in place, moving the chunk data to its front
+    ///
+    /// Returns how many bytes were consumed and how many bytes of chunk data were written.
+    /// All of `data` is consumed unless the body ends within it.
+    pub(crate) fn decode_in_place(&mut self, data: &mut [u8]) -> Result<(usize, usize), Error> {
+        let (mut read, mut written) = (0, 0);
+        while read < data.len() && !self.is_done() {
+            let (consumed, range) = self.decode(&data[read..])?;
+            data.copy_within(read + range.start..read + range.end, written);
+            written += range.len();
+            read += consumed;
+        }
+        Ok((read, written))
+    }
+}
+
+#[derive(Debug)]
+enum BodyState {
+    Length(usize),
+    Chunked(ChunkedDecoder),
+}
+
+/// Body of a request, read from the connection while the handler runs
+///
+/// Passed to a [`crate::StreamingHandler`], so that uploads larger than the request buffer
+/// (a firmware image, for example) can be processed piece by piece. Chunked bodies are
+/// decoded on the fly.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let mut piece = [0u8; 512];
+/// loop {
+///     let n = body.read(&mut piece).await?;
+///     if n == 0 {
+///         break;
+///     }
+///     flash.write(offset, &piece[..n]).await?;
+///     offset += n;
+/// }
+/// ```
+pub struct RequestBody<'r, C> {
+    conn: &'r mut C,
+    /// Request buffer behind the header section; raw bytes wait in `buffer[start..end]`
+    buffer: &'r mut [u8],
+    start: usize,
+    end: usize,
+    state: BodyState,
+}
+
+impl<'r, C> RequestBody<'r, C>
+where
+    C: EmbeddedRead,
+    Error: From<C::Error>,
+{
+    /// A body framed by `framing`, whose first `buffered` bytes were read with the headers
+    pub(crate) fn new(
+        conn: &'r mut C,
+        buffer: &'r mut [u8],
+        buffered: usize,
+        framing: BodyFraming,
+    ) -> Self {
+        let state = match framing {
+            BodyFraming::Length(len) => BodyState::Length(len),
+            BodyFraming::Chunked => BodyState::Chunked(ChunkedDecoder::new()),
+        };
+        Self {
+            conn,
+            buffer,
+            start: 0,
+            end: buffered,
+            state,
+        }
+    }
+
+    /// Number of body bytes not read yet, if the request announced its length
+    #[must_use]
+    pub fn remaining(&self) -> Option<usize> {
+        match self.state {
+            BodyState::Length(remaining) => Some(remaining),
+            BodyState::Chunked(_) => None,
+        }
+    }
+
+    /// Check whether the body is sent with `Transfer-Encoding: chunked`
+    #[must_use]
+    pub fn is_chunked(&self) -> bool {
+        matches!(self.state, BodyState::Chunked(_))
+    }
+
+    /// Check whether the whole body has been read
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        match &self.state {
+            BodyState::Length(remaining) => *remaining == 0,
+            BodyState::Chunked(decoder) => decoder.is_done(),
+        }
+    }
+
+    /// Bytes received after the end of the body, e.g. a pipelined request
+    pub(crate) fn unread(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Read the next piece of the body into `buf`
+    ///
+    /// Returns the number of bytes read; `0` means the body is complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PrematureEof`] if the client closes the connection early,
+    /// [`Error::InvalidResponse`] for malformed chunked encoding, and socket errors.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let buffered = &self.buffer[self.start..self.end];
+            match &mut self.state {
+                BodyState::Length(0) => return Ok(0),
+                BodyState::Length(remaining) => {
+                    let limit = (*remaining).min(buf.len());
+                    // Past the buffered bytes, read straight into the caller's buffer
+                    let n = if buffered.is_empty() {
+                        read_some(self.conn, &mut buf[..limit]).await?
+                    } else {
+                        let n = limit.min(buffered.len());
+                        buf[..n].copy_from_slice(&buffered[..n]);
+                        self.start += n;
+                        n
+                    };
+                    *remaining -= n;
+                    return Ok(n);
+                }
+                BodyState::Chunked(decoder) if decoder.is_done() => return Ok(0),
+                BodyState::Chunked(_) if buffered.is_empty() => {
+                    // The end of a chunked body is only known once it has been read, so
+                    // stage raw bytes in the request buffer to keep what follows it
+                    if self.buffer.is_empty() {
+                        return Err(Error::RequestTooLarge);
+                    }
+                    self.end = read_some(self.conn, self.buffer).await?;
+                    self.start = 0;
+                }
+                BodyState::Chunked(decoder) => {
+                    let raw = &buffered[..buffered.len().min(buf.len())];
+                    let (consumed, data) = decoder.decode(raw)?;
+                    buf[..data.len()].copy_from_slice(&raw[data.clone()]);
+                    self.start += consumed;
+                    if !data.is_empty() {
+                        return Ok(data.len());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Read at least one byte, treating a closed connection as an error
+pub(crate) async fn read_some<C>(conn: &mut C, buffer: &mut [u8]) -> Result<usize, Error>
+where
+    C: EmbeddedRead,
+    Error: From<C::Error>,
+{
+    match conn.read(buffer).await? {
+        0 => Err(Error::PrematureEof),
+        n => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_net_08::tcp;
+
+    /// Hands out the input a few bytes at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl embedded_io_async::ErrorType for Trickle<'_> {
+        type Error = tcp::Error;
+    }
+
+    impl EmbeddedRead for Trickle<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn read_all<C>(body: &mut RequestBody<'_, C>) -> Result<std::vec::Vec<u8>, Error>
+    where
+        C: EmbeddedRead,
+        Error: From<C::Error>,
+    {
+        let mut out = std::vec::Vec::new();
+        let mut piece = [0u8; 4];
+        loop {
+            let n = futures_lite::future::block_on(body.read(&mut piece))?;
+            if n == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&piece[..n]);
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let head = b"POST / HTTP/1.1\r\nContent-Length: 5\r\ncontent-length: 5";
+        assert_eq!(
+            BodyFraming::from_head(head).unwrap(),
+            BodyFraming::Length(5)
+        );
+        assert_eq!(
+            BodyFraming::from_head(b"GET / HTTP/1.1\r\nHost: a").unwrap(),
+            BodyFraming::Length(0)
+        );
+        assert_eq!(
+            BodyFraming::from_head(b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked").unwrap(),
+            BodyFraming::Chunked
+        );
+        for bad in [
+            &b"POST / HTTP/1.1\r\nContent-Length: ten"[..],
+            b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3",
+        ] {
+            assert!(matches!(
+                BodyFraming::from_head(bad),
+                Err(Error::InvalidResponse(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_decode_in_place() {
+        let mut data = *b"5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Sum: 1\r\n\r\nnext";
+        let mut decoder = ChunkedDecoder::new();
+        let (read, written) = decoder.decode_in_place(&mut data).unwrap();
+        assert!(decoder.is_done());
+        assert_eq!(&data[..written], b"hello world");
+        assert_eq!(&data[read..], b"next");
+
+        for bad in [&b"5\r\nhelloX"[..], b"\r\n", b"fffffffffffffffff\r\n"] {
+            let mut data = bad.to_vec();
+            assert!(ChunkedDecoder::new().decode_in_place(&mut data).is_err());
+        }
+    }
+
+    #[test]
+    fn test_streaming_chunked_body() {
+        let mut conn = Trickle(b"llo\r\n1A\r\nabcdefghijklmnopqrstuvwxyz\r\n0\r\n\r\n");
+        let mut buffer = [0u8; 16];
+        buffer[..5].copy_from_slice(b"5\r\nhe");
+        let mut body = RequestBody::new(&mut conn, &mut buffer, 5, BodyFraming::Chunked);
+        assert!(body.is_chunked());
+        assert_eq!(
+            read_all(&mut body).unwrap(),
+            b"helloabcdefghijklmnopqrstuvwxyz"
+        );
+        assert!(body.is_finished());
+
+        let mut conn = Trickle(b"");
+        let mut buffer = *b"3\r\nabc\r\n0\r\n\r\nGET";
+        let mut body = RequestBody::new(&mut conn, &mut buffer, 16, BodyFraming::Chunked);
+        assert_eq!(read_all(&mut body).unwrap(), b"abc");
+        let unread = body.unread();
+        assert_eq!(&buffer[unread], b"GET");
+    }
+
+    #[test]
+    fn test_streaming_length_body() {
+        let mut conn = Trickle(b"lo world");
+        let mut buffer = *b"hel";
+        let mut body = RequestBody::new(&mut conn, &mut buffer, 3, BodyFraming::Length(11));
+        assert_eq!(body.remaining(), Some(11));
+        assert_eq!(read_all(&mut body).unwrap(), b"hello world");
+        assert_eq!(body.remaining(), Some(0));
+
+        let mut conn = Trickle(b"");
+        let mut buffer = *b"hello world, and more";
+        let mut body = RequestBody::new(&mut conn, &mut buffer, 21, BodyFraming::Length(11));
+        assert_eq!(read_all(&mut body).unwrap(), b"hello world");
+        let unread = body.unread();
+        assert_eq!(&buffer[unread], b", and more");
+
+        let mut conn = Trickle(b"short");
+        let mut body = RequestBody::new(&mut conn, &mut [], 0, BodyFraming::Length(10));
+        assert!(matches!(read_all(&mut body), Err(Error::PrematureEof)));
+    }
+}
@@ -0,0 +1,38 @@
+/// A write that did not fit into its buffer
+#[derive(Debug)]
+pub(crate) struct Overflow;
+
+/// Counts the bytes written to it
+pub(crate) struct Counter(pub(crate) usize);
+
+impl core::fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Appends to a byte buffer at `len`
+pub(crate) struct SliceWriter<'w> {
+    pub(crate) buffer: &'w mut [u8],
+    pub(crate) len: &'w mut usize,
+}
+
+impl SliceWriter<'_> {
+    /// Append `bytes`, or nothing if they do not all fit
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Result<(), Overflow> {
+        let end = *self.len + bytes.len();
+        self.buffer
+            .get_mut(*self.len..end)
+            .ok_or(Overflow)?
+            .copy_from_slice(bytes);
+        *self.len = end;
+        Ok(())
+    }
+}
+
+impl core::fmt::Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push(s.as_bytes()).map_err(|Overflow| core::fmt::Error)
+    }
+}
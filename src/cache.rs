@@ -0,0 +1,346 @@
+use crate::{header::HttpHeader, response::HttpResponse, status_code::StatusCode};
+use core::cell::{Cell, RefCell};
+use heapless::String;
+
+/// Maximum length of a cached URL
+pub const MAX_URL_LEN: usize = 128;
+/// Maximum length of a cached `ETag` value
+pub const MAX_ETAG_LEN: usize = 64;
+/// Maximum length of a cached `Last-Modified` value
+pub const MAX_DATE_LEN: usize = 32;
+
+/// Validators remembered for one URL
+///
+/// Entries whose URL or validators exceed [`MAX_URL_LEN`], [`MAX_ETAG_LEN`] or
+/// [`MAX_DATE_LEN`] are not cached.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    url: String<MAX_URL_LEN>,
+    etag: Option<String<MAX_ETAG_LEN>>,
+    last_modified: Option<String<MAX_DATE_LEN>>,
+    /// Value of the cache's use counter when the entry was last stored or used
+    last_used: u32,
+}
+
+impl CacheEntry {
+    /// The URL the validators belong to
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The `ETag` of the last full response, sent back as `If-None-Match`
+    #[must_use]
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// The `Last-Modified` date of the last full response, sent back as `If-Modified-Since`
+    #[must_use]
+    pub fn last_modified(&self) -> Option<&str> {
+        self.last_modified.as_deref()
+    }
+
+    /// Build an entry from a response's validators, if it has any that fit
+    fn from_headers(url: &str, headers: &[HttpHeader<'_>]) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|h| h.name.eq_ignore_ascii_case(name))
+                .map(|h| h.value)
+        };
+        let etag = header("ETag").and_then(|v| String::try_from(v).ok());
+        let last_modified = header("Last-Modified").and_then(|v| String::try_from(v).ok());
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            url: String::try_from(url).ok()?,
+            etag,
+            last_modified,
+            last_used: 0,
+        })
+    }
+}
+
+/// Fixed-size cache of `ETag` / `Last-Modified` validators per URL
+///
+/// Attach a cache to an [`crate::HttpClient`] with [`crate::HttpClient::with_cache`]. For every
+/// `GET` the client then sends `If-None-Match` and `If-Modified-Since` with the validators of
+/// the last `200` response for the same URL, and remembers new validators as they arrive.
+///
+/// Only validators are cached, not bodies: when the server answers `304 Not Modified`, the
+/// representation the caller obtained earlier is still current.
+///
+/// When every slot is taken, the least recently used entry is replaced.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use nanofish::{CacheEntry, Conditional, DefaultHttpClient, ValidatorCache};
+///
+/// let mut storage: [Option<CacheEntry>; 4] = [const { None }; 4];
+/// let cache = ValidatorCache::new(&mut storage);
+/// let client = DefaultHttpClient::new(&stack).with_cache(&cache);
+///
+/// match client.get_conditional(url, &[], &mut buffer).await? {
+///     Conditional::Unchanged => { /* keep the current configuration */ }
+///     Conditional::Modified(response, _) => apply(response.body.as_bytes()),
+/// }
+/// ```
+pub struct ValidatorCache<'s> {
+    slots: RefCell<&'s mut [Option<CacheEntry>]>,
+    clock: Cell<u32>,
+}
+
+impl<'s> ValidatorCache<'s> {
+    /// Create an empty cache that remembers up to `storage.len()` URLs
+    #[must_use]
+    pub fn new(storage: &'s mut [Option<CacheEntry>]) -> Self {
+        storage.fill(None);
+        Self {
+            slots: RefCell::new(storage),
+            clock: Cell::new(0),
+        }
+    }
+
+    /// Maximum number of URLs the cache can hold
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.slots.borrow().len()
+    }
+
+    /// Number of cached URLs
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.slots.borrow().iter().flatten().count()
+    }
+
+    /// Check whether the cache is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all entries
+    pub fn clear(&self) {
+        self.slots.borrow_mut().fill(None);
+    }
+
+    /// Get a copy of the validators cached for `url`
+    #[must_use]
+    pub fn get(&self, url: &str) -> Option<CacheEntry> {
+        self.slots
+            .borrow()
+            .iter()
+            .flatten()
+            .find(|e| e.url == url)
+            .cloned()
+    }
+
+    /// Forget the validators for `url`, forcing the next request to fetch it in full
+    pub fn remove(&self, url: &str) {
+        for slot in self.slots.borrow_mut().iter_mut() {
+            if slot.as_ref().is_some_and(|e| e.url == url) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn tick(&self) -> u32 {
+        let now = self.clock.get().wrapping_add(1);
+        self.clock.set(now);
+        now
+    }
+
+    /// Write `If-None-Match` / `If-Modified-Since` header lines for `url`, if it is cached
+    pub(crate) fn write_conditional_headers(
+        &self,
+        out: &mut impl core::fmt::Write,
+        url: &str,
+    ) -> core::fmt::Result {
+        let tick = self.tick();
+        let mut slots = self.slots.borrow_mut();
+        let Some(entry) = slots.iter_mut().flatten().find(|e| e.url == url) else {
+            return Ok(());
+        };
+
+        entry.last_used = tick;
+        if let Some(etag) = &entry.etag {
+            write!(out, "If-None-Match: {etag}\r\n")?;
+        }
+        if let Some(date) = &entry.last_modified {
+            write!(out, "If-Modified-Since: {date}\r\n")?;
+        }
+        Ok(())
+    }
+
+    /// Update the validators for `url` from the response to a `GET`
+    ///
+    /// A `200` replaces the entry (or drops it if the response has no validators), a `304`
+    /// refreshes validators the server sent again, and other statuses leave the cache alone.
+    pub(crate) fn update(&self, url: &str, response: &HttpResponse<'_>) {
+        match response.status_code {
+            StatusCode::Ok => match CacheEntry::from_headers(url, &response.headers) {
+                Some(entry) => self.insert(entry),
+                None => self.remove(url),
+            },
+            StatusCode::NotModified => {
+                if let Some(mut entry) = CacheEntry::from_headers(url, &response.headers)
+                    && let Some(current) = self.get(url)
+                {
+                    // A 304 may omit validators that did not change
+                    entry.etag = entry.etag.or(current.etag);
+                    entry.last_modified = entry.last_modified.or(current.last_modified);
+                    self.insert(entry);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn insert(&self, mut entry: CacheEntry) {
+        entry.last_used = self.tick();
+        let clock = self.clock.get();
+        let mut slots = self.slots.borrow_mut();
+
+        let index = slots
+            .iter()
+            .position(|slot| slot.as_ref().is_some_and(|e| e.url == entry.url))
+            .or_else(|| slots.iter().position(Option::is_none))
+            .or_else(|| {
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, slot)| Some((i, slot.as_ref()?)))
+                    .max_by_key(|(_, e)| clock.wrapping_sub(e.last_used))
+                    .map(|(i, _)| i)
+            });
+        if let Some(index) = index {
+            slots[index] = Some(entry);
+        }
+    }
+}
+
+/// Outcome of a conditional `GET`
+// Boxing is not an option without a heap, and the response is on the stack either way
+#[allow(clippy::large_enum_variant)]
+pub enum Conditional<'a> {
+    /// The server answered `304 Not Modified`; the previously fetched representation is current
+    Unchanged,
+    /// The server sent a new representation, with the number of bytes read
+    Modified(HttpResponse<'a>, usize),
+}
+
+impl Conditional<'_> {
+    /// Check whether the resource is unchanged since it was last fetched
+    #[must_use]
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, Conditional::Unchanged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::ResponseBody;
+
+    fn response(status_code: StatusCode, headers: &[HttpHeader<'static>]) -> HttpResponse<'static> {
+        HttpResponse {
+            status_code,
+            headers: headers.iter().cloned().collect(),
+            body: ResponseBody::Empty,
+        }
+    }
+
+    fn conditional_headers(cache: &ValidatorCache<'_>, url: &str) -> std::string::String {
+        let mut out = std::string::String::new();
+        cache.write_conditional_headers(&mut out, url).unwrap();
+        out
+    }
+
+    const URL: &str = "http://device.local/config";
+
+    #[test]
+    fn test_store_and_send_validators() {
+        let mut storage: [Option<CacheEntry>; 2] = [const { None }; 2];
+        let cache = ValidatorCache::new(&mut storage);
+        assert_eq!(conditional_headers(&cache, URL), "");
+
+        cache.update(
+            URL,
+            &response(
+                StatusCode::Ok,
+                &[
+                    HttpHeader::new("ETag", "\"v1\""),
+                    HttpHeader::new("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+                ],
+            ),
+        );
+        assert_eq!(
+            conditional_headers(&cache, URL),
+            "If-None-Match: \"v1\"\r\nIf-Modified-Since: Wed, 21 Oct 2015 07:28:00 GMT\r\n"
+        );
+        assert_eq!(conditional_headers(&cache, "http://device.local/other"), "");
+    }
+
+    #[test]
+    fn test_update_rules() {
+        let mut storage: [Option<CacheEntry>; 2] = [const { None }; 2];
+        let cache = ValidatorCache::new(&mut storage);
+        cache.update(
+            URL,
+            &response(
+                StatusCode::Ok,
+                &[
+                    HttpHeader::new("ETag", "\"v1\""),
+                    HttpHeader::new("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+                ],
+            ),
+        );
+
+        // Errors do not touch the cache
+        cache.update(URL, &response(StatusCode::ServiceUnavailable, &[]));
+        assert_eq!(cache.get(URL).unwrap().etag(), Some("\"v1\""));
+
+        // A 304 with a new ETag keeps the Last-Modified date it omitted
+        cache.update(
+            URL,
+            &response(
+                StatusCode::NotModified,
+                &[HttpHeader::new("ETag", "\"v2\"")],
+            ),
+        );
+        let entry = cache.get(URL).unwrap();
+        assert_eq!(entry.etag(), Some("\"v2\""));
+        assert_eq!(entry.last_modified(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+
+        // A full response without validators can no longer be revalidated
+        cache.update(URL, &response(StatusCode::Ok, &[]));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut storage: [Option<CacheEntry>; 2] = [const { None }; 2];
+        let cache = ValidatorCache::new(&mut storage);
+        let store = |url: &str| {
+            cache.update(
+                url,
+                &response(StatusCode::Ok, &[HttpHeader::new("ETag", "\"x\"")]),
+            );
+        };
+
+        store("http://a/");
+        store("http://b/");
+        // Using `a` makes `b` the least recently used entry
+        conditional_headers(&cache, "http://a/");
+        store("http://c/");
+
+        assert!(cache.get("http://a/").is_some());
+        assert!(cache.get("http://b/").is_none());
+        assert!(cache.get("http://c/").is_some());
+        assert_eq!(cache.len(), cache.capacity());
+    }
+}
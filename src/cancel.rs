@@ -0,0 +1,157 @@
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+use embassy_sync::blocking_mutex::{
+    Mutex,
+    raw::{CriticalSectionRawMutex, RawMutex},
+};
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use embassy_sync_07 as embassy_sync;
+
+/// Number of waiters a token tracks before it wakes them all to make room
+///
+/// Requests beyond this still observe the cancellation; they are just polled again more
+/// often while waiting.
+const MAX_WAITERS: usize = 4;
+
+/// Token for cancelling in-flight HTTP requests
+///
+/// Pass a reference to the token to [`crate::HttpClient::request_with_cancel`] and call
+/// [`CancelToken::cancel`] from any task to abandon the request. The client then aborts the
+/// TCP connection (sending a reset, without a TLS `close_notify`), waits at most
+/// `socket_close_delay` for the reset to be flushed and returns [`crate::Error::Cancelled`].
+///
+/// One token can be shared by any number of concurrent requests; cancelling it aborts all of
+/// them. A cancelled token stays cancelled until [`CancelToken::reset`] is called, so the same
+/// token also aborts any request started after the cancellation.
+///
+/// # Type Parameters
+///
+/// * `M` - Raw mutex guarding the waiting requests (default: `CriticalSectionRawMutex`, which
+///   allows sharing the token between tasks and interrupts)
+pub struct CancelToken<M: RawMutex = CriticalSectionRawMutex> {
+    cancelled: AtomicBool,
+    wakers: Mutex<M, RefCell<MultiWakerRegistration<MAX_WAITERS>>>,
+}
+
+impl<M: RawMutex> CancelToken<M> {
+    /// Create a new token that is not cancelled
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            cancelled: AtomicBool::new(false),
+            wakers: Mutex::new(RefCell::new(MultiWakerRegistration::new())),
+        }
+    }
+
+    /// Request cancellation of every request using this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.wakers.lock(|wakers| wakers.borrow_mut().wake());
+    }
+
+    /// Check whether cancellation has been requested
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Clear a previous cancellation so the token can be reused
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Release);
+    }
+
+    /// Wait until cancellation is requested
+    pub(crate) async fn cancelled(&self) {
+        poll_fn(|cx| {
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+            self.wakers
+                .lock(|wakers| wakers.borrow_mut().register(cx.waker()));
+            // A cancellation between the check and the registration found no waker to wake
+            if self.is_cancelled() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+}
+
+impl<M: RawMutex> Default for CancelToken<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    #[test]
+    fn test_cancel_and_reset() {
+        let token = CancelToken::<NoopRawMutex>::new();
+        assert!(!token.is_cancelled());
+
+        token.cancel();
+        assert!(token.is_cancelled());
+
+        token.reset();
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancelled_is_sticky() {
+        let token = CancelToken::<NoopRawMutex>::default();
+        token.cancel();
+
+        futures_lite::future::block_on(token.cancelled());
+        assert!(token.is_cancelled());
+
+        // A second waiter must also observe the cancellation
+        futures_lite::future::block_on(token.cancelled());
+        assert!(token.is_cancelled());
+    }
+
+    /// Waker that records whether it was woken
+    struct Flag(std::sync::atomic::AtomicBool);
+
+    impl std::task::Wake for Flag {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_cancel_wakes_every_waiter() {
+        use core::pin::pin;
+        use core::task::Context;
+        use std::sync::Arc;
+
+        let token = CancelToken::<NoopRawMutex>::new();
+        let mut first = pin!(token.cancelled());
+        let mut second = pin!(token.cancelled());
+        let flags = [
+            Arc::new(Flag(AtomicBool::new(false))),
+            Arc::new(Flag(AtomicBool::new(false))),
+        ];
+        let wakers = flags.clone().map(std::task::Waker::from);
+
+        // Two requests waiting on the token, each with its own waker
+        let mut cx = Context::from_waker(&wakers[0]);
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        let mut cx = Context::from_waker(&wakers[1]);
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        token.cancel();
+        assert!(flags.iter().all(|flag| flag.0.load(Ordering::SeqCst)));
+        let mut cx = Context::from_waker(&wakers[0]);
+        assert!(first.poll(&mut cx).is_ready());
+        let mut cx = Context::from_waker(&wakers[1]);
+        assert!(second.poll(&mut cx).is_ready());
+    }
+}
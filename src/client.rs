@@ -1,29 +1,44 @@
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+use crate::codec::{BodyCodec, DecodeBody, EncodeBody};
+#[cfg(feature = "compression")]
+use crate::compression::{self, ContentEncoding};
 use crate::{
-    error::Error,
-    header::HttpHeader,
+    body::ChunkedDecoder,
+    cache::{Conditional, MAX_URL_LEN, ValidatorCache},
+    cancel::CancelToken,
+    cookie::CookieJar,
+    download::{Download, DownloadSink, Piece},
+    error::{Error, TimeoutKind},
+    form::FormEncoder,
+    header::{HttpHeader, has_token, mime_types},
     method::HttpMethod,
+    multipart::Multipart,
     options::HttpClientOptions,
-    response::{HttpResponse, ResponseBody},
+    proxy::{HttpProxy, Proxy, open_connect_tunnel, write_basic_credentials},
+    request::find_double_crlf,
+    response::{HttpResponse, ResponseBody, parse_status_line},
+    socks::socks5_connect,
     status_code::StatusCode,
 };
-#[cfg(feature = "tls")]
-use defmt::debug;
-use defmt::error;
+use embassy_futures::select::{Either, select};
+use embassy_futures_01 as embassy_futures;
 use embassy_net::{
-    Stack,
+    IpAddress, Stack,
     dns::{self, DnsSocket},
     tcp::TcpSocket,
 };
 use embassy_net_08 as embassy_net;
-#[cfg(feature = "tls")]
-use embassy_time::Instant;
-use embassy_time::Timer;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync_07 as embassy_sync;
+use embassy_time::{Instant, Timer, with_timeout};
 use embassy_time_05 as embassy_time;
-use embedded_io_async::Write as EmbeddedWrite;
+use embedded_io_async::{Read as EmbeddedRead, Write as EmbeddedWrite};
 use embedded_io_async_07 as embedded_io_async;
 
+use core::fmt::Write as _;
+use core::net::Ipv6Addr;
 #[cfg(feature = "tls")]
-use embedded_tls::{Aes128GcmSha256, NoVerify, TlsConfig, TlsConnection, TlsContext};
+use embedded_tls::{Aes128GcmSha256, TlsConfig, TlsConnection, TlsContext, UnsecureProvider};
 #[cfg(feature = "tls")]
 use embedded_tls_018 as embedded_tls;
 use heapless::Vec;
@@ -35,6 +50,10 @@ use rand_chacha_03 as rand_chacha;
 use rand_core::SeedableRng;
 #[cfg(feature = "tls")]
 use rand_core_06 as rand_core;
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "json")]
+use serde_json_core_06 as serde_json_core;
 
 const REQUEST_SIZE: usize = 1024;
 const MAX_HEADERS: usize = 16;
@@ -64,11 +83,86 @@ pub type SmallHttpClient<'a> = HttpClient<
 macro_rules! try_push {
     ($expr:expr) => {
         if $expr.is_err() {
-            return Err(Error::InvalidResponse("Request buffer overflow"));
+            return Err(Error::RequestTooLarge);
         }
     };
 }
 
+/// Components of an `http://` or `https://` endpoint URL
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Url<'u> {
+    /// Whether the URL uses the `https` scheme
+    https: bool,
+    /// Authority as written in the URL (`host` or `host:port`), used for the Host header
+    authority: &'u str,
+    /// Host name without port, and without the brackets around an IPv6 literal
+    host: &'u str,
+    /// Explicit port, or the scheme's default port
+    port: u16,
+    /// Path and query without the fragment; may be empty or start with `?`
+    path: &'u str,
+}
+
+impl<'u> Url<'u> {
+    /// Split an endpoint URL into its components
+    fn parse(endpoint: &'u str) -> Result<Self, Error> {
+        let (https, rest) = if let Some(rest) = endpoint.strip_prefix("http://") {
+            (false, rest)
+        } else if let Some(rest) = endpoint.strip_prefix("https://") {
+            (true, rest)
+        } else {
+            return Err(Error::InvalidUrl);
+        };
+
+        let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let authority = &rest[..authority_end];
+        let path = rest[authority_end..].split('#').next().unwrap_or_default();
+
+        let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+            // IPv6 literal: `[addr]` or `[addr]:port`
+            let (host, rest) = bracketed.split_once(']').ok_or(Error::InvalidUrl)?;
+            if host.parse::<Ipv6Addr>().is_err() {
+                return Err(Error::InvalidUrl);
+            }
+            let port = match rest {
+                "" => None,
+                _ => Some(rest.strip_prefix(':').ok_or(Error::InvalidUrl)?),
+            };
+            (host, port)
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, _)) if host.contains(':') => return Err(Error::InvalidUrl),
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| Error::InvalidUrl)?,
+            None if https => 443,
+            None => 80,
+        };
+        if host.is_empty() {
+            return Err(Error::InvalidUrl);
+        }
+
+        Ok(Self {
+            https,
+            authority,
+            host,
+            port,
+            path,
+        })
+    }
+
+    /// The URL without its fragment, as used to key the validator cache
+    fn cache_key(&self) -> Option<heapless::String<MAX_URL_LEN>> {
+        let mut key = heapless::String::new();
+        let scheme = if self.https { "https" } else { "http" };
+        write!(key, "{scheme}://{}{}", self.authority, self.path).ok()?;
+        Some(key)
+    }
+}
+
 /// HTTP Client for making HTTP requests with true zero-copy response handling
 ///
 /// This is the main client struct for making HTTP requests. It provides methods
@@ -98,6 +192,12 @@ pub struct HttpClient<
     stack: &'a Stack<'a>,
     /// HTTP client options
     options: HttpClientOptions,
+    /// Cookie store shared across requests, if attached
+    cookie_jar: Option<&'a CookieJar<'a>>,
+    /// Validator cache for conditional `GET` requests, if attached
+    cache: Option<&'a ValidatorCache<'a>>,
+    /// Proxy set with [`HttpClient::with_proxy`], taking precedence over the options
+    proxy: Option<Proxy<'a>>,
 }
 
 impl<
@@ -112,16 +212,55 @@ impl<
     /// Create a new HTTP client with custom buffer sizes and default options
     #[must_use]
     pub fn new(stack: &'a Stack<'a>) -> Self {
+        Self::with_options(stack, HttpClientOptions::default())
+    }
+
+    /// Create a new HTTP client with custom buffer sizes and custom options
+    #[must_use]
+    pub fn with_options(stack: &'a Stack<'a>, options: HttpClientOptions) -> Self {
         Self {
             stack,
-            options: HttpClientOptions::default(),
+            options,
+            cookie_jar: None,
+            cache: None,
+            proxy: None,
         }
     }
 
-    /// Create a new HTTP client with custom buffer sizes and custom options
+    /// Route all requests through an HTTP or SOCKS5 proxy
+    ///
+    /// Unlike [`HttpClientOptions::proxy`], the proxy may borrow its host and credentials
+    /// for the lifetime of the client. It replaces a proxy set in the options.
     #[must_use]
-    pub fn with_options(stack: &'a Stack<'a>, options: HttpClientOptions) -> Self {
-        Self { stack, options }
+    pub fn with_proxy(mut self, proxy: impl Into<Proxy<'a>>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// The proxy requests are sent through, if any
+    fn proxy(&self) -> Option<&Proxy<'a>> {
+        self.proxy.as_ref().or(self.options.proxy.as_ref())
+    }
+
+    /// Attach a cookie store
+    ///
+    /// Cookies set by responses are kept in `jar`, and matching cookies are sent with every
+    /// request unless the caller passes its own `Cookie` header.
+    #[must_use]
+    pub fn with_cookie_jar(mut self, jar: &'a CookieJar<'a>) -> Self {
+        self.cookie_jar = Some(jar);
+        self
+    }
+
+    /// Attach a validator cache for conditional requests
+    ///
+    /// `GET` requests then carry `If-None-Match` / `If-Modified-Since` for URLs fetched
+    /// before, unless the caller passes either header itself. Use
+    /// [`HttpClient::get_conditional`] to tell a `304 Not Modified` apart from new content.
+    #[must_use]
+    pub fn with_cache(mut self, cache: &'a ValidatorCache<'a>) -> Self {
+        self.cache = Some(cache);
+        self
     }
 
     /// Make an HTTP request with zero-copy response handling
@@ -187,148 +326,424 @@ impl<
         body: Option<&[u8]>,
         response_buffer: &'b mut [u8],
     ) -> Result<(HttpResponse<'b>, usize), Error> {
-        let (scheme, host_port) = if let Some(rest) = endpoint.strip_prefix("http://") {
-            ("http", rest)
-        } else if let Some(rest) = endpoint.strip_prefix("https://") {
-            ("https", rest)
-        } else {
-            return Err(Error::InvalidUrl);
-        };
+        self.perform(
+            method,
+            endpoint,
+            headers,
+            body,
+            response_buffer,
+            core::future::pending(),
+        )
+        .await
+    }
 
-        let mut url_parts = heapless::Vec::<&str, 8>::new();
-        for part in host_port.splitn(8, '/') {
-            if url_parts.push(part).is_err() {
-                break;
-            }
-        }
-        if url_parts.is_empty() {
-            return Err(Error::InvalidUrl);
-        }
+    /// Make an HTTP request that can be aborted through a [`CancelToken`]
+    ///
+    /// Behaves like [`HttpClient::request`], but when `cancel` is triggered before the
+    /// response has been read the socket is aborted (the peer receives a TCP reset and, for
+    /// HTTPS, no TLS `close_notify` is sent) and [`Error::Cancelled`] is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Cancelled`] if the token is cancelled, otherwise the same errors as
+    /// [`HttpClient::request`].
+    pub async fn request_with_cancel<'b, M: RawMutex>(
+        &self,
+        method: HttpMethod,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        body: Option<&[u8]>,
+        response_buffer: &'b mut [u8],
+        cancel: &CancelToken<M>,
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        self.perform(
+            method,
+            endpoint,
+            headers,
+            body,
+            response_buffer,
+            cancel.cancelled(),
+        )
+        .await
+    }
 
-        let host = url_parts[0];
-        let path = &host_port[host.len()..];
+    /// Run a request and apply `error_on_status` to the response
+    async fn perform<'b>(
+        &self,
+        method: HttpMethod,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        body: Option<&[u8]>,
+        response_buffer: &'b mut [u8],
+        cancelled: impl Future<Output = ()>,
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        let (response, total_read) = self
+            .fetch(method, endpoint, headers, body, response_buffer, cancelled)
+            .await?;
+
+        // A 304 answers a conditional request and is not a failure
+        if self.options.error_on_status
+            && !response.is_success()
+            && response.status_code != StatusCode::NotModified
+        {
+            return Err(Error::Status(response.status_code));
+        }
 
-        let (host, port) = if let Some(colon_pos) = host.rfind(':') {
-            if let Ok(port) = host[colon_pos + 1..].parse::<u16>() {
-                (&host[..colon_pos], port)
-            } else {
-                (host, if scheme == "https" { 443 } else { 80 })
-            }
-        } else {
-            (host, if scheme == "https" { 443 } else { 80 })
-        };
+        Ok((response, total_read))
+    }
 
-        let total_read = match scheme {
-            #[cfg(feature = "tls")]
-            "https" => {
-                self.make_https_request(method, (host, port), path, headers, body, response_buffer)
-                    .await?
-            }
-            #[cfg(not(feature = "tls"))]
-            "https" => return Err(Error::UnsupportedScheme("https (TLS support not enabled)")),
-            "http" => {
-                self.make_http_request(method, (host, port), path, headers, body, response_buffer)
-                    .await?
-            }
-            _ => return Err(Error::UnsupportedScheme(scheme)),
-        };
+    /// Run a request under the overall deadline, parse the response and update the cookie
+    /// jar and validator cache, whatever the response status
+    async fn fetch<'b>(
+        &self,
+        method: HttpMethod,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        body: Option<&[u8]>,
+        response_buffer: &'b mut [u8],
+        cancelled: impl Future<Output = ()>,
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        let url = Url::parse(endpoint)?;
+        let total_read = with_timeout(
+            self.options.request_timeout,
+            self.send_request(method, &url, headers, body, response_buffer, cancelled),
+        )
+        .await
+        .map_err(|_| Error::Timeout(TimeoutKind::Request))??;
 
-        let response = Self::parse_http_response_zero_copy(&response_buffer[..total_read])?;
+        let response = Self::parse_response(response_buffer, total_read)?;
+        self.record_response(method, &url, &response);
         Ok((response, total_read))
     }
 
-    /// Make HTTPS request over TLS with zero-copy response handling
-    #[cfg(feature = "tls")]
-    async fn make_https_request(
+    /// Like [`HttpClient::fetch`], but also returns the body bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidResponse`] for a body that is still encoded because the
+    /// `compression` feature is disabled.
+    #[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+    async fn fetch_plain<'b>(
         &self,
         method: HttpMethod,
-        host_port: (&str, u16),
-        path: &str,
+        endpoint: &str,
         headers: &[HttpHeader<'_>],
         body: Option<&[u8]>,
-        response_buffer: &mut [u8],
-    ) -> Result<usize, Error> {
-        use embedded_tls_018::UnsecureProvider;
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, &'b [u8]), Error> {
+        let (response, _) = self
+            .fetch(
+                method,
+                endpoint,
+                headers,
+                body,
+                response_buffer,
+                core::future::pending(),
+            )
+            .await?;
+        let body = response.plain_body()?;
+        Ok((response, body))
+    }
 
-        let (host, port) = host_port;
-        let mut rx_buffer = [0; TCP_RX];
-        let mut tx_buffer = [0; TCP_TX];
-        let mut socket = TcpSocket::new(*self.stack, &mut rx_buffer, &mut tx_buffer);
-        socket.set_timeout(Some(self.options.socket_timeout));
+    /// Parse the first `total_read` bytes of `buffer` as a response
+    ///
+    /// With the `compression` feature, a body with a `Content-Encoding` is decoded into the part
+    /// of `buffer` that the response did not use. The response then describes the decoded
+    /// body: its `Content-Encoding` and `Content-Length` headers are removed.
+    fn parse_response(buffer: &mut [u8], total_read: usize) -> Result<HttpResponse<'_>, Error> {
+        let (received, spare) = buffer.split_at_mut(total_read);
+        let response = Self::parse_http_response_zero_copy(received)?;
+
+        #[cfg(feature = "compression")]
+        let response = Self::decode_response(response, spare)?;
+        #[cfg(not(feature = "compression"))]
+        let _ = spare;
+
+        Ok(response)
+    }
 
-        let dns_socket = DnsSocket::new(*self.stack);
-        let ip_addresses = dns_socket.query(host, dns::DnsQueryType::A).await?;
+    /// Decode a compressed body into `out` and drop the headers that described the encoding
+    #[cfg(feature = "compression")]
+    fn decode_response<'b>(
+        mut response: HttpResponse<'b>,
+        out: &'b mut [u8],
+    ) -> Result<HttpResponse<'b>, Error> {
+        if response.is_encoded() && !response.body.is_empty() {
+            let body = response.decode_body(out)?;
+            response.headers.retain(|h| {
+                !h.name.eq_ignore_ascii_case("Content-Encoding")
+                    && !h.name.eq_ignore_ascii_case("Content-Length")
+            });
+            response.body = Self::parse_response_body(&response.headers, body);
+        }
+        Ok(response)
+    }
 
-        if ip_addresses.is_empty() {
-            return Err(Error::IpAddressEmpty);
+    /// Store the response's cookies and validators, if a jar or cache is attached
+    fn record_response(&self, method: HttpMethod, url: &Url<'_>, response: &HttpResponse<'_>) {
+        if let Some(jar) = self.cookie_jar {
+            jar.store_from_headers(url.host, url.path, &response.headers, Instant::now());
+        }
+        if let Some(cache) = self.cache
+            && method == HttpMethod::GET
+            && let Some(key) = url.cache_key()
+        {
+            cache.update(&key, response);
         }
+    }
 
-        let ip_addr = ip_addresses[0];
-        let remote_endpoint = (ip_addr, port);
+    /// Dispatch the request over HTTP or HTTPS
+    async fn send_request(
+        &self,
+        method: HttpMethod,
+        url: &Url<'_>,
+        headers: &[HttpHeader<'_>],
+        body: Option<&[u8]>,
+        response_buffer: &mut [u8],
+        cancelled: impl Future<Output = ()>,
+    ) -> Result<usize, Error> {
+        if url.https {
+            #[cfg(feature = "tls")]
+            return self
+                .make_https_request(method, url, headers, body, response_buffer, cancelled)
+                .await;
+            #[cfg(not(feature = "tls"))]
+            return Err(Error::UnsupportedScheme("https (TLS support not enabled)"));
+        }
 
-        socket
-            .connect(remote_endpoint)
+        self.make_http_request(method, url, headers, body, response_buffer, cancelled)
             .await
-            .map_err(|e: embassy_net::tcp::ConnectError| {
-                socket.abort();
-                Error::from(e)
-            })?;
-
-        let mut read_record_buffer = [0; TLS_READ];
-        let mut write_record_buffer = [0; TLS_WRITE];
-
-        let tls_config = TlsConfig::new().with_server_name(host);
-        let mut tls = TlsConnection::new(socket, &mut read_record_buffer, &mut write_record_buffer);
-        let mut rng = ChaCha8Rng::from_seed(timeseed());
+    }
 
-        // tls.open::<_, NoVerify>(TlsContext::new(&tls_config, &mut rng))
-        //     .await?;
+    /// Resolve the host and connect the socket, applying the DNS and connect timeouts
+    async fn connect_socket(
+        &self,
+        socket: &mut TcpSocket<'_>,
+        host: &str,
+        port: u16,
+    ) -> Result<(), Error> {
+        let ip_addr = if let Ok(ip) = host.parse::<Ipv6Addr>() {
+            // IPv6 literals are not resolved by an `A` query
+            IpAddress::Ipv6(ip)
+        } else {
+            let dns_socket = DnsSocket::new(*self.stack);
+            let ip_addresses = with_timeout(
+                self.options.dns_timeout,
+                dns_socket.query(host, dns::DnsQueryType::A),
+            )
+            .await
+            .map_err(|_| Error::Timeout(TimeoutKind::Dns))??;
+            *ip_addresses.first().ok_or(Error::IpAddressEmpty)?
+        };
+        let remote_endpoint = (ip_addr, port);
 
-        tls.open(TlsContext::new(
-            &tls_config,
-            UnsecureProvider::new::<Aes128GcmSha256>(rng),
-        ))
+        match with_timeout(
+            self.options.connect_timeout,
+            socket.connect(remote_endpoint),
+        )
         .await
-        .expect("error establishing TLS connection");
-
-        let http_request = Self::build_http_request(method, host, path, headers, body)?;
-
-        tls.write_all(http_request.as_bytes()).await?;
-
-        if let Some(body_data) = body {
-            tls.write_all(body_data).await?;
+        {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                socket.abort();
+                Err(Error::from(e))
+            }
+            Err(_) => {
+                socket.abort();
+                Err(Error::Timeout(TimeoutKind::Connect))
+            }
         }
+    }
 
-        tls.flush().await?;
-
+    /// Read the response into the buffer until it is complete, the peer closes or retries run out
+    ///
+    /// The first read is bounded by `first_byte_timeout`; subsequent reads rely on the
+    /// socket inactivity timeout.
+    ///
+    /// A response that ends before its announced length is reported as
+    /// [`Error::PrematureEof`]; one that does not fit into the buffer as
+    /// [`Error::HeaderTooLong`] or [`Error::ResponseTooLarge`].
+    async fn read_response<C>(
+        &self,
+        conn: &mut C,
+        method: HttpMethod,
+        response_buffer: &mut [u8],
+    ) -> Result<usize, Error>
+    where
+        C: EmbeddedRead,
+        Error: From<C::Error>,
+    {
+        // Responses to HEAD requests never carry a body, whatever Content-Length says
+        let is_complete = |data: &[u8], eof: bool| {
+            if method == HttpMethod::HEAD {
+                find_double_crlf(data).is_some()
+            } else {
+                Self::is_response_complete(data, eof)
+            }
+        };
         let mut total_read = 0;
+        let mut eof = false;
         let mut retries = self.options.max_retries;
 
         while total_read < response_buffer.len() && retries > 0 {
-            match tls.read(&mut response_buffer[total_read..]).await {
+            let read = conn.read(&mut response_buffer[total_read..]);
+            let result = if total_read == 0 {
+                with_timeout(self.options.first_byte_timeout, read)
+                    .await
+                    .map_err(|_| Error::Timeout(TimeoutKind::FirstByte))?
+            } else {
+                read.await
+            };
+
+            match result {
                 Ok(0) => {
+                    eof = true;
                     break;
                 }
                 Ok(n) => {
                     total_read += n;
-                    if Self::is_response_complete(&response_buffer[..total_read]) {
+                    if is_complete(&response_buffer[..total_read], false) {
                         break;
                     }
                 }
                 Err(e) => {
+                    let e = Error::from(e);
+                    error!("Socket read error: {:?}", e);
                     retries -= 1;
                     if retries > 0 {
                         Timer::after(self.options.retry_delay).await;
                     } else {
-                        return Err(Error::TlsError(e));
+                        return Err(e);
                     }
                 }
             }
         }
 
-        if let Err((_, e)) = tls.close().await {
-            debug!("Error closing TLS connection: {:?}", Error::from(e));
+        if total_read > 0 && !is_complete(&response_buffer[..total_read], eof) {
+            let data = &response_buffer[..total_read];
+            return Err(if total_read < response_buffer.len() {
+                Error::PrematureEof
+            } else if find_double_crlf(data).is_none() {
+                Error::HeaderTooLong
+            } else {
+                Error::ResponseTooLarge
+            });
+        }
+
+        Ok(total_read)
+    }
+
+    /// Connect the socket to the target of `url`, directly or through the configured proxy
+    ///
+    /// HTTP proxies are only asked for a `CONNECT` tunnel when `tunnel` is set; otherwise
+    /// the socket is just connected to the proxy. `scratch` holds the proxy's reply.
+    async fn open_connection(
+        &self,
+        socket: &mut TcpSocket<'_>,
+        url: &Url<'_>,
+        tunnel: bool,
+        scratch: &mut [u8],
+    ) -> Result<(), Error> {
+        match self.proxy() {
+            None => self.connect_socket(socket, url.host, url.port).await,
+            Some(Proxy::Http(proxy)) => {
+                self.connect_socket(socket, proxy.host, proxy.port).await?;
+                if tunnel {
+                    open_connect_tunnel(socket, proxy, url.host, url.port, scratch).await?;
+                }
+                Ok(())
+            }
+            Some(Proxy::Socks5(proxy)) => {
+                self.connect_socket(socket, proxy.host, proxy.port).await?;
+                socks5_connect(socket, proxy, url.host, url.port).await
+            }
         }
+    }
+
+    /// Abort a connection after cancellation and give the reset a chance to be sent
+    async fn abort_socket(&self, socket: &mut TcpSocket<'_>) {
+        socket.abort();
+        let _ = with_timeout(self.options.socket_close_delay, socket.flush()).await;
+    }
+
+    /// Make HTTPS request over TLS with zero-copy response handling
+    ///
+    /// With a proxy configured, a tunnel is opened first (`CONNECT` for HTTP proxies,
+    /// a SOCKS5 handshake otherwise) and the TLS handshake runs inside it.
+    #[cfg(feature = "tls")]
+    async fn make_https_request(
+        &self,
+        method: HttpMethod,
+        url: &Url<'_>,
+        headers: &[HttpHeader<'_>],
+        body: Option<&[u8]>,
+        response_buffer: &mut [u8],
+        cancelled: impl Future<Output = ()>,
+    ) -> Result<usize, Error> {
+        let mut rx_buffer = [0; TCP_RX];
+        let mut tx_buffer = [0; TCP_TX];
+        let mut socket = TcpSocket::new(*self.stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(self.options.socket_timeout));
+
+        let mut read_record_buffer = [0; TLS_READ];
+        let mut write_record_buffer = [0; TLS_WRITE];
+
+        let exchange = async {
+            self.open_connection(&mut socket, url, true, response_buffer)
+                .await?;
+
+            let tls_config = TlsConfig::new().with_server_name(url.host);
+            let mut tls = TlsConnection::new(
+                &mut socket,
+                &mut read_record_buffer,
+                &mut write_record_buffer,
+            );
+            let rng = ChaCha8Rng::from_seed(timeseed());
+
+            with_timeout(
+                self.options.tls_handshake_timeout,
+                tls.open(TlsContext::new(
+                    &tls_config,
+                    UnsecureProvider::new::<Aes128GcmSha256>(rng),
+                )),
+            )
+            .await
+            .map_err(|_| Error::Timeout(TimeoutKind::TlsHandshake))??;
+
+            // The tunnel is transparent, so the origin server gets an origin-form request
+            let http_request =
+                self.build_http_request(method, url, headers, body, None, Instant::now())?;
+
+            tls.write_all(http_request.as_bytes()).await?;
+
+            if let Some(body_data) = body {
+                tls.write_all(body_data).await?;
+            }
+
+            tls.flush().await?;
+
+            let total_read = self
+                .read_response(&mut tls, method, response_buffer)
+                .await?;
+
+            if let Err((_, e)) = tls.close().await {
+                debug!("Error closing TLS connection: {:?}", Error::from(e));
+            }
+
+            Ok::<_, Error>(total_read)
+        };
+
+        let total_read = match select(exchange, cancelled).await {
+            Either::First(Ok(total_read)) => total_read,
+            Either::First(Err(e)) => {
+                socket.abort();
+                return Err(e);
+            }
+            Either::Second(()) => {
+                self.abort_socket(&mut socket).await;
+                return Err(Error::Cancelled);
+            }
+        };
 
         Timer::after(self.options.socket_close_delay).await;
 
@@ -340,79 +755,55 @@ impl<
     }
 
     /// Make HTTP request with zero-copy response handling
+    ///
+    /// With an HTTP proxy configured, the request is sent to the proxy using an
+    /// absolute-form request target. SOCKS5 proxies are used as a plain tunnel.
     async fn make_http_request(
         &self,
         method: HttpMethod,
-        host_port: (&str, u16),
-        path: &str,
+        url: &Url<'_>,
         headers: &[HttpHeader<'_>],
         body: Option<&[u8]>,
         response_buffer: &mut [u8],
+        cancelled: impl Future<Output = ()>,
     ) -> Result<usize, Error> {
-        let (host, port) = host_port;
+        let http_proxy = match self.proxy() {
+            Some(Proxy::Http(proxy)) => Some(proxy),
+            _ => None,
+        };
         let mut rx_buffer = [0; TCP_RX];
         let mut tx_buffer = [0; TCP_TX];
         let mut socket = TcpSocket::new(*self.stack, &mut rx_buffer, &mut tx_buffer);
         socket.set_timeout(Some(self.options.socket_timeout));
 
-        let dns_socket = DnsSocket::new(*self.stack);
-        let ip_addresses = dns_socket.query(host, dns::DnsQueryType::A).await?;
+        let exchange = async {
+            self.open_connection(&mut socket, url, false, response_buffer)
+                .await?;
 
-        if ip_addresses.is_empty() {
-            return Err(Error::IpAddressEmpty);
-        }
-
-        let ip_addr = ip_addresses[0];
-        let remote_endpoint = (ip_addr, port);
+            let http_request =
+                self.build_http_request(method, url, headers, body, http_proxy, Instant::now())?;
 
-        socket
-            .connect(remote_endpoint)
-            .await
-            .map_err(|e: embassy_net::tcp::ConnectError| {
-                socket.abort();
-                Error::from(e)
-            })?;
+            socket.write_all(http_request.as_bytes()).await?;
 
-        let http_request = Self::build_http_request(method, host, path, headers, body)?;
+            if let Some(body_data) = body {
+                socket.write_all(body_data).await?;
+            }
 
-        socket
-            .write_all(http_request.as_bytes())
-            .await
-            .map_err(|e| {
-                socket.abort();
-                Error::from(e)
-            })?;
+            self.read_response(&mut socket, method, response_buffer)
+                .await
+        };
 
-        if let Some(body_data) = body {
-            socket.write_all(body_data).await.map_err(|e| {
+        let total_read = match select(exchange, cancelled).await {
+            Either::First(Ok(total_read)) => total_read,
+            Either::First(Err(e)) => {
                 socket.abort();
-                Error::from(e)
-            })?;
-        }
-
-        let mut total_read = 0;
-        let mut retries = self.options.max_retries;
-
-        while total_read < response_buffer.len() && retries > 0 {
-            match socket.read(&mut response_buffer[total_read..]).await {
-                Ok(0) => {
-                    break;
-                }
-                Ok(n) => {
-                    total_read += n;
-                    if Self::is_response_complete(&response_buffer[..total_read]) {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    error!("Socket read error: {:?}", defmt::Debug2Format(&e));
-                    retries -= 1;
-                    if retries > 0 {
-                        Timer::after(self.options.retry_delay).await;
-                    }
-                }
+                return Err(e);
             }
-        }
+            Either::Second(()) => {
+                self.abort_socket(&mut socket).await;
+                return Err(Error::Cancelled);
+            }
+        };
 
         socket.close();
         Timer::after(self.options.socket_close_delay).await;
@@ -582,11 +973,114 @@ impl<
             .await
     }
 
-    /// Convenience method for making a POST request
+    /// Make a conditional GET request
     ///
-    /// # Arguments
-    /// * `endpoint` - The URL to request (e.g., <http://example.com/api>)
-    /// * `headers` - A slice of HTTP headers to include in the request
+    /// With a [`ValidatorCache`] attached, the request revalidates the last response for
+    /// `endpoint`. A `304 Not Modified` answer is reported as [`Conditional::Unchanged`]
+    /// rather than as a response with an empty body.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`HttpClient::request`].
+    pub async fn get_conditional<'b>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        response_buffer: &'b mut [u8],
+    ) -> Result<Conditional<'b>, Error> {
+        let (response, bytes_read) = self.get(endpoint, headers, response_buffer).await?;
+        Ok(if response.status_code == StatusCode::NotModified {
+            Conditional::Unchanged
+        } else {
+            Conditional::Modified(response, bytes_read)
+        })
+    }
+
+    /// Download a resource in pieces, resuming where a previous attempt stopped
+    ///
+    /// Each piece is requested with `Range: bytes=N-M` and written to `sink` at its offset, and
+    /// `state` is advanced after every successful write. Once an `ETag` is known, resumed
+    /// requests carry `If-Range` so that a changed resource is fetched from the start again.
+    ///
+    /// Every request asks for as many bytes as fit into `response_buffer` after reserving up
+    /// to 1 KiB (at most half of the buffer) for the response head. Servers without range
+    /// support must send the whole resource in one response, so it has to fit into the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Sink`] if `sink` fails, [`Error::Status`] for statuses other than
+    /// `200`, `206` and a `416` marking the end of the resource, [`Error::DownloadChanged`] if
+    /// the resource changes again after the download was restarted once, and otherwise the
+    /// same errors as [`HttpClient::request`]. `state` keeps the progress made before the error.
+    pub async fn download<S: DownloadSink>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        state: &mut Download,
+        sink: &mut S,
+        response_buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        let piece_size = (response_buffer.len() - (response_buffer.len() / 2).min(1024)) as u64;
+        if piece_size == 0 {
+            return Err(Error::ResponseTooLarge);
+        }
+
+        let mut restarts = 0;
+        while !state.is_complete() {
+            let start = state.offset();
+            let end = match state.total() {
+                Some(total) => total.min(start + piece_size) - 1,
+                None => start + piece_size - 1,
+            };
+            let mut range = heapless::String::<48>::new();
+            let _ = write!(range, "bytes={start}-{end}");
+
+            let mut all_headers = Vec::<HttpHeader<'_>, { MAX_HEADERS + 2 }>::new();
+            for header in headers {
+                all_headers
+                    .push(header.clone())
+                    .map_err(|_| Error::TooManyHeaders)?;
+            }
+            // Room for two more headers was reserved above
+            let _ = all_headers.push(HttpHeader::new("Range", &range));
+            if let Some(etag) = state.etag() {
+                let _ = all_headers.push(HttpHeader::new("If-Range", etag));
+            }
+
+            let (response, _) = self
+                .fetch(
+                    HttpMethod::GET,
+                    endpoint,
+                    &all_headers,
+                    None,
+                    &mut *response_buffer,
+                    core::future::pending(),
+                )
+                .await?;
+
+            // `all_headers` borrows the ETag, so it has to go before `state` changes
+            drop(all_headers);
+            match state.accept_within(&response, &mut restarts)? {
+                Piece::Write(offset) => {
+                    let data = response.body.as_bytes();
+                    sink.write_at(offset, data).await.map_err(|_| Error::Sink)?;
+                    state.advance(data.len(), end - start + 1);
+                }
+                Piece::Restart => {
+                    warn!("Resource changed during download, restarting");
+                }
+                Piece::Done => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convenience method for making a POST request
+    ///
+    /// # Arguments
+    /// * `endpoint` - The URL to request (e.g., <http://example.com/api>)
+    /// * `headers` - A slice of HTTP headers to include in the request
     /// * `body` - The request body data
     ///
     /// # Returns
@@ -644,6 +1138,414 @@ impl<
         .await
     }
 
+    /// Make a POST request with a body compressed into `scratch`
+    ///
+    /// The body is encoded with `encoding` and sent with a matching `Content-Encoding`
+    /// header; `Content-Length` is set to the compressed size. Any `Content-Encoding` or
+    /// `Content-Length` in `headers` is ignored. With [`ContentEncoding::Identity`] the body
+    /// is sent unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::EncodedBodyTooLarge`] if the compressed body does not fit into
+    /// `scratch`, otherwise the same errors as [`HttpClient::request`].
+    #[cfg(feature = "compression")]
+    pub async fn post_compressed<'b>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        body: &[u8],
+        encoding: ContentEncoding,
+        scratch: &mut [u8],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        let len = compression::encode(encoding, body, scratch)?;
+        let headers = Self::compressed_headers(headers, encoding)?;
+        self.request(
+            HttpMethod::POST,
+            endpoint,
+            &headers,
+            Some(&scratch[..len]),
+            response_buffer,
+        )
+        .await
+    }
+
+    /// Make a PUT request with a body compressed into `scratch`
+    ///
+    /// See [`HttpClient::post_compressed`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::EncodedBodyTooLarge`] if the compressed body does not fit into
+    /// `scratch`, otherwise the same errors as [`HttpClient::request`].
+    #[cfg(feature = "compression")]
+    pub async fn put_compressed<'b>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        body: &[u8],
+        encoding: ContentEncoding,
+        scratch: &mut [u8],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        let len = compression::encode(encoding, body, scratch)?;
+        let headers = Self::compressed_headers(headers, encoding)?;
+        self.request(
+            HttpMethod::PUT,
+            endpoint,
+            &headers,
+            Some(&scratch[..len]),
+            response_buffer,
+        )
+        .await
+    }
+
+    /// Replace the caller's `Content-Encoding` / `Content-Length` with the `encoding` used
+    #[cfg(feature = "compression")]
+    fn compressed_headers<'h>(
+        headers: &[HttpHeader<'h>],
+        encoding: ContentEncoding,
+    ) -> Result<Vec<HttpHeader<'h>, { MAX_HEADERS + 1 }>, Error> {
+        let mut all_headers = Vec::new();
+        for header in headers.iter().filter(|h| {
+            !h.name.eq_ignore_ascii_case("Content-Encoding")
+                && !h.name.eq_ignore_ascii_case("Content-Length")
+        }) {
+            all_headers
+                .push(header.clone())
+                .map_err(|_| Error::TooManyHeaders)?;
+        }
+        if encoding != ContentEncoding::Identity {
+            // Room for one more header was reserved above
+            let _ = all_headers.push(HttpHeader::new("Content-Encoding", encoding.as_str()));
+        }
+        Ok(all_headers)
+    }
+
+    /// Make a GET request and deserialize the JSON response
+    ///
+    /// Sends `Accept: application/json` unless `headers` has an `Accept` header. The value may
+    /// borrow from `response_buffer`. A body with a `Content-Encoding` is decoded into the part
+    /// of `response_buffer` left after the response, so that part must hold the decoded body.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Status`] for non-2xx responses, [`Error::JsonDeserialize`] if the body
+    /// does not match `R`, the errors of [`HttpResponse::decode_body`] for an encoded body,
+    /// otherwise the same errors as [`HttpClient::request`].
+    #[cfg(feature = "json")]
+    pub async fn get_json<'b, R: Deserialize<'b>>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error> {
+        let headers =
+            Self::with_default_headers(headers, &[HttpHeader::new("Accept", mime_types::JSON)])?;
+        let (response, body) = self
+            .fetch_plain(HttpMethod::GET, endpoint, &headers, None, response_buffer)
+            .await?;
+        Self::json_response(response, body)
+    }
+
+    /// Make a POST request with a JSON body and deserialize the JSON response
+    ///
+    /// `value` is serialized into `scratch` and sent with `Content-Type: application/json`
+    /// unless `headers` has a `Content-Type` header.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::JsonSerialize`] if `value` does not fit into `scratch`, otherwise the
+    /// same errors as [`HttpClient::get_json`].
+    #[cfg(feature = "json")]
+    pub async fn post_json<'b, T: Serialize + ?Sized, R: Deserialize<'b>>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        value: &T,
+        scratch: &mut [u8],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error> {
+        let len = serde_json_core::to_slice(value, scratch)?;
+        let headers = Self::with_default_headers(
+            headers,
+            &[HttpHeader::new("Content-Type", mime_types::JSON)],
+        )?;
+        let (response, body) = self
+            .fetch_plain(
+                HttpMethod::POST,
+                endpoint,
+                &headers,
+                Some(&scratch[..len]),
+                response_buffer,
+            )
+            .await?;
+        Self::json_response(response, body)
+    }
+
+    /// Make a PUT request with a JSON body and deserialize the JSON response
+    ///
+    /// See [`HttpClient::post_json`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`HttpClient::post_json`].
+    #[cfg(feature = "json")]
+    pub async fn put_json<'b, T: Serialize + ?Sized, R: Deserialize<'b>>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        value: &T,
+        scratch: &mut [u8],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error> {
+        let len = serde_json_core::to_slice(value, scratch)?;
+        let headers = Self::with_default_headers(
+            headers,
+            &[HttpHeader::new("Content-Type", mime_types::JSON)],
+        )?;
+        let (response, body) = self
+            .fetch_plain(
+                HttpMethod::PUT,
+                endpoint,
+                &headers,
+                Some(&scratch[..len]),
+                response_buffer,
+            )
+            .await?;
+        Self::json_response(response, body)
+    }
+
+    /// Deserialize the plain `body` of a successful response
+    #[cfg(feature = "json")]
+    fn json_response<'b, R: Deserialize<'b>>(
+        response: HttpResponse<'b>,
+        body: &'b [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error> {
+        if !response.is_success() {
+            return Err(Error::Status(response.status_code));
+        }
+        let (value, _) = serde_json_core::from_slice(body)?;
+        Ok((response, value))
+    }
+
+    /// Make a GET request and decode the response with `codec`
+    ///
+    /// Sends `Accept` with the codec's media type unless `headers` has an `Accept` header,
+    /// and decodes the body like [`HttpResponse::parse_body`]. A body with a
+    /// `Content-Encoding` is first decoded into the part of `response_buffer` left after the
+    /// response, so that part must hold the decoded body.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Status`] for non-2xx responses, [`Error::BodyDecode`] if the response
+    /// is in another format or does not decode into `R`, the errors of
+    /// [`HttpResponse::decode_body`] for an encoded body, otherwise the same errors as
+    /// [`HttpClient::request`].
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    pub async fn get_with_codec<'b, C, R>(
+        &self,
+        codec: C,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error>
+    where
+        C: BodyCodec,
+        R: DecodeBody<'b, C>,
+    {
+        let headers =
+            Self::with_default_headers(headers, &[HttpHeader::new("Accept", C::CONTENT_TYPE)])?;
+        let (response, body) = self
+            .fetch_plain(HttpMethod::GET, endpoint, &headers, None, response_buffer)
+            .await?;
+        Self::decoded_response(codec, response, body)
+    }
+
+    /// Make a POST request with a body encoded by `codec` and decode the response
+    ///
+    /// `value` is encoded into `scratch` and sent with the codec's media type as
+    /// `Content-Type` and `Accept`, unless `headers` already has them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BodyEncode`] if `value` cannot be encoded into `scratch`, otherwise
+    /// the same errors as [`HttpClient::get_with_codec`].
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    pub async fn post_with_codec<'b, C, T, R>(
+        &self,
+        codec: C,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        value: &T,
+        scratch: &mut [u8],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error>
+    where
+        C: BodyCodec,
+        T: EncodeBody<C> + ?Sized,
+        R: DecodeBody<'b, C>,
+    {
+        let len = value.encode_body(scratch)?;
+        let headers = Self::with_default_headers(
+            headers,
+            &[
+                HttpHeader::new("Content-Type", C::CONTENT_TYPE),
+                HttpHeader::new("Accept", C::CONTENT_TYPE),
+            ],
+        )?;
+        let (response, body) = self
+            .fetch_plain(
+                HttpMethod::POST,
+                endpoint,
+                &headers,
+                Some(&scratch[..len]),
+                response_buffer,
+            )
+            .await?;
+        Self::decoded_response(codec, response, body)
+    }
+
+    /// Make a PUT request with a body encoded by `codec` and decode the response
+    ///
+    /// See [`HttpClient::post_with_codec`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`HttpClient::post_with_codec`].
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    pub async fn put_with_codec<'b, C, T, R>(
+        &self,
+        codec: C,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        value: &T,
+        scratch: &mut [u8],
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error>
+    where
+        C: BodyCodec,
+        T: EncodeBody<C> + ?Sized,
+        R: DecodeBody<'b, C>,
+    {
+        let len = value.encode_body(scratch)?;
+        let headers = Self::with_default_headers(
+            headers,
+            &[
+                HttpHeader::new("Content-Type", C::CONTENT_TYPE),
+                HttpHeader::new("Accept", C::CONTENT_TYPE),
+            ],
+        )?;
+        let (response, body) = self
+            .fetch_plain(
+                HttpMethod::PUT,
+                endpoint,
+                &headers,
+                Some(&scratch[..len]),
+                response_buffer,
+            )
+            .await?;
+        Self::decoded_response(codec, response, body)
+    }
+
+    /// Decode the plain `body` of a successful response with `codec`
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    fn decoded_response<'b, C, R>(
+        _codec: C,
+        response: HttpResponse<'b>,
+        body: &'b [u8],
+    ) -> Result<(HttpResponse<'b>, R), Error>
+    where
+        C: BodyCodec,
+        R: DecodeBody<'b, C>,
+    {
+        if !response.is_success() {
+            return Err(Error::Status(response.status_code));
+        }
+        if !crate::codec::has_content_type(&response.headers, C::CONTENT_TYPE) {
+            return Err(Error::BodyDecode("Unexpected Content-Type"));
+        }
+        let value = R::decode_body(body)?;
+        Ok((response, value))
+    }
+
+    /// Copy `headers` and add each of `defaults` unless a header of that name is present
+    fn with_default_headers<'h>(
+        headers: &[HttpHeader<'h>],
+        defaults: &[HttpHeader<'h>],
+    ) -> Result<Vec<HttpHeader<'h>, { MAX_HEADERS + 2 }>, Error> {
+        let mut all_headers = Vec::new();
+        let missing = defaults
+            .iter()
+            .filter(|d| !headers.iter().any(|h| h.name.eq_ignore_ascii_case(d.name)));
+        for header in headers.iter().chain(missing) {
+            all_headers
+                .push(header.clone())
+                .map_err(|_| Error::TooManyHeaders)?;
+        }
+        Ok(all_headers)
+    }
+
+    /// Make a POST request with an `application/x-www-form-urlencoded` body
+    ///
+    /// Sends `Content-Type: application/x-www-form-urlencoded` unless `headers` has a
+    /// `Content-Type` header.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`HttpClient::request`].
+    pub async fn post_form<'b>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        form: &FormEncoder<'_>,
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        let headers = Self::with_default_headers(
+            headers,
+            &[HttpHeader::new("Content-Type", mime_types::FORM)],
+        )?;
+        self.request(
+            HttpMethod::POST,
+            endpoint,
+            &headers,
+            Some(form.as_bytes()),
+            response_buffer,
+        )
+        .await
+    }
+
+    /// Make a POST request with a `multipart/form-data` body
+    ///
+    /// `form` is finished and sent with its `Content-Type` (including the boundary) unless
+    /// `headers` has a `Content-Type` header; `Content-Length` is the size of the body.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::FormTooLarge`] if the closing delimiter does not fit into the form's
+    /// buffer, otherwise the same errors as [`HttpClient::request`].
+    pub async fn post_multipart<'b>(
+        &self,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        form: Multipart<'_>,
+        response_buffer: &'b mut [u8],
+    ) -> Result<(HttpResponse<'b>, usize), Error> {
+        let content_type = form.content_type();
+        let body = form.finish()?;
+        let headers =
+            Self::with_default_headers(headers, &[HttpHeader::new("Content-Type", &content_type)])?;
+        self.request(
+            HttpMethod::POST,
+            endpoint,
+            &headers,
+            Some(body),
+            response_buffer,
+        )
+        .await
+    }
+
     /// Convenience method for making a DELETE request
     ///
     /// # Arguments
@@ -668,28 +1570,16 @@ impl<
     }
 
     /// Parse HTTP response from raw data with zero-copy handling
+    ///
+    /// Only the status line and headers need to be valid UTF-8; the body is left untouched.
     fn parse_http_response_zero_copy(data: &[u8]) -> Result<HttpResponse<'_>, Error> {
-        let response_str = core::str::from_utf8(data)
+        let head_end = find_double_crlf(data).ok_or(Error::PrematureEof)?;
+        let head = core::str::from_utf8(&data[..head_end])
             .map_err(|_| Error::InvalidResponse("Invalid HTTP response encoding"))?;
 
-        let status_line_end = response_str
-            .find("\r\n")
-            .ok_or(Error::InvalidResponse("Invalid HTTP response format"))?;
-
-        let status_line = &response_str[..status_line_end];
-        let status_code_str = status_line
-            .split_whitespace()
-            .nth(1)
-            .ok_or(Error::InvalidResponse("Invalid HTTP status line"))?;
+        let (status_line, headers_section) = head.split_once("\r\n").unwrap_or((head, ""));
+        let status_code = parse_status_line(status_line)?;
 
-        let status_code: StatusCode = status_code_str.try_into()?;
-
-        let headers_end = response_str
-            .find("\r\n\r\n")
-            .ok_or(Error::InvalidResponse("Invalid HTTP response format"))?
-            + 4;
-
-        let headers_section = &response_str[status_line_end + 2..headers_end - 4];
         let mut headers = Vec::<HttpHeader<'_>, MAX_HEADERS>::new();
 
         for header_line in headers_section.split("\r\n") {
@@ -704,11 +1594,7 @@ impl<
             }
         }
 
-        let body_data = if headers_end < data.len() {
-            &data[headers_end..]
-        } else {
-            &[]
-        };
+        let body_data = &data[head_end + 4..];
 
         // Determine response body type and content
         let body = Self::parse_response_body(&headers, body_data);
@@ -773,23 +1659,78 @@ impl<
     }
 
     /// Build HTTP request string
+    ///
+    /// When sending through `proxy`, the request target is written in absolute form
+    /// and `Proxy-Authorization` is added if the proxy has credentials. Cookies from the
+    /// cookie jar that match the URL at `now`, and cached validators for `GET` requests,
+    /// are added unless `headers` already carries the corresponding headers. With the
+    /// `compression` feature and `accept_compressed` enabled, `Accept-Encoding: gzip, deflate`
+    /// is added the same way.
     fn build_http_request(
+        &self,
         method: HttpMethod,
-        host: &str,
-        path: &str,
+        url: &Url<'_>,
         headers: &[HttpHeader<'_>],
         body: Option<&[u8]>,
+        proxy: Option<&HttpProxy<'_>>,
+        now: Instant,
     ) -> Result<heapless::String<RQ>, Error> {
         let mut http_request = heapless::String::<RQ>::new();
 
         try_push!(http_request.push_str(method.as_str()));
         try_push!(http_request.push_str(" "));
-        try_push!(http_request.push_str(path));
+        if proxy.is_some() {
+            try_push!(http_request.push_str("http://"));
+            try_push!(http_request.push_str(url.authority));
+        }
+        if !url.path.starts_with('/') {
+            try_push!(http_request.push_str("/"));
+        }
+        try_push!(http_request.push_str(url.path));
         try_push!(http_request.push_str(" HTTP/1.1\r\n"));
         try_push!(http_request.push_str("Host: "));
-        try_push!(http_request.push_str(host));
+        try_push!(http_request.push_str(url.authority));
         try_push!(http_request.push_str("\r\n"));
 
+        if let Some(credentials) = proxy.and_then(|p| p.credentials.as_ref()) {
+            try_push!(http_request.push_str("Proxy-Authorization: Basic "));
+            try_push!(write_basic_credentials(&mut http_request, credentials));
+            try_push!(http_request.push_str("\r\n"));
+        }
+
+        let has_header = |name: &str| headers.iter().any(|h| h.name.eq_ignore_ascii_case(name));
+
+        if let Some(jar) = self.cookie_jar
+            && !has_header("Cookie")
+        {
+            try_push!(jar.write_cookie_header(
+                &mut http_request,
+                url.host,
+                url.path,
+                url.https,
+                now
+            ));
+        }
+
+        if let Some(cache) = self.cache
+            && method == HttpMethod::GET
+            && !has_header("If-None-Match")
+            && !has_header("If-Modified-Since")
+            && !has_header("Range")
+            && let Some(key) = url.cache_key()
+        {
+            try_push!(cache.write_conditional_headers(&mut http_request, &key));
+        }
+
+        // Byte ranges of an encoded body cannot be decoded on their own
+        #[cfg(feature = "compression")]
+        if self.options.accept_compressed && !has_header("Accept-Encoding") && !has_header("Range")
+        {
+            try_push!(http_request.push_str("Accept-Encoding: "));
+            try_push!(http_request.push_str(crate::compression::SUPPORTED_ENCODINGS));
+            try_push!(http_request.push_str("\r\n"));
+        }
+
         let mut content_length_present = false;
 
         for header in headers {
@@ -804,18 +1745,9 @@ impl<
         }
 
         // Add Content-Length header if body is present and not already specified
-        if !content_length_present && body.is_some() {
+        if !content_length_present && let Some(body) = body {
             try_push!(http_request.push_str("Content-Length: "));
-            let mut len_str = heapless::String::<8>::new();
-            if core::fmt::write(
-                &mut len_str,
-                format_args!("{}", body.unwrap_or_default().len()),
-            )
-            .is_err()
-            {
-                return Err(Error::InvalidResponse("Failed to write content length"));
-            }
-            try_push!(http_request.push_str(&len_str));
+            try_push!(write!(http_request, "{}", body.len()));
             try_push!(http_request.push_str("\r\n"));
         }
 
@@ -826,30 +1758,45 @@ impl<
     }
 
     /// Check if HTTP response is complete
-    fn is_response_complete(data: &[u8]) -> bool {
-        let response_str = core::str::from_utf8(data).unwrap_or_default();
-
-        if !response_str.contains("\r\n\r\n") {
+    ///
+    /// A response is complete once the header terminator was received and its body has
+    /// ended: after `Content-Length` bytes, at the last chunk of a chunked body, or, without
+    /// either, when the server closed the connection (`eof`). `204 No Content` and
+    /// `304 Not Modified` responses never carry a body.
+    fn is_response_complete(data: &[u8], eof: bool) -> bool {
+        let Some(head_end) = find_double_crlf(data) else {
             return false;
+        };
+        let head = core::str::from_utf8(&data[..head_end]).unwrap_or_default();
+        let mut lines = head.split("\r\n");
+
+        let status = lines
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok());
+        if matches!(status, Some(204 | 304)) {
+            return true;
         }
 
-        // Check for Content-Length header to determine if we have the full body
-        if let Some(content_length_pos) = response_str.find("Content-Length:") {
-            let content_length_end = response_str[content_length_pos..]
-                .find("\r\n")
-                .unwrap_or_default()
-                + content_length_pos;
-            let content_length_str =
-                &response_str[content_length_pos + 15..content_length_end].trim();
-
-            if let Ok(content_length) = content_length_str.parse::<usize>() {
-                let headers_end = response_str.find("\r\n\r\n").unwrap_or_default() + 4;
-                let body_received = data.len().saturating_sub(headers_end);
-                return body_received >= content_length;
+        let body = &data[head_end + 4..];
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("Content-Length")
+                && let Ok(content_length) = value.parse::<usize>()
+            {
+                return body.len() >= content_length;
+            }
+            if name.eq_ignore_ascii_case("Transfer-Encoding") && has_token(value, "chunked") {
+                // Reading on cannot repair a malformed body, so stop and let parsing fail
+                return ChunkedDecoder::ends_within(body).unwrap_or(true);
             }
         }
 
-        true
+        // Without a length, the body ends when the server closes the connection
+        eof
     }
 }
 
@@ -864,24 +1811,475 @@ fn timeseed() -> [u8; 32] {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{cache::CacheEntry, cookie::Cookie};
     use embassy_net::Stack;
 
     #[test]
     fn test_is_response_complete_headers_only() {
         let data = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n";
-        assert!(DefaultHttpClient::is_response_complete(data));
+        assert!(!DefaultHttpClient::is_response_complete(data, false));
+        assert!(DefaultHttpClient::is_response_complete(data, true));
+    }
+
+    #[test]
+    fn test_is_response_complete_until_close() {
+        let data =
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\r\n\xff\xfe\x00\x01";
+        // Every segment boundary, as the body arrives piece by piece
+        for end in data.len() - 4..=data.len() {
+            assert!(!DefaultHttpClient::is_response_complete(
+                &data[..end],
+                false
+            ));
+        }
+        assert!(DefaultHttpClient::is_response_complete(data, true));
+    }
+
+    #[test]
+    fn test_is_response_complete_chunked() {
+        let data = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+        for end in data.len() - 30..data.len() {
+            assert!(!DefaultHttpClient::is_response_complete(
+                &data[..end],
+                false
+            ));
+        }
+        assert!(DefaultHttpClient::is_response_complete(data, false));
+        // A server closing before the last chunk cut the body short
+        assert!(!DefaultHttpClient::is_response_complete(
+            &data[..data.len() - 5],
+            true
+        ));
     }
 
     #[test]
     fn test_is_response_complete_with_content_length() {
         let data = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
-        assert!(DefaultHttpClient::is_response_complete(data));
+        assert!(DefaultHttpClient::is_response_complete(data, false));
     }
 
     #[test]
     fn test_is_response_complete_incomplete() {
         let data = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort";
-        assert!(!DefaultHttpClient::is_response_complete(data));
+        assert!(!DefaultHttpClient::is_response_complete(data, false));
+    }
+
+    #[test]
+    fn test_is_response_complete_case_insensitive_and_binary() {
+        let data = b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\n\xff\x00";
+        assert!(!DefaultHttpClient::is_response_complete(data, false));
+        let data = b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\n\xff\x00\x01";
+        assert!(DefaultHttpClient::is_response_complete(data, false));
+    }
+
+    #[test]
+    fn test_is_response_complete_not_modified() {
+        let data = b"HTTP/1.1 304 Not Modified\r\nContent-Length: 100\r\n\r\n";
+        assert!(DefaultHttpClient::is_response_complete(data, false));
+    }
+
+    #[test]
+    fn test_parse_response_binary_body() {
+        let data = b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\r\n\xff\xfe";
+        let response = DefaultHttpClient::parse_http_response_zero_copy(data).unwrap();
+        assert_eq!(response.status_code, StatusCode::Ok);
+        assert_eq!(response.body.as_bytes(), b"\xff\xfe");
+    }
+
+    #[test]
+    fn test_parse_response_malformed_status_line() {
+        let data = b"HTTP/1.1\r\n\r\n";
+        assert!(matches!(
+            DefaultHttpClient::parse_http_response_zero_copy(data),
+            Err(Error::MalformedStatusLine)
+        ));
+        let data = b"garbage 200 OK\r\n\r\n";
+        assert!(matches!(
+            DefaultHttpClient::parse_http_response_zero_copy(data),
+            Err(Error::MalformedStatusLine)
+        ));
+        let data = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n";
+        assert!(matches!(
+            DefaultHttpClient::parse_http_response_zero_copy(data),
+            Err(Error::PrematureEof)
+        ));
+    }
+
+    fn url(endpoint: &str) -> Url<'_> {
+        Url::parse(endpoint).unwrap()
+    }
+
+    #[test]
+    fn test_url_parse() {
+        assert_eq!(
+            url("http://example.com:8080/api?x=1#frag"),
+            Url {
+                https: false,
+                authority: "example.com:8080",
+                host: "example.com",
+                port: 8080,
+                path: "/api?x=1",
+            }
+        );
+        let u = url("https://example.com?q");
+        assert_eq!((u.https, u.port, u.path), (true, 443, "?q"));
+        assert_eq!(url("http://example.com").path, "");
+        assert!(matches!(Url::parse("ftp://x"), Err(Error::InvalidUrl)));
+        assert!(matches!(
+            Url::parse("http://example.com:abc/"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(Url::parse("http:///path"), Err(Error::InvalidUrl)));
+    }
+
+    #[test]
+    fn test_url_parse_ipv6() {
+        assert_eq!(
+            url("http://[::1]:8080/api"),
+            Url {
+                https: false,
+                authority: "[::1]:8080",
+                host: "::1",
+                port: 8080,
+                path: "/api",
+            }
+        );
+        let u = url("https://[fe80::1]");
+        assert_eq!((u.host, u.port, u.authority), ("fe80::1", 443, "[fe80::1]"));
+        for endpoint in [
+            "http://::1/",
+            "http://[::1/",
+            "http://[::1]x/",
+            "http://[::1]:/",
+            "http://[example.com]/",
+        ] {
+            assert!(
+                matches!(Url::parse(endpoint), Err(Error::InvalidUrl)),
+                "{endpoint}"
+            );
+        }
+    }
+
+    /// A client whose stack is never touched, for testing request building only
+    fn offline_client<'a>() -> DefaultHttpClient<'a> {
+        let fake_stack: *const Stack = core::ptr::NonNull::dangling().as_ptr();
+        DefaultHttpClient::new(unsafe { &*fake_stack })
+    }
+
+    fn build(
+        client: &DefaultHttpClient<'_>,
+        method: HttpMethod,
+        endpoint: &str,
+        headers: &[HttpHeader<'_>],
+        body: Option<&[u8]>,
+    ) -> Result<heapless::String<REQUEST_SIZE>, Error> {
+        client.build_http_request(
+            method,
+            &url(endpoint),
+            headers,
+            body,
+            None,
+            Instant::from_secs(1),
+        )
+    }
+
+    #[test]
+    fn test_build_request_too_large() {
+        let endpoint = format!("http://example.com{}", "/a".repeat(600));
+        let result = build(&offline_client(), HttpMethod::GET, &endpoint, &[], None);
+        assert!(matches!(result, Err(Error::RequestTooLarge)));
+    }
+
+    #[test]
+    fn test_build_request_content_length() {
+        let request = build(
+            &offline_client(),
+            HttpMethod::POST,
+            "http://example.com/api",
+            &[],
+            Some(b"hello"),
+        )
+        .unwrap();
+        assert!(request.starts_with("POST /api HTTP/1.1\r\nHost: example.com\r\n"));
+        assert!(request.contains("Content-Length: 5\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_build_request_empty_path() {
+        let request = build(
+            &offline_client(),
+            HttpMethod::GET,
+            "http://example.com:8080?x=1",
+            &[],
+            None,
+        )
+        .unwrap();
+        assert!(request.starts_with("GET /?x=1 HTTP/1.1\r\nHost: example.com:8080\r\n"));
+    }
+
+    #[test]
+    fn test_build_request_through_proxy() {
+        let proxy = HttpProxy::new("proxy.local", 3128).with_credentials("user", "pass");
+        let request = offline_client()
+            .build_http_request(
+                HttpMethod::GET,
+                &url("http://example.com/status"),
+                &[],
+                None,
+                Some(&proxy),
+                Instant::from_secs(0),
+            )
+            .unwrap();
+        assert!(request.starts_with(
+            "GET http://example.com/status HTTP/1.1\r\nHost: example.com\r\n\
+             Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"
+        ));
+    }
+
+    #[test]
+    fn test_proxy_from_options() {
+        let fake_stack: *const Stack = core::ptr::NonNull::dangling().as_ptr();
+        let options = HttpClientOptions {
+            proxy: Some(Proxy::Http(HttpProxy::new("proxy.local", 3128))),
+            ..HttpClientOptions::default()
+        };
+        let client = DefaultHttpClient::with_options(unsafe { &*fake_stack }, options);
+        assert!(matches!(client.proxy(), Some(Proxy::Http(p)) if p.host == "proxy.local"));
+
+        let host = std::string::String::from("socks.local");
+        let client = client.with_proxy(crate::Socks5Proxy::new(&host, 1080));
+        assert!(matches!(client.proxy(), Some(Proxy::Socks5(p)) if p.host == "socks.local"));
+        assert!(offline_client().proxy().is_none());
+    }
+
+    #[test]
+    fn test_build_request_with_cookies() {
+        let mut storage: [Option<Cookie>; 2] = [const { None }; 2];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers(
+            "example.com",
+            "/login",
+            &[HttpHeader::new("Set-Cookie", "session=abc; Path=/")],
+            Instant::from_secs(0),
+        );
+        let client = offline_client().with_cookie_jar(&jar);
+        let endpoint = "http://example.com/status";
+
+        let request = build(&client, HttpMethod::GET, endpoint, &[], None).unwrap();
+        assert!(request.contains("\r\nCookie: session=abc\r\n"));
+
+        // An explicit Cookie header replaces the jar's cookies
+        let headers = [HttpHeader::new("Cookie", "manual=1")];
+        let request = build(&client, HttpMethod::GET, endpoint, &headers, None).unwrap();
+        assert!(request.contains("\r\nCookie: manual=1\r\n"));
+        assert!(!request.contains("session=abc"));
+    }
+
+    #[test]
+    fn test_build_request_with_validators() {
+        let mut storage: [Option<CacheEntry>; 2] = [const { None }; 2];
+        let cache = ValidatorCache::new(&mut storage);
+        let endpoint = "http://example.com/config#section";
+        let response = HttpResponse {
+            status_code: StatusCode::Ok,
+            headers: [HttpHeader::new("ETag", "\"abc\"")].into_iter().collect(),
+            body: ResponseBody::Empty,
+        };
+        cache.update(&url(endpoint).cache_key().unwrap(), &response);
+        let client = offline_client().with_cache(&cache);
+
+        let request = build(&client, HttpMethod::GET, endpoint, &[], None).unwrap();
+        assert!(request.contains("\r\nIf-None-Match: \"abc\"\r\n"));
+
+        // Only GET requests are made conditional
+        let request = build(&client, HttpMethod::POST, endpoint, &[], Some(b"x")).unwrap();
+        assert!(!request.contains("If-None-Match"));
+
+        let request = build(
+            &client,
+            HttpMethod::GET,
+            "https://example.com/config",
+            &[],
+            None,
+        );
+        assert!(!request.unwrap().contains("If-None-Match"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_build_request_accept_encoding() {
+        let endpoint = "http://example.com/data";
+
+        let client = offline_client();
+        let request = build(&client, HttpMethod::GET, endpoint, &[], None).unwrap();
+        assert!(request.contains("\r\nAccept-Encoding: gzip, deflate\r\n"));
+
+        let headers = [HttpHeader::new("Accept-Encoding", "identity")];
+        let request = build(&client, HttpMethod::GET, endpoint, &headers, None).unwrap();
+        assert!(!request.contains("gzip"));
+
+        let headers = [HttpHeader::new("Range", "bytes=0-99")];
+        let request = build(&client, HttpMethod::GET, endpoint, &headers, None).unwrap();
+        assert!(!request.contains("Accept-Encoding"));
+
+        let fake_stack: *const Stack = core::ptr::NonNull::dangling().as_ptr();
+        let options = HttpClientOptions {
+            accept_compressed: false,
+            ..HttpClientOptions::default()
+        };
+        let client = DefaultHttpClient::with_options(unsafe { &*fake_stack }, options);
+        let request = build(&client, HttpMethod::GET, endpoint, &[], None).unwrap();
+        assert!(!request.contains("Accept-Encoding"));
+    }
+
+    /// Parse `raw` as the client does after reading it into `buffer`
+    #[cfg(feature = "compression")]
+    fn complete<'b>(raw: &[u8], buffer: &'b mut [u8]) -> Result<HttpResponse<'b>, Error> {
+        buffer[..raw.len()].copy_from_slice(raw);
+        DefaultHttpClient::parse_response(buffer, raw.len())
+    }
+
+    /// A response with `body` compressed with `encoding`
+    #[cfg(feature = "compression")]
+    fn compressed_response(
+        encoding: ContentEncoding,
+        content_type: &str,
+        body: &[u8],
+    ) -> std::vec::Vec<u8> {
+        let mut encoded = [0u8; 256];
+        let len = compression::encode(encoding, body, &mut encoded).unwrap();
+        let mut raw = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\n\
+             Content-Encoding: {}\r\nContent-Length: {len}\r\n\r\n",
+            encoding.as_str()
+        )
+        .into_bytes();
+        raw.extend_from_slice(&encoded[..len]);
+        raw
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_request_decodes_gzip() {
+        let raw = compressed_response(ContentEncoding::Gzip, "text/plain", b"hello hello hello");
+        let mut buffer = [0u8; 256];
+        let response = complete(&raw, &mut buffer).unwrap();
+        assert!(matches!(
+            response.body,
+            ResponseBody::Text("hello hello hello")
+        ));
+        assert_eq!(response.get_header("Content-Type"), Some("text/plain"));
+        assert!(response.get_header("Content-Encoding").is_none());
+        assert!(response.get_header("Content-Length").is_none());
+
+        // The decoded body has to fit after the response
+        let mut small = [0u8; 256];
+        assert!(matches!(
+            complete(&raw, &mut small[..raw.len() + 8]),
+            Err(Error::DecodedBodyTooLarge)
+        ));
+
+        // Bodiless answers keep their headers
+        let raw = b"HTTP/1.1 304 Not Modified\r\nContent-Encoding: gzip\r\n\r\n";
+        let response = complete(raw, &mut small).unwrap();
+        assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+    }
+
+    #[cfg(all(feature = "json", feature = "compression"))]
+    #[test]
+    fn test_json_response_gzip() {
+        #[derive(serde::Deserialize)]
+        struct Reading<'a> {
+            sensor: &'a str,
+            value: u32,
+        }
+
+        let raw = compressed_response(
+            ContentEncoding::Gzip,
+            "application/json",
+            br#"{"sensor":"t1","value":21}"#,
+        );
+        let mut buffer = [0u8; 256];
+        let response = complete(&raw, &mut buffer).unwrap();
+        let body = response.plain_body().unwrap();
+        let (_, reading) = DefaultHttpClient::json_response::<Reading<'_>>(response, body).unwrap();
+        assert_eq!((reading.sensor, reading.value), ("t1", 21));
+    }
+
+    #[cfg(all(feature = "cbor", feature = "compression"))]
+    #[test]
+    fn test_decoded_response_deflate() {
+        use crate::codec::Cbor;
+
+        // CBOR array [1, 2, 3]
+        let raw = compressed_response(
+            ContentEncoding::Deflate,
+            "application/cbor",
+            b"\x83\x01\x02\x03",
+        );
+        let mut buffer = [0u8; 256];
+        let response = complete(&raw, &mut buffer).unwrap();
+        assert_eq!(response.parse_body::<_, [u8; 3]>(Cbor).unwrap(), [1, 2, 3]);
+        let body = response.plain_body().unwrap();
+        let (_, values): (_, [u8; 3]) =
+            DefaultHttpClient::decoded_response(Cbor, response, body).unwrap();
+        assert_eq!(values, [1, 2, 3]);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_headers() {
+        let headers = [
+            HttpHeader::new("Content-Type", "text/csv"),
+            HttpHeader::new("Content-Length", "1000"),
+        ];
+        let all = DefaultHttpClient::compressed_headers(&headers, ContentEncoding::Gzip).unwrap();
+        let names: std::vec::Vec<_> = all.iter().map(|h| (h.name, h.value)).collect();
+        assert_eq!(
+            names,
+            [("Content-Type", "text/csv"), ("Content-Encoding", "gzip")]
+        );
+
+        let all =
+            DefaultHttpClient::compressed_headers(&headers, ContentEncoding::Identity).unwrap();
+        assert_eq!(all.len(), 1);
+
+        // The compressed body gets its own Content-Length
+        let request = build(
+            &offline_client(),
+            HttpMethod::POST,
+            "http://example.com/telemetry",
+            &all,
+            Some(&[0u8; 12]),
+        )
+        .unwrap();
+        assert!(request.contains("Content-Length: 12\r\n"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_with_default_headers() {
+        let defaults = [
+            HttpHeader::new("Content-Type", mime_types::JSON),
+            HttpHeader::new("Accept", mime_types::JSON),
+        ];
+        let headers = [HttpHeader::new("Authorization", "Bearer x")];
+        let all = DefaultHttpClient::with_default_headers(&headers, &defaults).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2].value, "application/json");
+
+        let headers = [HttpHeader::new("accept", "application/geo+json")];
+        let all = DefaultHttpClient::with_default_headers(&headers, &defaults).unwrap();
+        let names: std::vec::Vec<_> = all.iter().map(|h| (h.name, h.value)).collect();
+        assert_eq!(
+            names,
+            [
+                ("accept", "application/geo+json"),
+                ("Content-Type", "application/json")
+            ]
+        );
     }
 
     #[test]
@@ -895,6 +2293,7 @@ mod tests {
             socket_timeout: embassy_time::Duration::from_secs(1),
             retry_delay: embassy_time::Duration::from_millis(1),
             socket_close_delay: embassy_time::Duration::from_millis(1),
+            ..HttpClientOptions::default()
         };
         let client2 = DefaultHttpClient::with_options(unsafe { &*fake_stack }, opts);
         assert_eq!(client.options.max_retries, 5);
@@ -914,6 +2313,7 @@ mod tests {
                 socket_timeout: embassy_time::Duration::from_secs(2),
                 retry_delay: embassy_time::Duration::from_millis(10),
                 socket_close_delay: embassy_time::Duration::from_millis(5),
+                ..HttpClientOptions::default()
             },
         );
         assert_eq!(client_custom.options.max_retries, 3);
@@ -932,6 +2332,7 @@ mod tests {
                 socket_timeout: embassy_time::Duration::from_secs(1),
                 retry_delay: embassy_time::Duration::from_millis(5),
                 socket_close_delay: embassy_time::Duration::from_millis(2),
+                ..HttpClientOptions::default()
             },
         );
         assert_eq!(client_small_custom.options.max_retries, 2);
@@ -0,0 +1,537 @@
+use crate::{error::Error, header::HttpHeader};
+#[cfg(feature = "cbor")]
+use minicbor_019 as minicbor;
+#[cfg(feature = "msgpack")]
+pub use rmp_08 as rmp;
+
+/// A binary body format, identified by its media type
+///
+/// Codecs are passed by value to select the format, e.g.
+/// `client.post_with_codec(Cbor, url, &[], &reading, &mut scratch, &mut buffer)`.
+pub trait BodyCodec: Copy {
+    /// Media type used for `Content-Type` and `Accept`
+    const CONTENT_TYPE: &'static str;
+}
+
+/// A value that can be written as a body in format `C`
+pub trait EncodeBody<C: BodyCodec> {
+    /// Encode `self` into `out`, returning the number of bytes written
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BodyEncode`] if the value cannot be encoded or does not fit.
+    fn encode_body(&self, out: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// A value that can be read from a body in format `C`, possibly borrowing from it
+pub trait DecodeBody<'a, C: BodyCodec>: Sized {
+    /// Decode a value from `body`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BodyDecode`] if `body` is not a valid encoding of `Self`.
+    fn decode_body(body: &'a [u8]) -> Result<Self, Error>;
+}
+
+/// CBOR (RFC 8949) through `minicbor`
+///
+/// Every type implementing `minicbor::Encode<()>` / `minicbor::Decode<'_, ()>` can be used as
+/// a body, including types with `#[derive(Encode, Decode)]`.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl BodyCodec for Cbor {
+    const CONTENT_TYPE: &'static str = "application/cbor";
+}
+
+#[cfg(feature = "cbor")]
+impl<T: minicbor::Encode<()>> EncodeBody<Cbor> for T {
+    fn encode_body(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let mut cursor = minicbor::encode::write::Cursor::new(out);
+        minicbor::encode(self, &mut cursor).map_err(|e| {
+            if e.is_write() {
+                Error::BodyEncode("CBOR body does not fit into the scratch buffer")
+            } else {
+                Error::BodyEncode("CBOR encoding failed")
+            }
+        })?;
+        Ok(cursor.position())
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<'a, T: minicbor::Decode<'a, ()>> DecodeBody<'a, Cbor> for T {
+    fn decode_body(body: &'a [u8]) -> Result<Self, Error> {
+        minicbor::decode(body).map_err(|e| {
+            if e.is_end_of_input() {
+                Error::BodyDecode("CBOR body is truncated")
+            } else {
+                Error::BodyDecode("Invalid CBOR body")
+            }
+        })
+    }
+}
+
+/// `MessagePack` through `rmp`
+///
+/// Every type implementing [`MsgPackEncode`] / [`MsgPackDecode`] can be used as a body. These
+/// are implemented for booleans, integers, floats, strings, byte strings ([`Bin`]), `Option`,
+/// slices and `heapless::Vec` (as arrays) and tuples of up to four elements (as arrays).
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl BodyCodec for MsgPack {
+    const CONTENT_TYPE: &'static str = "application/msgpack";
+}
+
+/// A value that can be written as `MessagePack`
+///
+/// `rmp` has no data model of its own, so this trait provides one. Implement it for your own
+/// types by encoding their fields, for example as a tuple:
+///
+/// ```rust,ignore
+/// impl MsgPackEncode for Reading<'_> {
+///     fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+///         (self.sensor, self.value).encode_msgpack(out)
+///     }
+/// }
+/// ```
+#[cfg(feature = "msgpack")]
+pub trait MsgPackEncode {
+    /// Write `self` to the front of `out`, returning the number of bytes written
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BodyEncode`] if the value does not fit into `out`.
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// A value that can be read from `MessagePack`, possibly borrowing from the input
+///
+/// See [`MsgPackEncode`] for implementing it on your own types.
+#[cfg(feature = "msgpack")]
+pub trait MsgPackDecode<'a>: Sized {
+    /// Read a value from the front of `input` and advance `input` past it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BodyDecode`] if `input` does not start with a valid encoding of `Self`.
+    fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error>;
+}
+
+/// A `MessagePack` byte string (`bin`), as opposed to an array of integers
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bin<'a>(pub &'a [u8]);
+
+#[cfg(feature = "msgpack")]
+fn msgpack_encode_error<E>(_: E) -> Error {
+    Error::BodyEncode("MessagePack body does not fit into the scratch buffer")
+}
+
+#[cfg(feature = "msgpack")]
+fn msgpack_decode_error<E>(_: E) -> Error {
+    Error::BodyDecode("Invalid MessagePack body")
+}
+
+/// Call an `rmp::encode` function on `out`, evaluating to the number of bytes written
+///
+/// Returns the encode error from the enclosing function if the value does not fit.
+#[cfg(feature = "msgpack")]
+macro_rules! rmp_write {
+    ($out:expr, $write:ident($($arg:expr),*)) => {{
+        let capacity = $out.len();
+        let mut rest: &mut [u8] = &mut *$out;
+        rmp::encode::$write(&mut rest, $($arg),*).map_err(msgpack_encode_error)?;
+        capacity - rest.len()
+    }};
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: MsgPackEncode + ?Sized> EncodeBody<MsgPack> for T {
+    fn encode_body(&self, out: &mut [u8]) -> Result<usize, Error> {
+        self.encode_msgpack(out)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<'a, T: MsgPackDecode<'a>> DecodeBody<'a, MsgPack> for T {
+    fn decode_body(mut body: &'a [u8]) -> Result<Self, Error> {
+        T::decode_msgpack(&mut body)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: MsgPackEncode + ?Sized> MsgPackEncode for &T {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        (**self).encode_msgpack(out)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackEncode for bool {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        Ok(rmp_write!(out, write_bool(*self)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackDecode<'_> for bool {
+    fn decode_msgpack(input: &mut &[u8]) -> Result<Self, Error> {
+        rmp::decode::read_bool(input).map_err(msgpack_decode_error)
+    }
+}
+
+/// Integers are written in their most compact form and read from any integer form that fits
+#[cfg(feature = "msgpack")]
+macro_rules! msgpack_int {
+    ($write:ident, $wide:ty: $($int:ty),*) => {$(
+        impl MsgPackEncode for $int {
+            fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+                Ok(rmp_write!(out, $write(<$wide>::from(*self))))
+            }
+        }
+
+        impl MsgPackDecode<'_> for $int {
+            fn decode_msgpack(input: &mut &[u8]) -> Result<Self, Error> {
+                rmp::decode::read_int(input).map_err(msgpack_decode_error)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "msgpack")]
+msgpack_int!(write_uint, u64: u8, u16, u32, u64);
+#[cfg(feature = "msgpack")]
+msgpack_int!(write_sint, i64: i8, i16, i32, i64);
+
+#[cfg(feature = "msgpack")]
+impl MsgPackEncode for f32 {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        Ok(rmp_write!(out, write_f32(*self)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackDecode<'_> for f32 {
+    fn decode_msgpack(input: &mut &[u8]) -> Result<Self, Error> {
+        rmp::decode::read_f32(input).map_err(msgpack_decode_error)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackEncode for f64 {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        Ok(rmp_write!(out, write_f64(*self)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackDecode<'_> for f64 {
+    fn decode_msgpack(input: &mut &[u8]) -> Result<Self, Error> {
+        // Many encoders write floats that fit as `float 32`
+        if input.first() == Some(&rmp::Marker::F32.to_u8()) {
+            return f32::decode_msgpack(input).map(f64::from);
+        }
+        rmp::decode::read_f64(input).map_err(msgpack_decode_error)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackEncode for str {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        Ok(rmp_write!(out, write_str(self)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<'a> MsgPackDecode<'a> for &'a str {
+    fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error> {
+        let (value, rest) =
+            rmp::decode::read_str_from_slice(*input).map_err(msgpack_decode_error)?;
+        *input = rest;
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl MsgPackEncode for Bin<'_> {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        Ok(rmp_write!(out, write_bin(self.0)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<'a> MsgPackDecode<'a> for Bin<'a> {
+    fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error> {
+        let len = rmp::decode::read_bin_len(input).map_err(msgpack_decode_error)?;
+        let (value, rest) = input
+            .split_at_checked(len as usize)
+            .ok_or(msgpack_decode_error(()))?;
+        *input = rest;
+        Ok(Bin(value))
+    }
+}
+
+/// `None` is written as `nil`
+#[cfg(feature = "msgpack")]
+impl<T: MsgPackEncode> MsgPackEncode for Option<T> {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        match self {
+            Some(value) => value.encode_msgpack(out),
+            None => Ok(rmp_write!(out, write_nil())),
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<'a, T: MsgPackDecode<'a>> MsgPackDecode<'a> for Option<T> {
+    fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error> {
+        if input.first() == Some(&rmp::Marker::Null.to_u8()) {
+            *input = &input[1..];
+            return Ok(None);
+        }
+        T::decode_msgpack(input).map(Some)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: MsgPackEncode> MsgPackEncode for [T] {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let len = u32::try_from(self.len()).map_err(msgpack_encode_error)?;
+        let mut written = rmp_write!(out, write_array_len(len));
+        for value in self {
+            written += value.encode_msgpack(&mut out[written..])?;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: MsgPackEncode, const N: usize> MsgPackEncode for heapless::Vec<T, N> {
+    fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+        self.as_slice().encode_msgpack(out)
+    }
+}
+
+/// Arrays longer than `N` are rejected
+#[cfg(feature = "msgpack")]
+impl<'a, T: MsgPackDecode<'a>, const N: usize> MsgPackDecode<'a> for heapless::Vec<T, N> {
+    fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error> {
+        let len = rmp::decode::read_array_len(input).map_err(msgpack_decode_error)?;
+        let mut values = Self::new();
+        for _ in 0..len {
+            values
+                .push(T::decode_msgpack(input)?)
+                .map_err(|_| Error::BodyDecode("MessagePack array is too long"))?;
+        }
+        Ok(values)
+    }
+}
+
+/// Tuples are written as arrays, the usual `MessagePack` form of a record
+#[cfg(feature = "msgpack")]
+macro_rules! msgpack_tuple {
+    ($len:literal: $($name:ident),+) => {
+        impl<$($name: MsgPackEncode),+> MsgPackEncode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+                let ($($name,)+) = self;
+                let mut written = rmp_write!(out, write_array_len($len));
+                $(written += $name.encode_msgpack(&mut out[written..])?;)+
+                Ok(written)
+            }
+        }
+
+        impl<'a, $($name: MsgPackDecode<'a>),+> MsgPackDecode<'a> for ($($name,)+) {
+            fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error> {
+                if rmp::decode::read_array_len(input).map_err(msgpack_decode_error)? != $len {
+                    return Err(Error::BodyDecode("MessagePack array has the wrong length"));
+                }
+                Ok(($($name::decode_msgpack(input)?,)+))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "msgpack")]
+msgpack_tuple!(1: A);
+#[cfg(feature = "msgpack")]
+msgpack_tuple!(2: A, B);
+#[cfg(feature = "msgpack")]
+msgpack_tuple!(3: A, B, C);
+#[cfg(feature = "msgpack")]
+msgpack_tuple!(4: A, B, C, D);
+
+/// Check that a `Content-Type` header, if present, names `content_type`
+///
+/// Parameters such as `charset` are ignored. A missing header is accepted, as many small
+/// servers leave it out.
+pub(crate) fn has_content_type(headers: &[HttpHeader<'_>], content_type: &str) -> bool {
+    headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("Content-Type"))
+        .is_none_or(|h| media_type(h.value).eq_ignore_ascii_case(content_type))
+}
+
+/// Check whether an `Accept` header value allows `content_type`
+///
+/// Wildcards (`*/*`, `application/*`) match, and entries with `q=0` are treated as refused.
+#[must_use]
+pub fn accepts(accept: &str, content_type: &str) -> bool {
+    let (kind, _) = content_type.split_once('/').unwrap_or((content_type, ""));
+    accept.split(',').any(|entry| {
+        let range = media_type(entry);
+        let refused = entry.split(';').skip(1).any(|param| {
+            param
+                .split_once('=')
+                .is_some_and(|(k, v)| k.trim().eq_ignore_ascii_case("q") && is_zero(v.trim()))
+        });
+        let matches = range == "*/*"
+            || range.eq_ignore_ascii_case(content_type)
+            || range
+                .strip_suffix("/*")
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(kind));
+        matches && !refused
+    })
+}
+
+/// The media type of a header value, without parameters
+fn media_type(value: &str) -> &str {
+    value.split(';').next().unwrap_or("").trim()
+}
+
+/// Check whether a quality value is zero (`0`, `0.0`, `0.000`)
+fn is_zero(q: &str) -> bool {
+    let (int, frac) = q.split_once('.').unwrap_or((q, ""));
+    int == "0" && frac.bytes().all(|b| b == b'0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts() {
+        assert!(accepts("application/cbor", "application/cbor"));
+        assert!(accepts(
+            "application/json, application/cbor;q=0.9",
+            "application/cbor"
+        ));
+        assert!(accepts("application/*", "application/cbor"));
+        assert!(accepts("*/*", "application/msgpack"));
+        assert!(!accepts("application/json", "application/cbor"));
+        assert!(!accepts("application/cbor;q=0", "application/cbor"));
+        assert!(!accepts("text/*", "application/cbor"));
+    }
+
+    #[test]
+    fn test_has_content_type() {
+        let cbor = [HttpHeader::new("content-type", "Application/CBOR; foo=bar")];
+        assert!(has_content_type(&cbor, "application/cbor"));
+        assert!(!has_content_type(&cbor, "application/msgpack"));
+        assert!(has_content_type(&[], "application/cbor"));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_round_trip() {
+        #[derive(minicbor::Encode, minicbor::Decode, Debug, PartialEq)]
+        struct Reading<'a> {
+            #[b(0)]
+            sensor: &'a str,
+            #[n(1)]
+            value: i32,
+        }
+
+        let reading = Reading {
+            sensor: "t1",
+            value: -40,
+        };
+        let mut buffer = [0u8; 32];
+        let len = EncodeBody::<Cbor>::encode_body(&reading, &mut buffer).unwrap();
+        let decoded: Reading<'_> = DecodeBody::<Cbor>::decode_body(&buffer[..len]).unwrap();
+        assert_eq!(decoded, reading);
+
+        assert!(matches!(
+            EncodeBody::<Cbor>::encode_body(&reading, &mut buffer[..4]),
+            Err(Error::BodyEncode(_))
+        ));
+        assert!(matches!(
+            <Reading<'_> as DecodeBody<Cbor>>::decode_body(&buffer[..len - 1]),
+            Err(Error::BodyDecode(_))
+        ));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_round_trip() {
+        #[derive(Debug, PartialEq)]
+        struct Reading<'a> {
+            sensor: &'a str,
+            value: u32,
+            offset: Option<i16>,
+        }
+
+        impl MsgPackEncode for Reading<'_> {
+            fn encode_msgpack(&self, out: &mut [u8]) -> Result<usize, Error> {
+                (self.sensor, self.value, self.offset).encode_msgpack(out)
+            }
+        }
+
+        impl<'a> MsgPackDecode<'a> for Reading<'a> {
+            fn decode_msgpack(input: &mut &'a [u8]) -> Result<Self, Error> {
+                let (sensor, value, offset) = MsgPackDecode::decode_msgpack(input)?;
+                Ok(Self {
+                    sensor,
+                    value,
+                    offset,
+                })
+            }
+        }
+
+        let reading = Reading {
+            sensor: "t1",
+            value: 70_000,
+            offset: Some(-3),
+        };
+        let mut buffer = [0u8; 24];
+        let len = EncodeBody::<MsgPack>::encode_body(&reading, &mut buffer).unwrap();
+        // fixarray(3), fixstr "t1", uint 32, negative fixint
+        assert_eq!(&buffer[..len], b"\x93\xa2t1\xce\x00\x01\x11\x70\xfd");
+        let decoded: Reading<'_> = DecodeBody::<MsgPack>::decode_body(&buffer[..len]).unwrap();
+        assert_eq!(decoded, reading);
+
+        assert!(matches!(
+            EncodeBody::<MsgPack>::encode_body(&reading, &mut buffer[..4]),
+            Err(Error::BodyEncode(_))
+        ));
+        assert!(matches!(
+            <Reading<'_> as DecodeBody<MsgPack>>::decode_body(&buffer[..len - 1]),
+            Err(Error::BodyDecode(_))
+        ));
+
+        // Other common types
+        let values: heapless::Vec<f32, 4> = heapless::Vec::from_slice(&[1.5, -2.0]).unwrap();
+        let len =
+            EncodeBody::<MsgPack>::encode_body(&(true, Bin(b"\x00\xff"), &values), &mut buffer)
+                .unwrap();
+        let (flag, bin, decoded): (bool, Bin<'_>, heapless::Vec<f64, 2>) =
+            DecodeBody::<MsgPack>::decode_body(&buffer[..len]).unwrap();
+        assert!(flag);
+        assert_eq!(bin, Bin(b"\x00\xff"));
+        assert_eq!(decoded.as_slice(), &[1.5, -2.0]);
+        assert!(matches!(
+            <heapless::Vec<f32, 1> as DecodeBody<MsgPack>>::decode_body(&buffer[6..len]),
+            Err(Error::BodyDecode(_))
+        ));
+        let none: Option<u8> = DecodeBody::<MsgPack>::decode_body(b"\xc0").unwrap();
+        assert_eq!(none, None);
+        let big: Result<u8, _> = DecodeBody::<MsgPack>::decode_body(b"\xcd\x01\x00");
+        assert!(matches!(big, Err(Error::BodyDecode(_))));
+    }
+}
@@ -0,0 +1,571 @@
+use crate::error::Error;
+use miniz_oxide::inflate::{
+    TINFLStatus,
+    core::{
+        DecompressorOxide, decompress,
+        inflate_flags::{TINFL_FLAG_PARSE_ZLIB_HEADER, TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF},
+    },
+};
+use miniz_oxide_08 as miniz_oxide;
+
+/// `Accept-Encoding` value sent by the client when the `compression` feature is enabled
+pub const SUPPORTED_ENCODINGS: &str = "gzip, deflate";
+
+/// A `Content-Encoding` the client can decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ContentEncoding {
+    /// No encoding (`identity`)
+    Identity,
+    /// `gzip` (RFC 1952)
+    Gzip,
+    /// `deflate`, i.e. a zlib stream (RFC 1950); raw deflate data is accepted as well
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Parse a `Content-Encoding` header value
+    ///
+    /// Returns `None` for encodings that are not supported, such as `br` or stacked encodings.
+    #[must_use]
+    pub fn from_header(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("identity") {
+            Some(ContentEncoding::Identity)
+        } else if value.eq_ignore_ascii_case("gzip") || value.eq_ignore_ascii_case("x-gzip") {
+            Some(ContentEncoding::Gzip)
+        } else if value.eq_ignore_ascii_case("deflate") {
+            Some(ContentEncoding::Deflate)
+        } else {
+            None
+        }
+    }
+
+    /// The header value for this encoding
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3) as used by the gzip trailer
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) const fn new() -> Self {
+        Self(!0)
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= u32::from(byte);
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    pub(crate) const fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Length of the fixed part of a gzip header
+const GZIP_HEADER_LEN: usize = 10;
+/// Header flag: a CRC-16 of the header follows
+const FHCRC: u8 = 0x02;
+/// Header flag: an extra field follows
+const FEXTRA: u8 = 0x04;
+/// Header flag: a zero-terminated file name follows
+const FNAME: u8 = 0x08;
+/// Header flag: a zero-terminated comment follows
+const FCOMMENT: u8 = 0x10;
+
+/// Return the deflate payload and trailer of a gzip member, skipping the header
+fn strip_gzip_header(data: &[u8]) -> Option<&[u8]> {
+    if data.len() < GZIP_HEADER_LEN || data[..3] != [0x1f, 0x8b, 0x08] {
+        return None;
+    }
+    let flags = data[3];
+    let mut rest = &data[GZIP_HEADER_LEN..];
+
+    if flags & FEXTRA != 0 {
+        let len = usize::from(u16::from_le_bytes([*rest.first()?, *rest.get(1)?]));
+        rest = rest.get(2 + len..)?;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = rest.iter().position(|&b| b == 0)?;
+            rest = &rest[end + 1..];
+        }
+    }
+    if flags & FHCRC != 0 {
+        rest = rest.get(2..)?;
+    }
+    Some(rest)
+}
+
+/// Inflate a deflate or zlib stream into `out`, returning the decoded length and the
+/// number of input bytes consumed
+fn inflate(data: &[u8], out: &mut [u8], zlib: bool) -> Result<(usize, usize), Error> {
+    let mut decompressor = DecompressorOxide::new();
+    let mut flags = TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    if zlib {
+        flags |= TINFL_FLAG_PARSE_ZLIB_HEADER;
+    }
+
+    let (status, consumed, written) = decompress(&mut decompressor, data, out, 0, flags);
+    match status {
+        TINFLStatus::Done => Ok((written, consumed)),
+        TINFLStatus::HasMoreOutput => Err(Error::DecodedBodyTooLarge),
+        _ => Err(Error::InvalidCompressedBody),
+    }
+}
+
+/// Decode a body with the given `Content-Encoding` into `out`, returning the decoded length
+///
+/// The whole decoded body has to fit into `out`, which also serves as the decompression
+/// window. Apart from `out`, decoding needs about 10.5 KiB of stack for the decompressor.
+/// The gzip trailer (CRC-32 and length) and the zlib Adler-32 checksum are verified.
+///
+/// # Errors
+///
+/// Returns [`Error::DecodedBodyTooLarge`] if `out` is too small and
+/// [`Error::InvalidCompressedBody`] if the data is corrupt or truncated.
+pub fn decode(encoding: ContentEncoding, data: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    match encoding {
+        ContentEncoding::Identity => {
+            let out = out
+                .get_mut(..data.len())
+                .ok_or(Error::DecodedBodyTooLarge)?;
+            out.copy_from_slice(data);
+            Ok(data.len())
+        }
+        ContentEncoding::Gzip => {
+            let payload = strip_gzip_header(data).ok_or(Error::InvalidCompressedBody)?;
+            let (len, consumed) = inflate(payload, out, false)?;
+
+            let trailer = payload
+                .get(consumed..consumed + 8)
+                .ok_or(Error::InvalidCompressedBody)?;
+            let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+            let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+            let mut actual = Crc32::new();
+            actual.update(&out[..len]);
+            // ISIZE is the decoded length modulo 2^32
+            #[allow(clippy::cast_possible_truncation)]
+            if actual.finish() != crc || size != len as u32 {
+                return Err(Error::InvalidCompressedBody);
+            }
+            Ok(len)
+        }
+        ContentEncoding::Deflate => {
+            // Some servers send raw deflate data despite the name; a valid zlib header has
+            // compression method 8 and a header checksum that is a multiple of 31
+            let zlib = data.len() >= 2
+                && data[0] & 0x0f == 8
+                && (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 == 0;
+            inflate(data, out, zlib).map(|(len, _)| len)
+        }
+    }
+}
+
+/// Compute the Adler-32 checksum that ends a zlib stream
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+/// Writes a deflate bit stream (least significant bit first) into a fixed buffer
+struct BitWriter<'o> {
+    out: &'o mut [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'o> BitWriter<'o> {
+    fn new(out: &'o mut [u8], pos: usize) -> Self {
+        Self {
+            out,
+            pos,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) -> Result<(), Error> {
+        *self
+            .out
+            .get_mut(self.pos)
+            .ok_or(Error::EncodedBodyTooLarge)? = byte;
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Write the low `count` bits of `value` (at most 16)
+    fn write(&mut self, value: u32, count: u32) -> Result<(), Error> {
+        self.bits |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.push_byte(self.bits.to_le_bytes()[0])?;
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+        Ok(())
+    }
+
+    /// Write a Huffman code, which deflate stores most significant bit first
+    fn write_code(&mut self, code: u32, len: u32) -> Result<(), Error> {
+        self.write(code.reverse_bits() >> (32 - len), len)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        bytes.iter().try_for_each(|&b| self.push_byte(b))
+    }
+
+    /// Pad to a byte boundary and return the number of bytes written
+    fn finish(mut self) -> Result<usize, Error> {
+        if self.count > 0 {
+            self.write(0, 8 - self.count)?;
+        }
+        Ok(self.pos)
+    }
+}
+
+/// Base match lengths of the length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+/// Extra bits of the length symbols 257..=285
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances of the distance symbols 0..=29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// Extra bits of the distance symbols 0..=29
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Shortest and longest match deflate can encode
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Largest distance deflate can refer back
+const WINDOW_SIZE: usize = 32 * 1024;
+/// Number of hash chains heads kept by the encoder (4 KiB of stack)
+const HASH_BITS: u32 = 10;
+
+/// Write a literal or length symbol with the fixed Huffman code of RFC 1951 §3.2.6
+fn write_literal(w: &mut BitWriter<'_>, symbol: u32) -> Result<(), Error> {
+    match symbol {
+        0..=143 => w.write_code(0x30 + symbol, 8),
+        144..=255 => w.write_code(0x190 + symbol - 144, 9),
+        256..=279 => w.write_code(symbol - 256, 7),
+        _ => w.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+/// Write a back reference of `len` bytes at `dist` bytes distance
+#[allow(clippy::cast_possible_truncation)]
+fn write_match(w: &mut BitWriter<'_>, len: usize, dist: usize) -> Result<(), Error> {
+    // Both searches succeed: len is in 3..=258 and dist in 1..=32768
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|&b| usize::from(b) <= len)
+        .unwrap_or(0);
+    write_literal(w, 257 + index as u32)?;
+    w.write(
+        (len - usize::from(LENGTH_BASE[index])) as u32,
+        u32::from(LENGTH_EXTRA[index]),
+    )?;
+
+    let index = DIST_BASE
+        .iter()
+        .rposition(|&b| usize::from(b) <= dist)
+        .unwrap_or(0);
+    w.write_code(index as u32, 5)?;
+    w.write(
+        (dist - usize::from(DIST_BASE[index])) as u32,
+        u32::from(DIST_EXTRA[index]),
+    )
+}
+
+/// Hash the three bytes at the start of `data` into a `HASH_BITS` index
+fn hash(data: &[u8]) -> usize {
+    let key = u32::from_le_bytes([data[0], data[1], data[2], 0]);
+    (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compress `data` as a single fixed-Huffman deflate block
+///
+/// Matches are found greedily through a hash of the next three bytes. This trades some
+/// compression ratio for a small, allocation-free encoder, which is still very effective on
+/// repetitive telemetry such as CSV or JSON.
+#[allow(clippy::cast_possible_truncation)]
+fn deflate(data: &[u8], w: &mut BitWriter<'_>) -> Result<(), Error> {
+    // Position + 1 of the last occurrence of each hash, 0 if none
+    let mut heads = [0u32; 1 << HASH_BITS];
+
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes)
+    w.write(0b011, 3)?;
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = 0;
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(&data[i..]);
+            let candidate = heads[h] as usize;
+            heads[h] = i as u32 + 1;
+            if candidate > 0 && i - (candidate - 1) <= WINDOW_SIZE {
+                let start = candidate - 1;
+                let max = (data.len() - i).min(MAX_MATCH);
+                best = data[start..]
+                    .iter()
+                    .zip(&data[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if best >= MIN_MATCH {
+                    write_match(w, best, i - start)?;
+                }
+            }
+        }
+
+        if best >= MIN_MATCH {
+            // Remember the positions inside the match for later references
+            for j in i + 1..(i + best).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+                heads[hash(&data[j..])] = j as u32 + 1;
+            }
+            i += best;
+        } else {
+            write_literal(w, u32::from(data[i]))?;
+            i += 1;
+        }
+    }
+
+    // End of block
+    write_literal(w, 256)
+}
+
+/// Encode `data` with the given `Content-Encoding` into `out`, returning the encoded length
+///
+/// `Deflate` produces a zlib stream, as RFC 9110 requires for `Content-Encoding: deflate`.
+/// Besides `out`, encoding needs about 4 KiB of stack for the match finder.
+///
+/// # Errors
+///
+/// Returns [`Error::EncodedBodyTooLarge`] if the encoded data does not fit into `out`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn encode(encoding: ContentEncoding, data: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    match encoding {
+        ContentEncoding::Identity => {
+            let out = out
+                .get_mut(..data.len())
+                .ok_or(Error::EncodedBodyTooLarge)?;
+            out.copy_from_slice(data);
+            Ok(data.len())
+        }
+        ContentEncoding::Gzip => {
+            let mut w = BitWriter::new(out, 0);
+            // No flags, no modification time, unknown operating system
+            w.write_bytes(&[0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff])?;
+            deflate(data, &mut w)?;
+            let len = w.finish()?;
+
+            let mut crc = Crc32::new();
+            crc.update(data);
+            let mut w = BitWriter::new(out, len);
+            w.write_bytes(&crc.finish().to_le_bytes())?;
+            w.write_bytes(&(data.len() as u32).to_le_bytes())?;
+            w.finish()
+        }
+        ContentEncoding::Deflate => {
+            let mut w = BitWriter::new(out, 0);
+            // 32 KiB window, deflate, fastest compression level
+            w.write_bytes(&[0x78, 0x01])?;
+            deflate(data, &mut w)?;
+            let len = w.finish()?;
+
+            let mut w = BitWriter::new(out, len);
+            w.write_bytes(&adler32(data).to_be_bytes())?;
+            w.finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: &[u8] =
+        b"{\"temperature\":21.5,\"humidity\":40,\"temperature\":21.5,\"humidity\":40}";
+
+    const GZIP: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56, 0x2a, 0x49, 0xcd,
+        0x2d, 0x48, 0x2d, 0x4a, 0x2c, 0x29, 0x2d, 0x4a, 0x55, 0xb2, 0x32, 0x32, 0xd4, 0x33, 0xd5,
+        0x51, 0xca, 0x28, 0xcd, 0xcd, 0x4c, 0xc9, 0x2c, 0xa9, 0x54, 0xb2, 0x32, 0x31, 0xd0, 0x21,
+        0xa4, 0xa0, 0x16, 0x00, 0x69, 0xd0, 0x7b, 0x64, 0x43, 0x00, 0x00, 0x00,
+    ];
+
+    const ZLIB: &[u8] = &[
+        0x78, 0x9c, 0xab, 0x56, 0x2a, 0x49, 0xcd, 0x2d, 0x48, 0x2d, 0x4a, 0x2c, 0x29, 0x2d, 0x4a,
+        0x55, 0xb2, 0x32, 0x32, 0xd4, 0x33, 0xd5, 0x51, 0xca, 0x28, 0xcd, 0xcd, 0x4c, 0xc9, 0x2c,
+        0xa9, 0x54, 0xb2, 0x32, 0x31, 0xd0, 0x21, 0xa4, 0xa0, 0x16, 0x00, 0xfc, 0xc4, 0x15, 0xff,
+    ];
+
+    #[test]
+    fn test_crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_content_encoding_from_header() {
+        assert_eq!(
+            ContentEncoding::from_header("GZIP"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::from_header(" deflate "),
+            Some(ContentEncoding::Deflate)
+        );
+        assert_eq!(
+            ContentEncoding::from_header("identity"),
+            Some(ContentEncoding::Identity)
+        );
+        assert_eq!(ContentEncoding::from_header("br"), None);
+        assert_eq!(ContentEncoding::from_header("gzip, br"), None);
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        let mut out = [0u8; 128];
+        let len = decode(ContentEncoding::Gzip, GZIP, &mut out).unwrap();
+        assert_eq!(&out[..len], PLAIN);
+    }
+
+    #[test]
+    fn test_decode_gzip_with_file_name() {
+        let mut data = std::vec::Vec::from(&GZIP[..10]);
+        data[3] = FNAME;
+        data.extend_from_slice(b"reading.json\0");
+        data.extend_from_slice(&GZIP[10..]);
+
+        let mut out = [0u8; 128];
+        let len = decode(ContentEncoding::Gzip, &data, &mut out).unwrap();
+        assert_eq!(&out[..len], PLAIN);
+    }
+
+    #[test]
+    fn test_decode_deflate_zlib_and_raw() {
+        let mut out = [0u8; 128];
+        let len = decode(ContentEncoding::Deflate, ZLIB, &mut out).unwrap();
+        assert_eq!(&out[..len], PLAIN);
+
+        // Raw deflate: the zlib stream without its 2-byte header and Adler-32 trailer
+        let raw = &ZLIB[2..ZLIB.len() - 4];
+        let len = decode(ContentEncoding::Deflate, raw, &mut out).unwrap();
+        assert_eq!(&out[..len], PLAIN);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        use core::fmt::Write as _;
+
+        let mut csv = std::string::String::new();
+        for i in 0..200 {
+            writeln!(csv, "{i},sensor-{},21.{}", i % 4, i % 10).unwrap();
+        }
+        let mut encoded = [0u8; 4096];
+        let mut decoded = [0u8; 8192];
+
+        for encoding in [ContentEncoding::Gzip, ContentEncoding::Deflate] {
+            let len = encode(encoding, csv.as_bytes(), &mut encoded).unwrap();
+            assert!(len < csv.len() / 2);
+            let decoded_len = decode(encoding, &encoded[..len], &mut decoded).unwrap();
+            assert_eq!(&decoded[..decoded_len], csv.as_bytes());
+        }
+
+        let len = encode(ContentEncoding::Gzip, b"", &mut encoded).unwrap();
+        assert_eq!(
+            decode(ContentEncoding::Gzip, &encoded[..len], &mut decoded).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_encode_beyond_window() {
+        // Matches must not reach further back than 32 KiB
+        let data: std::vec::Vec<u8> = (0..70_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .chain(b"repeat repeat repeat".iter().copied())
+            .collect();
+        let mut encoded = std::vec![0u8; 80_000];
+        let mut decoded = std::vec![0u8; 80_000];
+
+        let len = encode(ContentEncoding::Deflate, &data, &mut encoded).unwrap();
+        let decoded_len = decode(ContentEncoding::Deflate, &encoded[..len], &mut decoded).unwrap();
+        assert_eq!(&decoded[..decoded_len], &data[..]);
+    }
+
+    #[test]
+    fn test_encode_too_large() {
+        let mut out = [0u8; 8];
+        assert!(matches!(
+            encode(ContentEncoding::Gzip, PLAIN, &mut out),
+            Err(Error::EncodedBodyTooLarge)
+        ));
+        assert!(matches!(
+            encode(ContentEncoding::Identity, PLAIN, &mut out),
+            Err(Error::EncodedBodyTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut small = [0u8; 16];
+        assert!(matches!(
+            decode(ContentEncoding::Gzip, GZIP, &mut small),
+            Err(Error::DecodedBodyTooLarge)
+        ));
+
+        let mut out = [0u8; 128];
+        let mut corrupt = std::vec::Vec::from(GZIP);
+        let crc_pos = corrupt.len() - 8;
+        corrupt[crc_pos] ^= 0xff;
+        assert!(matches!(
+            decode(ContentEncoding::Gzip, &corrupt, &mut out),
+            Err(Error::InvalidCompressedBody)
+        ));
+        assert!(matches!(
+            decode(ContentEncoding::Gzip, &GZIP[..30], &mut out),
+            Err(Error::InvalidCompressedBody)
+        ));
+        assert!(matches!(
+            decode(ContentEncoding::Gzip, PLAIN, &mut out),
+            Err(Error::InvalidCompressedBody)
+        ));
+    }
+}
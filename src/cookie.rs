@@ -0,0 +1,740 @@
+use crate::header::HttpHeader;
+use core::cell::{Cell, RefCell};
+use embassy_time::{Duration, Instant};
+use embassy_time_05 as embassy_time;
+use heapless::String;
+
+/// Maximum length of a cookie name
+pub const MAX_NAME_LEN: usize = 64;
+/// Maximum length of a cookie value
+pub const MAX_VALUE_LEN: usize = 256;
+/// Maximum length of a cookie domain
+pub const MAX_DOMAIN_LEN: usize = 64;
+/// Maximum length of a cookie path
+pub const MAX_PATH_LEN: usize = 64;
+
+/// A cookie received through `Set-Cookie`
+///
+/// Names, values, domains and paths are copied into fixed-capacity strings; cookies that
+/// exceed [`MAX_NAME_LEN`], [`MAX_VALUE_LEN`], [`MAX_DOMAIN_LEN`] or [`MAX_PATH_LEN`] are
+/// ignored rather than truncated.
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    name: String<MAX_NAME_LEN>,
+    value: String<MAX_VALUE_LEN>,
+    domain: String<MAX_DOMAIN_LEN>,
+    path: String<MAX_PATH_LEN>,
+    /// `None` for session cookies, which live until they are evicted
+    expires: Option<Instant>,
+    /// Set when no `Domain` attribute was given, so only the exact origin host matches
+    host_only: bool,
+    secure: bool,
+    http_only: bool,
+    /// Value of the jar's use counter when the cookie was last stored or sent
+    last_used: u32,
+}
+
+impl Cookie {
+    /// The cookie name
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The cookie value
+    #[must_use]
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The domain the cookie is sent to (lowercase, without a leading dot)
+    #[must_use]
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The path prefix the cookie is sent for
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The instant the cookie expires, or `None` for a session cookie
+    #[must_use]
+    pub fn expires(&self) -> Option<Instant> {
+        self.expires
+    }
+
+    /// Whether the cookie is only sent over HTTPS
+    #[must_use]
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Whether the cookie carried the `HttpOnly` attribute
+    #[must_use]
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn is_same(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    fn matches(&self, host: &str, path: &str, https: bool) -> bool {
+        let domain_ok = if self.host_only {
+            host.eq_ignore_ascii_case(&self.domain)
+        } else {
+            domain_match(host, &self.domain)
+        };
+        domain_ok && path_match(path, &self.path) && (https || !self.secure)
+    }
+}
+
+/// Cookie store backed by caller-provided, fixed-capacity storage
+///
+/// Attach a jar to an [`crate::HttpClient`] with [`crate::HttpClient::with_cookie_jar`]. Cookies
+/// from `Set-Cookie` response headers are stored automatically and matching cookies are sent
+/// in a `Cookie` header with every request.
+///
+/// # Eviction
+///
+/// When a new cookie arrives and every slot is taken:
+/// 1. a cookie with the same name, domain and path is replaced in place;
+/// 2. otherwise an expired cookie is dropped;
+/// 3. otherwise the least recently used cookie (stored or sent longest ago) is dropped.
+///
+/// # Expiry
+///
+/// `Max-Age` is relative and always honoured. `Expires` is an absolute date, so it is
+/// interpreted relative to the response's `Date` header; without one the cookie is kept as a
+/// session cookie.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use nanofish::{Cookie, CookieJar, DefaultHttpClient};
+///
+/// let mut storage: [Option<Cookie>; 4] = [const { None }; 4];
+/// let jar = CookieJar::new(&mut storage);
+/// let client = DefaultHttpClient::new(&stack).with_cookie_jar(&jar);
+/// ```
+pub struct CookieJar<'s> {
+    slots: RefCell<&'s mut [Option<Cookie>]>,
+    clock: Cell<u32>,
+}
+
+impl<'s> CookieJar<'s> {
+    /// Create an empty jar that stores up to `storage.len()` cookies
+    #[must_use]
+    pub fn new(storage: &'s mut [Option<Cookie>]) -> Self {
+        storage.fill(None);
+        Self {
+            slots: RefCell::new(storage),
+            clock: Cell::new(0),
+        }
+    }
+
+    /// Maximum number of cookies the jar can hold
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.slots.borrow().len()
+    }
+
+    /// Number of stored cookies, including ones that have expired but not been evicted yet
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.slots.borrow().iter().flatten().count()
+    }
+
+    /// Check whether the jar holds no cookies
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all cookies
+    pub fn clear(&self) {
+        self.slots.borrow_mut().fill(None);
+    }
+
+    /// Get a copy of the first stored cookie with the given name
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Cookie> {
+        self.slots
+            .borrow()
+            .iter()
+            .flatten()
+            .find(|c| c.name == name)
+            .cloned()
+    }
+
+    fn tick(&self) -> u32 {
+        let now = self.clock.get().wrapping_add(1);
+        self.clock.set(now);
+        now
+    }
+
+    /// Store every `Set-Cookie` header of a response from `host` for a request to `path`
+    pub(crate) fn store_from_headers(
+        &self,
+        host: &str,
+        path: &str,
+        headers: &[HttpHeader<'_>],
+        now: Instant,
+    ) {
+        let date = headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("Date"))
+            .and_then(|h| parse_http_date(h.value));
+
+        for header in headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case("Set-Cookie"))
+        {
+            if let Some(cookie) = parse_set_cookie(header.value, host, path, date, now) {
+                self.store(cookie, now);
+            }
+        }
+    }
+
+    fn store(&self, mut cookie: Cookie, now: Instant) {
+        cookie.last_used = self.tick();
+        let mut slots = self.slots.borrow_mut();
+
+        if let Some(slot) = slots
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_some_and(|c| c.is_same(&cookie)))
+        {
+            // An already expired cookie is how servers delete cookies
+            *slot = (!cookie.is_expired(now)).then_some(cookie);
+            return;
+        }
+        if cookie.is_expired(now) {
+            return;
+        }
+
+        let index = slots
+            .iter()
+            .position(Option::is_none)
+            .or_else(|| {
+                slots
+                    .iter()
+                    .position(|slot| slot.as_ref().is_some_and(|c| c.is_expired(now)))
+            })
+            .or_else(|| {
+                let clock = self.clock.get();
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, slot)| Some((i, slot.as_ref()?)))
+                    .max_by_key(|(_, c)| clock.wrapping_sub(c.last_used))
+                    .map(|(i, _)| i)
+            });
+        if let Some(index) = index {
+            slots[index] = Some(cookie);
+        }
+    }
+
+    /// Write a `Cookie` header line with all cookies matching the request, if there are any
+    pub(crate) fn write_cookie_header(
+        &self,
+        out: &mut impl core::fmt::Write,
+        host: &str,
+        path: &str,
+        https: bool,
+        now: Instant,
+    ) -> core::fmt::Result {
+        let path = request_path(path);
+        let tick = self.tick();
+        let mut slots = self.slots.borrow_mut();
+        let mut first = true;
+
+        for cookie in slots.iter_mut().flatten() {
+            if cookie.is_expired(now) || !cookie.matches(host, path, https) {
+                continue;
+            }
+            out.write_str(if first { "Cookie: " } else { "; " })?;
+            out.write_str(&cookie.name)?;
+            out.write_char('=')?;
+            out.write_str(&cookie.value)?;
+            cookie.last_used = tick;
+            first = false;
+        }
+
+        if !first {
+            out.write_str("\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Strip the query from a request target and fall back to `/` for an empty path
+fn request_path(path: &str) -> &str {
+    match path.split('?').next() {
+        Some(p) if p.starts_with('/') => p,
+        _ => "/",
+    }
+}
+
+/// Default cookie path for a request path (RFC 6265, section 5.1.4)
+fn default_path(path: &str) -> &str {
+    let path = request_path(path);
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+/// Domain matching (RFC 6265, section 5.1.3)
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host.eq_ignore_ascii_case(domain) {
+        return true;
+    }
+    let Some(prefix_len) = host.len().checked_sub(domain.len()) else {
+        return false;
+    };
+    prefix_len > 0
+        && host.as_bytes()[prefix_len - 1] == b'.'
+        && host[prefix_len..].eq_ignore_ascii_case(domain)
+        && host.parse::<core::net::IpAddr>().is_err()
+}
+
+/// Path matching (RFC 6265, section 5.1.4)
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path
+        .strip_prefix(cookie_path)
+        .is_some_and(|rest| rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'))
+}
+
+/// Parse a `Set-Cookie` header value received from `host` for a request to `path`
+///
+/// `date` is the response's `Date` header as Unix seconds, used to interpret `Expires`.
+fn parse_set_cookie(
+    header: &str,
+    host: &str,
+    path: &str,
+    date: Option<u64>,
+    now: Instant,
+) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut cookie = Cookie {
+        name: String::try_from(name).ok()?,
+        value: String::try_from(value.trim()).ok()?,
+        domain: String::new(),
+        path: String::new(),
+        expires: None,
+        host_only: true,
+        secure: false,
+        http_only: false,
+        last_used: 0,
+    };
+    let mut max_age = None;
+    let mut expires = None;
+    let mut domain = None;
+
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let (key, value) = (key.trim(), value.trim());
+        if key.eq_ignore_ascii_case("Max-Age") {
+            max_age = value.parse::<i64>().ok();
+        } else if key.eq_ignore_ascii_case("Expires") {
+            expires = parse_http_date(value);
+        } else if key.eq_ignore_ascii_case("Domain") {
+            let value = value.strip_prefix('.').unwrap_or(value);
+            domain = (!value.is_empty()).then_some(value);
+        } else if key.eq_ignore_ascii_case("Path") {
+            if value.starts_with('/') {
+                cookie.path = String::try_from(value).ok()?;
+            }
+        } else if key.eq_ignore_ascii_case("Secure") {
+            cookie.secure = true;
+        } else if key.eq_ignore_ascii_case("HttpOnly") {
+            cookie.http_only = true;
+        }
+    }
+
+    match domain {
+        // A single label such as `com` is a public suffix (RFC 6265, section 5.3): only the
+        // host itself may name it, and the cookie then stays host-only
+        Some(domain) if !domain.contains('.') && !domain.eq_ignore_ascii_case(host) => {
+            return None;
+        }
+        Some(domain) if domain.contains('.') => {
+            // Servers may only set cookies for their own domain or a parent of it
+            if !domain_match(host, domain) {
+                return None;
+            }
+            cookie.host_only = false;
+            for c in domain.chars() {
+                cookie.domain.push(c.to_ascii_lowercase()).ok()?;
+            }
+        }
+        _ => {
+            for c in host.chars() {
+                cookie.domain.push(c.to_ascii_lowercase()).ok()?;
+            }
+        }
+    }
+
+    if cookie.path.is_empty() {
+        cookie.path = String::try_from(default_path(path)).ok()?;
+    }
+
+    cookie.expires = match (max_age, expires, date) {
+        (Some(secs), _, _) => Some(match u64::try_from(secs) {
+            Ok(secs) if secs > 0 => now
+                .checked_add(Duration::from_secs(secs))
+                .unwrap_or(Instant::MAX),
+            _ => now,
+        }),
+        (None, Some(expires), Some(date)) => Some(match expires.checked_sub(date) {
+            Some(secs) if secs > 0 => now
+                .checked_add(Duration::from_secs(secs))
+                .unwrap_or(Instant::MAX),
+            _ => now,
+        }),
+        _ => None,
+    };
+
+    Some(cookie)
+}
+
+/// Parse a cookie date into Unix seconds (RFC 6265, section 5.1.1)
+///
+/// Accepts the IMF-fixdate, RFC 850 and asctime formats as well as the usual variations.
+pub(crate) fn parse_http_date(date: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    let tokens = date
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != ':')
+        .filter(|t| !t.is_empty());
+    for token in tokens {
+        if time.is_none() && token.contains(':') {
+            let mut fields = token.split(':').map(str::parse::<u64>);
+            if let (Some(Ok(h)), Some(Ok(m)), Some(Ok(s)), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            {
+                time = Some((h, m, s));
+            }
+        } else if day.is_none() && (1..=2).contains(&token.len()) {
+            day = token.parse::<u64>().ok();
+        } else if month.is_none()
+            && let Some(m) = MONTHS
+                .iter()
+                .position(|m| token.len() >= 3 && token[..3].eq_ignore_ascii_case(m))
+        {
+            month = Some(m as u64 + 1);
+        } else if year.is_none() && (2..=4).contains(&token.len()) {
+            year = token.parse::<u64>().ok().map(|y| match y {
+                0..=69 => y + 2000,
+                70..=99 => y + 1900,
+                _ => y,
+            });
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, year) = (day?, month?, year?);
+    if !(1..=31).contains(&day) || year < 1970 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Days since the Unix epoch for a proleptic Gregorian date
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let year_of_era = y % 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    fn header<'a>(name: &'a str, value: &'a str) -> HttpHeader<'a> {
+        HttpHeader::new(name, value)
+    }
+
+    fn cookie_header(
+        jar: &CookieJar<'_>,
+        host: &str,
+        path: &str,
+        https: bool,
+    ) -> std::string::String {
+        let mut out = std::string::String::new();
+        jar.write_cookie_header(&mut out, host, path, https, at(100))
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(
+            parse_http_date("Wednesday, 21-Oct-15 07:28:00 GMT"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(
+            parse_http_date("Wed Oct 21 07:28:00 2015"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(
+            parse_http_date("Sat, 29 Feb 2020 12:00:00 GMT"),
+            Some(1_582_977_600)
+        );
+        assert_eq!(parse_http_date("not a date"), None);
+        assert_eq!(parse_http_date("Wed, 21 Oct 2015 25:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn test_store_and_send() {
+        let mut storage: [Option<Cookie>; 4] = [const { None }; 4];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers(
+            "portal.example.com",
+            "/login",
+            &[
+                header("Set-Cookie", "session=abc123; Path=/; HttpOnly"),
+                header("Set-Cookie", "theme=dark"),
+                header("Content-Type", "text/html"),
+            ],
+            at(0),
+        );
+
+        assert_eq!(jar.len(), 2);
+        let session = jar.get("session").unwrap();
+        assert_eq!(session.value(), "abc123");
+        assert_eq!(session.domain(), "portal.example.com");
+        assert!(session.is_http_only());
+        assert!(session.expires().is_none());
+
+        assert_eq!(
+            cookie_header(&jar, "portal.example.com", "/api?x=1", false),
+            "Cookie: session=abc123; theme=dark\r\n"
+        );
+        // Host-only cookies are not sent to other hosts, even subdomains
+        assert_eq!(cookie_header(&jar, "a.portal.example.com", "/", false), "");
+    }
+
+    #[test]
+    fn test_domain_and_path_matching() {
+        let mut storage: [Option<Cookie>; 4] = [const { None }; 4];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers(
+            "www.example.com",
+            "/docs/index.html",
+            &[
+                header("Set-Cookie", "wide=1; Domain=.Example.com; Path=/docs"),
+                header("Set-Cookie", "default=2"),
+                header("Set-Cookie", "foreign=3; Domain=other.com"),
+            ],
+            at(0),
+        );
+
+        assert!(jar.get("foreign").is_none());
+        assert_eq!(jar.get("wide").unwrap().domain(), "example.com");
+        assert_eq!(jar.get("default").unwrap().path(), "/docs");
+
+        assert_eq!(
+            cookie_header(&jar, "api.example.com", "/docs/v1", false),
+            "Cookie: wide=1\r\n"
+        );
+        assert_eq!(
+            cookie_header(&jar, "www.example.com", "/docs", false),
+            "Cookie: wide=1; default=2\r\n"
+        );
+        assert_eq!(
+            cookie_header(&jar, "www.example.com", "/docsearch", false),
+            ""
+        );
+        assert_eq!(cookie_header(&jar, "notexample.com", "/docs", false), "");
+    }
+
+    #[test]
+    fn test_single_label_domain_is_rejected() {
+        let mut storage: [Option<Cookie>; 2] = [const { None }; 2];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers(
+            "a.example.com",
+            "/",
+            &[header("Set-Cookie", "tracker=1; Domain=.com")],
+            at(0),
+        );
+        jar.store_from_headers(
+            "example.com",
+            "/",
+            &[header("Set-Cookie", "tracker=2; Domain=com")],
+            at(0),
+        );
+        assert!(jar.get("tracker").is_none());
+        assert_eq!(cookie_header(&jar, "a.example.com", "/", false), "");
+
+        // A host may still name itself
+        jar.store_from_headers(
+            "localhost",
+            "/",
+            &[header("Set-Cookie", "session=abc; Domain=localhost")],
+            at(0),
+        );
+        let session = jar.get("session").unwrap();
+        assert_eq!(session.domain(), "localhost");
+        assert_eq!(
+            cookie_header(&jar, "localhost", "/", false),
+            "Cookie: session=abc\r\n"
+        );
+        assert_eq!(cookie_header(&jar, "sub.localhost", "/", false), "");
+    }
+
+    #[test]
+    fn test_secure_cookie_only_over_https() {
+        let mut storage: [Option<Cookie>; 2] = [const { None }; 2];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers(
+            "example.com",
+            "/",
+            &[header("Set-Cookie", "token=x; Secure")],
+            at(0),
+        );
+
+        assert_eq!(cookie_header(&jar, "example.com", "/", false), "");
+        assert_eq!(
+            cookie_header(&jar, "example.com", "/", true),
+            "Cookie: token=x\r\n"
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut storage: [Option<Cookie>; 4] = [const { None }; 4];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers(
+            "example.com",
+            "/",
+            &[
+                header("Date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+                header("Set-Cookie", "short=1; Max-Age=50"),
+                header(
+                    "Set-Cookie",
+                    "long=2; Max-Age=500; Expires=Wed, 21 Oct 2015 07:28:10 GMT",
+                ),
+                header(
+                    "Set-Cookie",
+                    "dated=3; Expires=Wed, 21 Oct 2015 07:30:00 GMT",
+                ),
+            ],
+            at(10),
+        );
+
+        assert_eq!(jar.get("short").unwrap().expires(), Some(at(60)));
+        // Max-Age takes precedence over Expires
+        assert_eq!(jar.get("long").unwrap().expires(), Some(at(510)));
+        // Expires is taken relative to the Date header
+        assert_eq!(jar.get("dated").unwrap().expires(), Some(at(130)));
+        assert_eq!(
+            cookie_header(&jar, "example.com", "/", false),
+            "Cookie: long=2; dated=3\r\n"
+        );
+
+        // An expired Set-Cookie deletes the stored cookie
+        jar.store_from_headers(
+            "example.com",
+            "/",
+            &[header("Set-Cookie", "long=; Max-Age=0")],
+            at(20),
+        );
+        assert!(jar.get("long").is_none());
+    }
+
+    #[test]
+    fn test_replace_same_cookie() {
+        let mut storage: [Option<Cookie>; 2] = [const { None }; 2];
+        let jar = CookieJar::new(&mut storage);
+        jar.store_from_headers("example.com", "/", &[header("Set-Cookie", "a=1")], at(0));
+        jar.store_from_headers("example.com", "/", &[header("Set-Cookie", "a=2")], at(0));
+        assert_eq!(jar.len(), 1);
+        assert_eq!(jar.get("a").unwrap().value(), "2");
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut storage: [Option<Cookie>; 3] = [const { None }; 3];
+        let jar = CookieJar::new(&mut storage);
+        let store = |value: &str, now| {
+            jar.store_from_headers("example.com", "/", &[header("Set-Cookie", value)], at(now));
+        };
+
+        store("a=1; Path=/a", 0);
+        store("b=2; Max-Age=10", 0);
+        store("c=3; Path=/c", 0);
+
+        // The expired cookie is dropped first, even though `a` is older
+        store("d=4; Path=/d", 50);
+        assert!(jar.get("b").is_none());
+        assert!(jar.get("a").is_some());
+
+        // Sending `a` makes `c` the least recently used cookie
+        let mut out = std::string::String::new();
+        jar.write_cookie_header(&mut out, "example.com", "/a", false, at(60))
+            .unwrap();
+        store("e=5", 60);
+        assert!(jar.get("c").is_none());
+        assert_eq!(jar.len(), 3);
+
+        // `d` is now the least recently used cookie
+        store("f=6", 70);
+        assert!(jar.get("d").is_none());
+        assert!(jar.get("a").is_some());
+    }
+
+    #[test]
+    fn test_rejects_oversized_and_invalid_cookies() {
+        let mut storage: [Option<Cookie>; 2] = [const { None }; 2];
+        let jar = CookieJar::new(&mut storage);
+        let long_value = format!("big={}", "x".repeat(MAX_VALUE_LEN + 1));
+        jar.store_from_headers(
+            "example.com",
+            "/",
+            &[
+                header("Set-Cookie", &long_value),
+                header("Set-Cookie", "novalue"),
+                header("Set-Cookie", "=anonymous"),
+            ],
+            at(0),
+        );
+        assert!(jar.is_empty());
+        assert_eq!(jar.capacity(), 2);
+    }
+}
@@ -0,0 +1,400 @@
+use crate::{cache::MAX_ETAG_LEN, error::Error, response::HttpResponse, status_code::StatusCode};
+use heapless::String;
+
+/// Destination for the bytes of a resumable download
+///
+/// Implement this for flash partitions, files or RAM buffers. Pieces arrive in order, but
+/// after a restart (when the resource changed on the server) writing begins again at offset 0.
+#[allow(async_fn_in_trait)]
+pub trait DownloadSink {
+    /// Error returned when data cannot be stored
+    type Error;
+
+    /// Store `data` at byte `offset` of the downloaded resource
+    async fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Progress of a resumable download
+///
+/// Keep this value between attempts: after a failed [`crate::HttpClient::download`], calling it
+/// again with the same state continues from [`Download::offset`] instead of byte zero.
+#[derive(Debug, Clone, Default)]
+pub struct Download {
+    offset: u64,
+    total: Option<u64>,
+    etag: Option<String<MAX_ETAG_LEN>>,
+}
+
+/// Number of times one [`crate::HttpClient::download`] call starts over before giving up
+///
+/// Without a limit, a server that answers every request with a different `ETag` would
+/// restart the download forever.
+pub(crate) const MAX_RESTARTS: u8 = 1;
+
+/// What to do with the body of a response to a range request
+#[derive(Debug, PartialEq)]
+pub(crate) enum Piece {
+    /// Write the body at this offset, then call [`Download::advance`]
+    Write(u64),
+    /// The resource changed on the server; the download starts over
+    Restart,
+    /// Nothing left to fetch
+    Done,
+}
+
+impl Download {
+    /// Start a new download at byte zero
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            offset: 0,
+            total: None,
+            etag: None,
+        }
+    }
+
+    /// Number of bytes written to the sink so far
+    #[must_use]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Full size of the resource, once the server reported it
+    #[must_use]
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// The strong `ETag` that resumed requests are checked against with `If-Range`
+    #[must_use]
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// Check whether every byte of the resource has been written
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.total.is_some_and(|total| self.offset >= total)
+    }
+
+    /// Remember the response's `ETag` if it can be used with `If-Range`
+    fn set_etag(&mut self, response: &HttpResponse<'_>) {
+        // Weak validators are not allowed in If-Range
+        self.etag = response
+            .get_header("ETag")
+            .filter(|etag| !etag.starts_with("W/"))
+            .and_then(|etag| String::try_from(etag).ok());
+    }
+
+    /// Check the response to a range request starting at [`Download::offset`]
+    pub(crate) fn accept(&mut self, response: &HttpResponse<'_>) -> Result<Piece, Error> {
+        match response.status_code {
+            StatusCode::PartialContent => {
+                let etag = response.get_header("ETag");
+                if let (Some(ours), Some(theirs)) = (&self.etag, etag)
+                    && ours != theirs
+                {
+                    // The server ignored If-Range although the resource changed
+                    *self = Self::new();
+                    return Ok(Piece::Restart);
+                }
+                if self.etag.is_none() {
+                    self.set_etag(response);
+                }
+
+                let (start, end, total) = response
+                    .get_header("Content-Range")
+                    .and_then(parse_content_range)
+                    .ok_or(Error::InvalidResponse("Invalid Content-Range"))?;
+                let (Some(start), Some(end)) = (start, end) else {
+                    return Err(Error::InvalidResponse("Invalid Content-Range"));
+                };
+                if start != self.offset || end - start + 1 != response.body.len() as u64 {
+                    return Err(Error::InvalidResponse("Unexpected Content-Range"));
+                }
+                if total.is_some() {
+                    self.total = total;
+                }
+                Ok(Piece::Write(start))
+            }
+            StatusCode::Ok => {
+                // Either ranges are unsupported or If-Range failed; this is the whole resource
+                *self = Self::new();
+                self.set_etag(response);
+                self.total = Some(response.body.len() as u64);
+                Ok(Piece::Write(0))
+            }
+            StatusCode::RequestedRangeNotSatisfiable => {
+                let total = response
+                    .get_header("Content-Range")
+                    .and_then(parse_content_range)
+                    .and_then(|(_, _, total)| total);
+                match total {
+                    Some(total) if self.offset == total => {
+                        self.total = Some(total);
+                        Ok(Piece::Done)
+                    }
+                    // The resource shrank since the download started
+                    Some(total) if self.offset > total => {
+                        *self = Self::new();
+                        Ok(Piece::Restart)
+                    }
+                    _ => Err(Error::Status(response.status_code)),
+                }
+            }
+            status => Err(Error::Status(status)),
+        }
+    }
+
+    /// Like [`Download::accept`], but counts restarts in `restarts` and fails with
+    /// [`Error::DownloadChanged`] once more than [`MAX_RESTARTS`] are needed
+    pub(crate) fn accept_within(
+        &mut self,
+        response: &HttpResponse<'_>,
+        restarts: &mut u8,
+    ) -> Result<Piece, Error> {
+        let piece = self.accept(response)?;
+        if piece == Piece::Restart {
+            if *restarts >= MAX_RESTARTS {
+                return Err(Error::DownloadChanged);
+            }
+            *restarts += 1;
+        }
+        Ok(piece)
+    }
+
+    /// Record that `written` bytes were stored after asking for `requested`
+    pub(crate) fn advance(&mut self, written: usize, requested: u64) {
+        self.offset += written as u64;
+        // A short piece from a server that does not report the total marks the end
+        if self.total.is_none() && (written as u64) < requested {
+            self.total = Some(self.offset);
+        }
+    }
+}
+
+/// Parse `Content-Range: bytes <start>-<end>/<total>` where the range or total may be `*`
+///
+/// Returns `(start, end, total)`; the range is `None` for the `bytes */<total>` form of a
+/// `416` response.
+pub(crate) fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>, Option<u64>)> {
+    let (unit, rest) = value.trim().split_once(' ')?;
+    if !unit.eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let (range, total) = rest.trim().split_once('/')?;
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    let (start, end) = match range {
+        "*" => (None, None),
+        range => {
+            let (start, end) = range.split_once('-')?;
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start || total.is_some_and(|total| end >= total) {
+                return None;
+            }
+            (Some(start), Some(end))
+        }
+    };
+    if start.is_none() && total.is_none() {
+        return None;
+    }
+    Some((start, end, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::HttpHeader, response::ResponseBody};
+
+    fn response<'a>(
+        status_code: StatusCode,
+        headers: &[HttpHeader<'a>],
+        body: &'a [u8],
+    ) -> HttpResponse<'a> {
+        HttpResponse {
+            status_code,
+            headers: headers.iter().cloned().collect(),
+            body: ResponseBody::Binary(body),
+        }
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(
+            parse_content_range("bytes 0-499/1234"),
+            Some((Some(0), Some(499), Some(1234)))
+        );
+        assert_eq!(
+            parse_content_range("bytes 500-999/*"),
+            Some((Some(500), Some(999), None))
+        );
+        assert_eq!(
+            parse_content_range("bytes */1234"),
+            Some((None, None, Some(1234)))
+        );
+        assert_eq!(parse_content_range("bytes */*"), None);
+        assert_eq!(parse_content_range("bytes 10-5/100"), None);
+        assert_eq!(parse_content_range("bytes 0-100/100"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+    }
+
+    #[test]
+    fn test_resume_with_partial_content() {
+        let mut download = Download::new();
+        let first = response(
+            StatusCode::PartialContent,
+            &[
+                HttpHeader::new("Content-Range", "bytes 0-3/10"),
+                HttpHeader::new("ETag", "\"fw-1\""),
+            ],
+            b"abcd",
+        );
+        assert_eq!(download.accept(&first).unwrap(), Piece::Write(0));
+        download.advance(4, 4);
+        assert_eq!(download.offset(), 4);
+        assert_eq!(download.total(), Some(10));
+        assert_eq!(download.etag(), Some("\"fw-1\""));
+
+        // A piece that does not continue where we stopped is rejected
+        let wrong = response(
+            StatusCode::PartialContent,
+            &[HttpHeader::new("Content-Range", "bytes 0-3/10")],
+            b"abcd",
+        );
+        assert!(download.accept(&wrong).is_err());
+        assert_eq!(download.offset(), 4);
+
+        let last = response(
+            StatusCode::PartialContent,
+            &[HttpHeader::new("Content-Range", "bytes 4-9/10")],
+            b"efghij",
+        );
+        assert_eq!(download.accept(&last).unwrap(), Piece::Write(4));
+        download.advance(6, 6);
+        assert!(download.is_complete());
+    }
+
+    #[test]
+    fn test_full_response_restarts() {
+        let mut download = Download::new();
+        download.offset = 4;
+        download.etag = Some(String::try_from("\"fw-1\"").unwrap());
+
+        // If-Range failed: the server sends the new version in full
+        let full = response(
+            StatusCode::Ok,
+            &[HttpHeader::new("ETag", "\"fw-2\"")],
+            b"new",
+        );
+        assert_eq!(download.accept(&full).unwrap(), Piece::Write(0));
+        download.advance(3, 3);
+        assert!(download.is_complete());
+        assert_eq!(download.etag(), Some("\"fw-2\""));
+    }
+
+    #[test]
+    fn test_changed_etag_restarts() {
+        let mut download = Download::new();
+        download.offset = 4;
+        download.etag = Some(String::try_from("\"fw-1\"").unwrap());
+
+        let changed = response(
+            StatusCode::PartialContent,
+            &[
+                HttpHeader::new("Content-Range", "bytes 4-5/6"),
+                HttpHeader::new("ETag", "\"fw-2\""),
+            ],
+            b"xy",
+        );
+        assert_eq!(download.accept(&changed).unwrap(), Piece::Restart);
+        assert_eq!(download.offset(), 0);
+        assert!(download.etag().is_none());
+    }
+
+    #[test]
+    fn test_restarts_are_bounded() {
+        // A server that sends a new ETag with every piece
+        let piece = |range, etag, body| {
+            response(
+                StatusCode::PartialContent,
+                &[
+                    HttpHeader::new("Content-Range", range),
+                    HttpHeader::new("ETag", etag),
+                ],
+                body,
+            )
+        };
+        let mut download = Download::new();
+        let mut restarts = 0;
+
+        for _ in 0..MAX_RESTARTS {
+            let first = piece("bytes 0-1/4", "\"v1\"", b"ab");
+            assert_eq!(
+                download.accept_within(&first, &mut restarts).unwrap(),
+                Piece::Write(0)
+            );
+            download.advance(2, 2);
+            let changed = piece("bytes 2-3/4", "\"v2\"", b"cd");
+            assert_eq!(
+                download.accept_within(&changed, &mut restarts).unwrap(),
+                Piece::Restart
+            );
+        }
+
+        let first = piece("bytes 0-1/4", "\"v3\"", b"ab");
+        download.accept_within(&first, &mut restarts).unwrap();
+        download.advance(2, 2);
+        let changed = piece("bytes 2-3/4", "\"v4\"", b"cd");
+        assert!(matches!(
+            download.accept_within(&changed, &mut restarts),
+            Err(Error::DownloadChanged)
+        ));
+        assert_eq!(download.offset(), 0);
+    }
+
+    #[test]
+    fn test_range_not_satisfiable() {
+        let mut download = Download::new();
+        download.offset = 10;
+        let done = response(
+            StatusCode::RequestedRangeNotSatisfiable,
+            &[HttpHeader::new("Content-Range", "bytes */10")],
+            b"",
+        );
+        assert_eq!(download.accept(&done).unwrap(), Piece::Done);
+        assert!(download.is_complete());
+
+        let mut download = Download::new();
+        download.offset = 4;
+        let shrunk = response(
+            StatusCode::RequestedRangeNotSatisfiable,
+            &[HttpHeader::new("Content-Range", "bytes */2")],
+            b"",
+        );
+        assert_eq!(download.accept(&shrunk).unwrap(), Piece::Restart);
+        assert_eq!(download.offset(), 0);
+
+        let missing = response(StatusCode::NotFound, &[], b"");
+        assert!(matches!(
+            Download::new().accept(&missing),
+            Err(Error::Status(StatusCode::NotFound))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_total_ends_on_short_piece() {
+        let mut download = Download::new();
+        let piece = response(
+            StatusCode::PartialContent,
+            &[HttpHeader::new("Content-Range", "bytes 0-2/*")],
+            b"abc",
+        );
+        assert_eq!(download.accept(&piece).unwrap(), Piece::Write(0));
+        download.advance(3, 8);
+        assert!(download.is_complete());
+        assert_eq!(download.total(), Some(3));
+    }
+}
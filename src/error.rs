@@ -1,6 +1,9 @@
+use crate::status_code::StatusCode;
 use embassy_net_08 as embassy_net;
 #[cfg(feature = "tls")]
 use embedded_tls_018 as embedded_tls;
+#[cfg(feature = "json")]
+use serde_json_core_06 as serde_json_core;
 /// Errors that can occur during HTTP operations
 ///
 /// This enum represents all possible errors that can be returned by the HTTP client
@@ -32,8 +35,219 @@ pub enum Error {
     HeaderError(&'static str),
     /// Invalid status code received from the server
     InvalidStatusCode,
+    /// A configured timeout elapsed before the operation completed
+    Timeout(TimeoutKind),
+    /// The request was cancelled through its [`crate::CancelToken`]
+    Cancelled,
+    /// The request does not fit into the request buffer (`RQ`)
+    RequestTooLarge,
+    /// The response does not fit into the response buffer
+    ResponseTooLarge,
+    /// The header section does not fit into the available buffer
+    HeaderTooLong,
+    /// More headers were received than can be stored
+    TooManyHeaders,
+    /// The response status line is not of the form `HTTP/x.y <code> <reason>`
+    MalformedStatusLine,
+    /// The request line is not of the form `<method> <target> HTTP/x.y`
+    MalformedRequestLine,
+    /// The connection was closed before the complete message was received
+    PrematureEof,
+    /// The server answered with a non-2xx status
+    ///
+    /// Returned by [`crate::HttpClient::request`] and the methods built on it when
+    /// [`crate::HttpClientOptions::error_on_status`] is enabled, and always by the JSON and
+    /// codec helpers (`get_json`, `post_json`, `put_json`, `get_with_codec`, `post_with_codec`
+    /// and `put_with_codec`) and by [`crate::HttpClient::download`].
+    Status(StatusCode),
+    /// The HTTP proxy refused to open a tunnel
+    ProxyRejected(StatusCode),
+    /// The SOCKS5 handshake with the proxy failed
+    Socks5(Socks5Error),
+    /// The [`crate::DownloadSink`] failed to store downloaded data
+    Sink,
+    /// The resource kept changing on the server while it was being downloaded
+    DownloadChanged,
+    /// A form body, or a decoded form, query or path component, does not fit into its buffer
+    FormTooLarge,
+    /// A multipart boundary is malformed or occurs in a part's content
+    InvalidBoundary,
+    /// A form or query component has a malformed percent escape or is not UTF-8
+    InvalidForm,
+    /// A request path has a malformed percent escape, is not UTF-8 or contains a `..` segment
+    InvalidPath,
+    /// The decoded response body does not fit into the output buffer
+    #[cfg(feature = "compression")]
+    DecodedBodyTooLarge,
+    /// The compressed response body is corrupt or truncated
+    #[cfg(feature = "compression")]
+    InvalidCompressedBody,
+    /// The compressed request body does not fit into the scratch buffer
+    #[cfg(feature = "compression")]
+    EncodedBodyTooLarge,
+    /// A body could not be encoded with a [`crate::codec::BodyCodec`]
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    BodyEncode(&'static str),
+    /// A body could not be decoded with a [`crate::codec::BodyCodec`]
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    BodyDecode(&'static str),
+    /// A value could not be serialized as JSON into the scratch buffer
+    #[cfg(feature = "json")]
+    JsonSerialize(serde_json_core::ser::Error),
+    /// The response body could not be deserialized from JSON
+    #[cfg(feature = "json")]
+    JsonDeserialize(serde_json_core::de::Error),
 }
 
+impl Error {
+    /// Check whether retrying the same request may succeed
+    ///
+    /// Transient network conditions (timeouts, resets, unreachable hosts, truncated
+    /// responses) and `408`, `429` and `5xx` statuses are considered retryable.
+    /// Malformed input, configuration problems and cancellation are not.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        use embassy_net::{dns, tcp::ConnectError};
+
+        match self {
+            Error::DnsError(e) => matches!(e, dns::Error::Failed),
+            Error::ConnectionError(e) => matches!(
+                e,
+                ConnectError::ConnectionReset | ConnectError::TimedOut | ConnectError::NoRoute
+            ),
+            Error::IpAddressEmpty
+            | Error::TcpError(_)
+            | Error::NoResponse
+            | Error::Timeout(_)
+            | Error::PrematureEof => true,
+            #[cfg(feature = "tls")]
+            Error::TlsError(e) => matches!(
+                e,
+                embedded_tls::TlsError::ConnectionClosed
+                    | embedded_tls::TlsError::IoError
+                    | embedded_tls::TlsError::Io(_)
+            ),
+            Error::Status(status) => {
+                matches!(status.as_u16(), 408 | 429) || status.is_server_error()
+            }
+            Error::ProxyRejected(status) => matches!(status.as_u16(), 502..=504),
+            Error::Socks5(e) => matches!(
+                e,
+                Socks5Error::GeneralFailure
+                    | Socks5Error::NetworkUnreachable
+                    | Socks5Error::HostUnreachable
+                    | Socks5Error::TtlExpired
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// The stage of a request that exceeded its configured timeout
+///
+/// Each variant corresponds to one of the timeouts in [`crate::HttpClientOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeoutKind {
+    /// DNS lookup of the host name
+    Dns,
+    /// TCP connection establishment
+    Connect,
+    /// TLS handshake
+    TlsHandshake,
+    /// Waiting for the first byte of the response
+    FirstByte,
+    /// Overall request deadline
+    Request,
+}
+
+impl core::fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TimeoutKind::Dns => write!(f, "DNS lookup"),
+            TimeoutKind::Connect => write!(f, "TCP connect"),
+            TimeoutKind::TlsHandshake => write!(f, "TLS handshake"),
+            TimeoutKind::FirstByte => write!(f, "first byte"),
+            TimeoutKind::Request => write!(f, "request deadline"),
+        }
+    }
+}
+
+/// Failures reported by, or while talking to, a SOCKS5 proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Socks5Error {
+    /// The proxy answered with an unexpected protocol version
+    InvalidVersion,
+    /// The proxy sent a reply that does not follow the protocol
+    InvalidResponse,
+    /// The proxy accepts none of the offered authentication methods
+    NoAcceptableAuthMethod,
+    /// The proxy rejected the username or password
+    AuthenticationFailed,
+    /// A host name, username or password is longer than 255 bytes
+    NameTooLong,
+    /// General SOCKS server failure (reply `0x01`)
+    GeneralFailure,
+    /// Connection not allowed by ruleset (reply `0x02`)
+    NotAllowed,
+    /// Network unreachable (reply `0x03`)
+    NetworkUnreachable,
+    /// Host unreachable (reply `0x04`)
+    HostUnreachable,
+    /// Connection refused by the target (reply `0x05`)
+    ConnectionRefused,
+    /// TTL expired (reply `0x06`)
+    TtlExpired,
+    /// Command not supported (reply `0x07`)
+    CommandNotSupported,
+    /// Address type not supported (reply `0x08`)
+    AddressTypeNotSupported,
+    /// Reply code not defined by RFC 1928
+    Unknown(u8),
+}
+
+impl Socks5Error {
+    /// Map a non-zero `REP` field of a SOCKS5 reply
+    pub(crate) fn from_reply(code: u8) -> Self {
+        match code {
+            0x01 => Socks5Error::GeneralFailure,
+            0x02 => Socks5Error::NotAllowed,
+            0x03 => Socks5Error::NetworkUnreachable,
+            0x04 => Socks5Error::HostUnreachable,
+            0x05 => Socks5Error::ConnectionRefused,
+            0x06 => Socks5Error::TtlExpired,
+            0x07 => Socks5Error::CommandNotSupported,
+            0x08 => Socks5Error::AddressTypeNotSupported,
+            other => Socks5Error::Unknown(other),
+        }
+    }
+}
+
+impl core::fmt::Display for Socks5Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Socks5Error::InvalidVersion => write!(f, "unexpected protocol version"),
+            Socks5Error::InvalidResponse => write!(f, "invalid reply"),
+            Socks5Error::NoAcceptableAuthMethod => write!(f, "no acceptable authentication method"),
+            Socks5Error::AuthenticationFailed => write!(f, "authentication failed"),
+            Socks5Error::NameTooLong => write!(f, "name longer than 255 bytes"),
+            Socks5Error::GeneralFailure => write!(f, "general server failure"),
+            Socks5Error::NotAllowed => write!(f, "connection not allowed by ruleset"),
+            Socks5Error::NetworkUnreachable => write!(f, "network unreachable"),
+            Socks5Error::HostUnreachable => write!(f, "host unreachable"),
+            Socks5Error::ConnectionRefused => write!(f, "connection refused"),
+            Socks5Error::TtlExpired => write!(f, "TTL expired"),
+            Socks5Error::CommandNotSupported => write!(f, "command not supported"),
+            Socks5Error::AddressTypeNotSupported => write!(f, "address type not supported"),
+            Socks5Error::Unknown(code) => write!(f, "unknown reply code {code:#04x}"),
+        }
+    }
+}
+
+impl core::error::Error for Socks5Error {}
+
+#[cfg(feature = "defmt")]
 impl defmt::Format for Error {
     fn format(&self, fmt: defmt::Formatter) {
         defmt::write!(fmt, "{:?}", defmt::Debug2Format(self));
@@ -52,6 +266,12 @@ impl From<embassy_net::tcp::ConnectError> for Error {
     }
 }
 
+impl From<Socks5Error> for Error {
+    fn from(err: Socks5Error) -> Self {
+        Error::Socks5(err)
+    }
+}
+
 impl From<embassy_net::tcp::Error> for Error {
     fn from(err: embassy_net::tcp::Error) -> Self {
         Error::TcpError(err)
@@ -65,21 +285,104 @@ impl From<embedded_tls::TlsError> for Error {
     }
 }
 
+#[cfg(feature = "json")]
+impl From<serde_json_core::ser::Error> for Error {
+    fn from(err: serde_json_core::ser::Error) -> Self {
+        Error::JsonSerialize(err)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json_core::de::Error> for Error {
+    fn from(err: serde_json_core::de::Error) -> Self {
+        Error::JsonDeserialize(err)
+    }
+}
+
 impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Error::InvalidUrl => write!(f, "Invalid URL"),
-            Error::DnsError(_) => write!(f, "DNS resolution failed"),
+            Error::DnsError(e) => write!(f, "DNS resolution failed: {e:?}"),
             Error::IpAddressEmpty => write!(f, "No IP addresses returned by DNS"),
-            Error::ConnectionError(_) => write!(f, "Failed to establish TCP connection"),
-            Error::TcpError(_) => write!(f, "TCP communication error"),
+            Error::ConnectionError(e) => write!(f, "Failed to establish TCP connection: {e:?}"),
+            Error::TcpError(e) => write!(f, "TCP communication error: {e:?}"),
             Error::NoResponse => write!(f, "No response received from server"),
             Error::InvalidResponse(msg) => write!(f, "Invalid response: {msg}"),
             #[cfg(feature = "tls")]
-            Error::TlsError(_) => write!(f, "TLS error occurred"),
+            Error::TlsError(e) => write!(f, "TLS error occurred: {e:?}"),
             Error::UnsupportedScheme(scheme) => write!(f, "Unsupported scheme: {scheme}"),
             Error::HeaderError(msg) => write!(f, "Header error: {msg}"),
             Error::InvalidStatusCode => write!(f, "Invalid status code"),
+            Error::Timeout(kind) => write!(f, "Timed out: {kind}"),
+            Error::Cancelled => write!(f, "Request cancelled"),
+            Error::RequestTooLarge => write!(f, "Request does not fit into the request buffer"),
+            Error::ResponseTooLarge => {
+                write!(f, "Response does not fit into the response buffer")
+            }
+            Error::HeaderTooLong => write!(f, "Header section too long"),
+            Error::TooManyHeaders => write!(f, "Too many headers"),
+            Error::MalformedStatusLine => write!(f, "Malformed status line"),
+            Error::MalformedRequestLine => write!(f, "Malformed request line"),
+            Error::PrematureEof => write!(f, "Connection closed before message was complete"),
+            Error::Status(status) => {
+                write!(
+                    f,
+                    "HTTP error status: {} {}",
+                    status.as_u16(),
+                    status.text()
+                )
+            }
+            Error::ProxyRejected(status) => {
+                write!(
+                    f,
+                    "Proxy rejected tunnel: {} {}",
+                    status.as_u16(),
+                    status.text()
+                )
+            }
+            Error::Socks5(e) => write!(f, "SOCKS5 proxy error: {e}"),
+            Error::Sink => write!(f, "Download sink failed to store data"),
+            Error::DownloadChanged => write!(f, "Resource kept changing during download"),
+            Error::FormTooLarge => write!(f, "Form does not fit into its buffer"),
+            Error::InvalidBoundary => write!(f, "Invalid or colliding multipart boundary"),
+            Error::InvalidForm => write!(f, "Malformed form encoding"),
+            Error::InvalidPath => write!(f, "Malformed or escaping request path"),
+            #[cfg(feature = "compression")]
+            Error::DecodedBodyTooLarge => {
+                write!(f, "Decoded body does not fit into the output buffer")
+            }
+            #[cfg(feature = "compression")]
+            Error::InvalidCompressedBody => write!(f, "Compressed body is corrupt or truncated"),
+            #[cfg(feature = "compression")]
+            Error::EncodedBodyTooLarge => {
+                write!(f, "Compressed body does not fit into the scratch buffer")
+            }
+            #[cfg(any(feature = "cbor", feature = "msgpack"))]
+            Error::BodyEncode(msg) => write!(f, "Body encoding failed: {msg}"),
+            #[cfg(any(feature = "cbor", feature = "msgpack"))]
+            Error::BodyDecode(msg) => write!(f, "Body decoding failed: {msg}"),
+            #[cfg(feature = "json")]
+            Error::JsonSerialize(e) => write!(f, "JSON serialization failed: {e}"),
+            #[cfg(feature = "json")]
+            Error::JsonDeserialize(e) => write!(f, "JSON deserialization failed: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::ConnectionError(e) => Some(e),
+            Error::TcpError(e) => Some(e),
+            Error::Socks5(e) => Some(e),
+            #[cfg(feature = "tls")]
+            Error::TlsError(e) => Some(e),
+            #[cfg(feature = "json")]
+            Error::JsonSerialize(e) => Some(e),
+            #[cfg(feature = "json")]
+            Error::JsonDeserialize(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -106,6 +409,63 @@ mod tests {
         assert_eq!(format!("{e}"), "Header error: too long");
         let e = Error::InvalidStatusCode;
         assert_eq!(format!("{e}"), "Invalid status code");
+        let e = Error::Timeout(TimeoutKind::Dns);
+        assert_eq!(format!("{e}"), "Timed out: DNS lookup");
+        let e = Error::Timeout(TimeoutKind::Request);
+        assert_eq!(format!("{e}"), "Timed out: request deadline");
+        let e = Error::Cancelled;
+        assert_eq!(format!("{e}"), "Request cancelled");
+        let e = Error::Status(StatusCode::NotFound);
+        assert_eq!(format!("{e}"), "HTTP error status: 404 Not Found");
+        let e = Error::DnsError(dns::Error::NameTooLong);
+        assert_eq!(format!("{e}"), "DNS resolution failed: NameTooLong");
+        let e = Error::TcpError(tcp::Error::ConnectionReset);
+        assert_eq!(format!("{e}"), "TCP communication error: ConnectionReset");
+        let e = Error::Socks5(Socks5Error::from_reply(0x05));
+        assert_eq!(format!("{e}"), "SOCKS5 proxy error: connection refused");
+        let e = Error::Socks5(Socks5Error::from_reply(0x2a));
+        assert_eq!(
+            format!("{e}"),
+            "SOCKS5 proxy error: unknown reply code 0x2a"
+        );
+    }
+
+    #[test]
+    fn test_error_source() {
+        use core::error::Error as _;
+
+        // `?` converts into boxed trait objects in generic error stacks
+        fn fails() -> Result<(), Box<dyn std::error::Error>> {
+            Err(Error::Cancelled)?
+        }
+
+        let e = Error::TcpError(tcp::Error::ConnectionReset);
+        assert!(e.source().is_some());
+        let e = Error::InvalidUrl;
+        assert!(e.source().is_none());
+        assert_eq!(fails().unwrap_err().to_string(), "Request cancelled");
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(Error::Timeout(TimeoutKind::Connect).is_retryable());
+        assert!(Error::TcpError(tcp::Error::ConnectionReset).is_retryable());
+        assert!(Error::ConnectionError(tcp::ConnectError::TimedOut).is_retryable());
+        assert!(!Error::ConnectionError(tcp::ConnectError::InvalidState).is_retryable());
+        assert!(Error::DnsError(dns::Error::Failed).is_retryable());
+        assert!(!Error::DnsError(dns::Error::InvalidName).is_retryable());
+        assert!(Error::PrematureEof.is_retryable());
+        assert!(Error::Status(StatusCode::ServiceUnavailable).is_retryable());
+        assert!(Error::Status(StatusCode::RequestTimeout).is_retryable());
+        assert!(Error::Status(StatusCode::Other(429)).is_retryable());
+        assert!(!Error::Status(StatusCode::NotFound).is_retryable());
+        assert!(Error::ProxyRejected(StatusCode::BadGateway).is_retryable());
+        assert!(!Error::ProxyRejected(StatusCode::Forbidden).is_retryable());
+        assert!(Error::Socks5(Socks5Error::HostUnreachable).is_retryable());
+        assert!(!Error::Socks5(Socks5Error::AuthenticationFailed).is_retryable());
+        assert!(!Error::InvalidUrl.is_retryable());
+        assert!(!Error::RequestTooLarge.is_retryable());
+        assert!(!Error::Cancelled.is_retryable());
     }
 
     #[test]
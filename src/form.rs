@@ -0,0 +1,348 @@
+use crate::error::Error;
+
+/// Builder for an `application/x-www-form-urlencoded` body in a fixed buffer
+///
+/// Keys and values are percent-encoded as they are appended. Send the result with
+/// [`crate::HttpClient::post_form`].
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use nanofish::FormEncoder;
+///
+/// let mut buffer = [0u8; 256];
+/// let mut form = FormEncoder::new(&mut buffer);
+/// form.append("ssid", "Home WiFi")?.append("channel", "6")?;
+/// assert_eq!(form.as_bytes(), b"ssid=Home+WiFi&channel=6");
+/// ```
+pub struct FormEncoder<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> FormEncoder<'b> {
+    /// Start an empty form in `buffer`
+    #[must_use]
+    pub fn new(buffer: &'b mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// Append a `key=value` pair
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::FormTooLarge`] if the pair does not fit into the buffer; the form is
+    /// left as it was.
+    pub fn append(&mut self, key: &str, value: &str) -> Result<&mut Self, Error> {
+        let start = self.len;
+        let result = self.write_pair(key, value);
+        if result.is_err() {
+            self.len = start;
+        }
+        result.map(|()| self)
+    }
+
+    fn write_pair(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        if self.len > 0 {
+            self.push(b'&')?;
+        }
+        self.write_encoded(key)?;
+        self.push(b'=')?;
+        self.write_encoded(value)
+    }
+
+    fn write_encoded(&mut self, s: &str) -> Result<(), Error> {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        for &b in s.as_bytes() {
+            match b {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                    self.push(b)?;
+                }
+                b' ' => self.push(b'+')?,
+                b => {
+                    self.push(b'%')?;
+                    self.push(HEX[usize::from(b >> 4)])?;
+                    self.push(HEX[usize::from(b & 0x0f)])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), Error> {
+        *self.buffer.get_mut(self.len).ok_or(Error::FormTooLarge)? = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The encoded form so far
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Number of bytes written so far
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether no pair has been appended yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return the encoded form, borrowed for the lifetime of the buffer
+    #[must_use]
+    pub fn finish(self) -> &'b [u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+/// Iterator over the still-encoded `(key, value)` pairs of a form body or query string
+///
+/// Pairs borrow from the input; decode them with [`decode`] or look a key up with
+/// [`FormPairs::get`]. Empty segments (`a=1&&b=2`) are skipped, and a segment without `=`
+/// has an empty value.
+#[derive(Debug, Clone)]
+pub struct FormPairs<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> FormPairs<'a> {
+    /// Iterate over the pairs of `input`, e.g. a request body or the part after `?`
+    #[must_use]
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { rest: input }
+    }
+
+    /// Find the first pair whose decoded key is `key` and decode its value into `scratch`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidForm`] if the value is malformed and [`Error::FormTooLarge`]
+    /// if it does not fit into `scratch`.
+    pub fn get<'s>(self, key: &str, scratch: &'s mut [u8]) -> Result<Option<&'s str>, Error>
+    where
+        'a: 's,
+    {
+        match self.into_iter().find(|(k, _)| decoded_eq(k, key)) {
+            Some((_, value)) => decode(value, scratch).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`Iterator::next`], but with the key and value decoded into `scratch`
+    ///
+    /// `scratch` never needs to be longer than the encoded pair.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidForm`] if the pair is malformed and [`Error::FormTooLarge`] if
+    /// it does not fit into `scratch`.
+    pub fn next_decoded<'s>(
+        &mut self,
+        scratch: &'s mut [u8],
+    ) -> Option<Result<(&'s str, &'s str), Error>>
+    where
+        'a: 's,
+    {
+        let (key, value) = self.next()?;
+        // A decoded component is never longer than the encoded one
+        let (key_scratch, value_scratch) = scratch.split_at_mut(key.len().min(scratch.len()));
+        Some(decode(key, key_scratch).and_then(|key| Ok((key, decode(value, value_scratch)?))))
+    }
+}
+
+impl<'a> Iterator for FormPairs<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            let (segment, rest) = match self.rest.iter().position(|&b| b == b'&') {
+                Some(i) => (&self.rest[..i], &self.rest[i + 1..]),
+                None => (self.rest, &[][..]),
+            };
+            self.rest = rest;
+            if segment.is_empty() {
+                continue;
+            }
+            return Some(match segment.iter().position(|&b| b == b'=') {
+                Some(i) => (&segment[..i], &segment[i + 1..]),
+                None => (segment, &[][..]),
+            });
+        }
+    }
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Iterate over the decoded bytes of a form component, or of a path if `plus_is_space` is off
+fn decoded_bytes(raw: &[u8], plus_is_space: bool) -> impl Iterator<Item = Result<u8, Error>> + '_ {
+    let mut i = 0;
+    core::iter::from_fn(move || {
+        let b = *raw.get(i)?;
+        i += 1;
+        Some(match b {
+            b'+' if plus_is_space => Ok(b' '),
+            b'%' => {
+                let hi = raw.get(i).copied().and_then(hex_value);
+                let lo = raw.get(i + 1).copied().and_then(hex_value);
+                i += 2;
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => Ok(hi << 4 | lo),
+                    _ => Err(Error::InvalidForm),
+                }
+            }
+            b => Ok(b),
+        })
+    })
+}
+
+/// Check whether a raw component decodes to `expected`
+fn decoded_eq(raw: &[u8], expected: &str) -> bool {
+    let mut expected = expected.bytes();
+    decoded_bytes(raw, true).all(|b| b.ok() == expected.next()) && expected.next().is_none()
+}
+
+/// Percent-decode a form component, turning `+` into a space
+///
+/// Components without escapes are returned as they are; others are decoded into `scratch`,
+/// which never needs to be longer than `raw`.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidForm`] for malformed escapes or invalid UTF-8 and
+/// [`Error::FormTooLarge`] if the decoded component does not fit into `scratch`.
+pub fn decode<'r>(raw: &'r [u8], scratch: &'r mut [u8]) -> Result<&'r str, Error> {
+    percent_decode(raw, scratch, true)
+}
+
+/// Percent-decode `raw` into `scratch`, turning `+` into a space if `plus_is_space` is set
+pub(crate) fn percent_decode<'r>(
+    raw: &'r [u8],
+    scratch: &'r mut [u8],
+    plus_is_space: bool,
+) -> Result<&'r str, Error> {
+    if !raw
+        .iter()
+        .any(|&b| b == b'%' || (plus_is_space && b == b'+'))
+    {
+        return core::str::from_utf8(raw).map_err(|_| Error::InvalidForm);
+    }
+    let mut len = 0;
+    for b in decoded_bytes(raw, plus_is_space) {
+        *scratch.get_mut(len).ok_or(Error::FormTooLarge)? = b?;
+        len += 1;
+    }
+    core::str::from_utf8(&scratch[..len]).map_err(|_| Error::InvalidForm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut buffer = [0u8; 64];
+        let mut form = FormEncoder::new(&mut buffer);
+        form.append("ssid", "Home WiFi")
+            .unwrap()
+            .append("pass", "a&b=c/ü")
+            .unwrap();
+        assert_eq!(form.as_bytes(), b"ssid=Home+WiFi&pass=a%26b%3Dc%2F%C3%BC");
+
+        let mut small = [0u8; 12];
+        let mut form = FormEncoder::new(&mut small);
+        form.append("a", "1").unwrap();
+        assert!(matches!(
+            form.append("key", "long value"),
+            Err(Error::FormTooLarge)
+        ));
+        assert_eq!(form.finish(), b"a=1");
+    }
+
+    #[test]
+    fn test_pairs() {
+        let pairs: std::vec::Vec<_> = FormPairs::new(b"a=1&&flag&b=x%20y&c=").collect();
+        assert_eq!(
+            pairs,
+            [
+                (&b"a"[..], &b"1"[..]),
+                (b"flag", b""),
+                (b"b", b"x%20y"),
+                (b"c", b""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut scratch = [0u8; 32];
+        assert_eq!(decode(b"plain", &mut scratch).unwrap(), "plain");
+        assert_eq!(
+            decode(b"Home+WiFi%21%C3%BC", &mut scratch).unwrap(),
+            "Home WiFi!ü"
+        );
+        assert!(matches!(
+            decode(b"bad%2", &mut scratch),
+            Err(Error::InvalidForm)
+        ));
+        assert!(matches!(
+            decode(b"%zz", &mut scratch),
+            Err(Error::InvalidForm)
+        ));
+        assert!(matches!(
+            decode(b"%FF", &mut scratch),
+            Err(Error::InvalidForm)
+        ));
+        assert!(matches!(
+            decode(b"a+b", &mut scratch[..2]),
+            Err(Error::FormTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_next_decoded() {
+        let mut pairs = FormPairs::new(b"a+b=c%26d&plain=x&bad=%2");
+        let mut scratch = [0u8; 16];
+        assert_eq!(
+            pairs.next_decoded(&mut scratch).unwrap().unwrap(),
+            ("a b", "c&d")
+        );
+        assert_eq!(
+            pairs.next_decoded(&mut scratch).unwrap().unwrap(),
+            ("plain", "x")
+        );
+        assert!(matches!(
+            pairs.next_decoded(&mut scratch),
+            Some(Err(Error::InvalidForm))
+        ));
+        assert!(pairs.next_decoded(&mut scratch).is_none());
+    }
+
+    #[test]
+    fn test_get() {
+        let body = b"ssid=Home+WiFi&pass+word=s%3Dcret";
+        let mut scratch = [0u8; 32];
+        assert_eq!(
+            FormPairs::new(body).get("pass word", &mut scratch).unwrap(),
+            Some("s=cret")
+        );
+        assert_eq!(
+            FormPairs::new(body).get("missing", &mut scratch).unwrap(),
+            None
+        );
+    }
+}
@@ -1,10 +1,14 @@
 use crate::{
+    body::RequestBody,
     error::Error,
     header::HttpHeader,
+    middleware::{Layered, Middleware},
     request::HttpRequest,
-    response::{HttpResponse, ResponseBody},
+    response::{HttpResponse, ResponseBody, ResponseBuilder, ServerResponse},
     status_code::StatusCode,
 };
+use embedded_io_async::Read as EmbeddedRead;
+use embedded_io_async_07 as embedded_io_async;
 use heapless::Vec;
 
 /// Trait for handling HTTP requests
@@ -17,6 +21,99 @@ pub trait HttpHandler {
     ) -> Result<HttpResponse<'_>, Error>;
 }
 
+/// Trait for handling HTTP requests whose response is built in a buffer provided by the server
+///
+/// Implement this instead of [`HttpHandler`] when the response contains formatted data, such
+/// as a JSON reading or a computed header value (see [`ResponseBuilder`]), or a body streamed
+/// with [`ServerResponse::streamed`]. Every [`HttpHandler`] is a `ResponseHandler` that
+/// leaves the buffer unused, so both can be passed to [`crate::HttpServer::serve`].
+#[allow(async_fn_in_trait)]
+pub trait ResponseHandler {
+    /// Handle an incoming HTTP request, building the response in `response` where needed
+    async fn respond<'h>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+    ) -> Result<ServerResponse<'h>, Error>;
+
+    /// Wrap this handler in `middleware`
+    ///
+    /// Every call adds an outer layer: in `handler.with_middleware(a).with_middleware(b)`,
+    /// `b` sees each request first and its response last.
+    fn with_middleware<M>(self, middleware: M) -> Layered<M, Self>
+    where
+        M: Middleware,
+        Self: Sized,
+    {
+        Layered::new(middleware, self)
+    }
+}
+
+impl<H> ResponseHandler for H
+where
+    H: HttpHandler,
+{
+    async fn respond<'h>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        _response: ResponseBuilder<'h>,
+    ) -> Result<ServerResponse<'h>, Error> {
+        self.handle_request(request).await.map(Into::into)
+    }
+}
+
+/// Trait for handling HTTP requests whose body is read from the connection incrementally
+///
+/// Used with [`crate::HttpServer::serve_streaming`]. `request.body` is empty; read the body
+/// from `body` instead, in pieces as small as you like.
+#[allow(async_fn_in_trait)]
+pub trait StreamingHandler {
+    /// Handle an incoming HTTP request and return a response
+    async fn handle_request<C>(
+        &mut self,
+        request: &HttpRequest<'_>,
+        body: &mut RequestBody<'_, C>,
+    ) -> Result<ServerResponse<'_>, Error>
+    where
+        C: EmbeddedRead,
+        Error: From<C::Error>;
+}
+
+/// Creates the handler of each worker in [`crate::HttpServer::serve_pool`]
+///
+/// Every worker gets its own handler, so state kept in a handler is never shared between
+/// concurrent connections. Closures `Fn(usize) -> H` are factories, called with the worker
+/// index. To share one handler between all workers instead, implement [`HttpHandler`] for a
+/// reference to it (using interior mutability for any state) and return that reference.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// struct Api<'a> {
+///     config: &'a Mutex<NoopRawMutex, Config>,
+/// }
+///
+/// server.serve_pool::<3, _>(stack, |_worker| Api { config: &CONFIG }).await;
+/// ```
+pub trait HandlerFactory {
+    /// Handler created for each worker
+    type Handler;
+
+    /// Create the handler for worker number `worker`
+    fn handler(&self, worker: usize) -> Self::Handler;
+}
+
+impl<F, H> HandlerFactory for F
+where
+    F: Fn(usize) -> H,
+{
+    type Handler = H;
+
+    fn handler(&self, worker: usize) -> H {
+        self(worker)
+    }
+}
+
 /// A simple handler that serves basic endpoints for testing
 #[derive(Debug)]
 pub struct SimpleHandler;
@@ -109,4 +206,50 @@ mod tests {
         assert_eq!(response.status_code, StatusCode::NotFound);
         assert_eq!(response.body.as_str(), Some("404 Not Found"));
     }
+
+    #[test]
+    fn test_handler_factory() {
+        struct Shared {
+            hits: core::cell::Cell<usize>,
+        }
+
+        impl HttpHandler for &Shared {
+            async fn handle_request(
+                &mut self,
+                _request: &HttpRequest<'_>,
+            ) -> Result<HttpResponse<'_>, Error> {
+                self.hits.set(self.hits.get() + 1);
+                Ok(HttpResponse {
+                    status_code: StatusCode::NoContent,
+                    headers: Vec::new(),
+                    body: ResponseBody::Empty,
+                })
+            }
+        }
+
+        fn handlers<F: HandlerFactory>(factory: &F) -> [F::Handler; 2] {
+            [factory.handler(0), factory.handler(1)]
+        }
+
+        let request = HttpRequest {
+            method: HttpMethod::GET,
+            path: "/",
+            version: "HTTP/1.1",
+            headers: Vec::new(),
+            body: b"",
+        };
+
+        // One handler per worker
+        let [first, second] = handlers(&|worker| worker);
+        assert_eq!((first, second), (0, 1));
+
+        // One handler shared by reference
+        let shared = Shared {
+            hits: core::cell::Cell::new(0),
+        };
+        for mut handler in handlers(&|_| &shared) {
+            futures_lite::future::block_on(handler.handle_request(&request)).unwrap();
+        }
+        assert_eq!(shared.hits.get(), 2);
+    }
 }
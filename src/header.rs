@@ -20,6 +20,10 @@ pub mod headers {
     pub const X_API_KEY: &str = "X-API-Key";
     /// Accept-Encoding header
     pub const ACCEPT_ENCODING: &str = "Accept-Encoding";
+    /// Content-Encoding header
+    pub const CONTENT_ENCODING: &str = "Content-Encoding";
+    /// Allow header, listing the methods a resource supports
+    pub const ALLOW: &str = "Allow";
 }
 
 /// Common MIME types for Content-Type header values
@@ -89,6 +93,13 @@ impl<'a> HttpHeader<'a> {
     }
 }
 
+/// Check whether a comma-separated header value such as `Connection` lists `token`
+pub(crate) fn has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|item| item.trim().eq_ignore_ascii_case(token))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,4 +113,11 @@ mod tests {
         assert_eq!(header.name, "Content-Type");
         assert_eq!(header.value, "application/json");
     }
+
+    #[test]
+    fn test_has_token() {
+        assert!(has_token("keep-alive, Upgrade", "upgrade"));
+        assert!(has_token("Close", "close"));
+        assert!(!has_token("closed", "close"));
+    }
 }
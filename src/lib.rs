@@ -2,34 +2,88 @@
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
+// This mod MUST go first, so that the others see its macros.
+pub(crate) mod fmt;
+
+/// Request bodies read from the connection by the server.
+pub mod body;
+/// Byte buffer helpers shared by the body builders.
+mod buf;
+/// Validator cache for conditional requests.
+pub mod cache;
+/// Cancellation tokens for in-flight requests.
+pub mod cancel;
 /// HTTP client implementation and request logic.
 pub mod client;
+/// CBOR and `MessagePack` body codecs.
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub mod codec;
+/// Gzip and deflate encoding and decoding of message bodies.
+#[cfg(feature = "compression")]
+pub mod compression;
+/// Cookie storage for the HTTP client.
+pub mod cookie;
+/// Resumable downloads with range requests.
+pub mod download;
 /// Error types for HTTP operations.
 pub mod error;
+/// `application/x-www-form-urlencoded` encoding and decoding.
+pub mod form;
 /// HTTP request handlers and traits.
 pub mod handler;
 /// HTTP header types and helpers.
 pub mod header;
 /// HTTP method enum and helpers.
 pub mod method;
+/// Middleware run around server handlers.
+pub mod middleware;
+/// `multipart/form-data` request bodies.
+pub mod multipart;
 /// HTTP client configuration options.
 pub mod options;
+/// HTTP proxy configuration and tunnelling.
+pub mod proxy;
 /// HTTP request types and parsing.
 pub mod request;
 /// HTTP response types and body handling.
 pub mod response;
+/// Path routing for server handlers.
+pub mod router;
 /// HTTP server implementation.
 pub mod server;
+/// SOCKS5 proxy configuration and handshake.
+pub mod socks;
 /// Predefined HTTP status codes as per RFC 2616.
 pub mod status_code;
 
+pub use body::RequestBody;
+pub use cache::{CacheEntry, Conditional, ValidatorCache};
+pub use cancel::CancelToken;
 pub use client::{DefaultHttpClient, HttpClient, SmallHttpClient};
-pub use error::Error;
-pub use handler::{HttpHandler, SimpleHandler};
+#[cfg(feature = "cbor")]
+pub use codec::Cbor;
+#[cfg(feature = "msgpack")]
+pub use codec::{Bin, MsgPack, MsgPackDecode, MsgPackEncode};
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub use codec::{BodyCodec, DecodeBody, EncodeBody};
+#[cfg(feature = "compression")]
+pub use compression::ContentEncoding;
+pub use cookie::{Cookie, CookieJar};
+pub use download::{Download, DownloadSink};
+pub use error::{Error, Socks5Error, TimeoutKind};
+pub use form::{FormEncoder, FormPairs};
+pub use handler::{HandlerFactory, HttpHandler, ResponseHandler, SimpleHandler, StreamingHandler};
 pub use header::{HttpHeader, headers, mime_types};
 pub use method::HttpMethod;
+pub use middleware::{BearerAuth, Cors, Layered, Logger, Middleware, RateLimit};
+pub use multipart::{Multipart, Part};
 pub use options::HttpClientOptions;
+pub use proxy::{HttpProxy, Proxy, ProxyCredentials};
 pub use request::HttpRequest;
-pub use response::{HttpResponse, ResponseBody};
+pub use response::{
+    BodySource, BodyStream, HttpResponse, ResponseBody, ResponseBuilder, ServerResponse,
+};
+pub use router::{Params, Route, RouteHandler, RouteMatch, Router, RouterHandler};
 pub use server::{DefaultHttpServer, HttpServer, ServerTimeouts, SmallHttpServer};
+pub use socks::Socks5Proxy;
 pub use status_code::StatusCode;
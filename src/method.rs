@@ -38,6 +38,8 @@ impl core::fmt::Display for InvalidHttpMethod {
     }
 }
 
+impl core::error::Error for InvalidHttpMethod {}
+
 impl HttpMethod {
     #[must_use]
     /// Returns the string representation of the HTTP method.
@@ -187,6 +189,18 @@ mod tests {
         assert_eq!(format!("{error}"), "Invalid HTTP method");
     }
 
+    #[test]
+    fn test_invalid_http_method_is_error() {
+        fn parse(s: &str) -> Result<HttpMethod, Box<dyn core::error::Error>> {
+            Ok(HttpMethod::try_from(s)?)
+        }
+        assert_eq!(parse("GET").unwrap(), HttpMethod::GET);
+        assert_eq!(
+            parse("NOPE").unwrap_err().to_string(),
+            "Invalid HTTP method"
+        );
+    }
+
     #[test]
     fn test_roundtrip_str_conversion() {
         let methods = [
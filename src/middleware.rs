@@ -0,0 +1,477 @@
+use crate::{
+    error::Error,
+    handler::ResponseHandler,
+    header::{HttpHeader, headers, mime_types},
+    method::HttpMethod,
+    request::HttpRequest,
+    response::{HttpResponse, ResponseBody, ResponseBuilder, ServerResponse},
+    status_code::StatusCode,
+};
+use core::fmt::Write as _;
+use embassy_time::{Duration, Instant};
+use embassy_time_05 as embassy_time;
+use heapless::{String, Vec};
+
+/// Code run around a handler, such as logging, authentication or CORS
+///
+/// A middleware sees every request before the handler. It can answer the request itself,
+/// or pass it on to `next`, along with the response buffer, and inspect or modify the
+/// response on its way back. Add one to a
+/// handler with [`ResponseHandler::with_middleware`]; layers are plain generic types, so a
+/// chain needs neither `dyn` nor a heap.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// struct PoweredBy;
+///
+/// impl Middleware for PoweredBy {
+///     async fn handle<'h, N: ResponseHandler>(
+///         &'h mut self,
+///         request: &HttpRequest<'_>,
+///         response: ResponseBuilder<'h>,
+///         next: &'h mut N,
+///     ) -> Result<ServerResponse<'h>, Error> {
+///         let mut response = next.respond(request, response).await?;
+///         let _ = response.headers.push(HttpHeader::new("X-Powered-By", "nanofish"));
+///         Ok(response)
+///     }
+/// }
+/// ```
+#[allow(async_fn_in_trait)]
+pub trait Middleware {
+    /// Handle `request`, usually by passing it on to `next`
+    ///
+    /// The response may borrow from the middleware, from `next` or from the buffer of
+    /// `response`.
+    async fn handle<'h, N>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+        next: &'h mut N,
+    ) -> Result<ServerResponse<'h>, Error>
+    where
+        N: ResponseHandler;
+}
+
+/// A handler wrapped in a middleware, created by [`ResponseHandler::with_middleware`]
+#[derive(Debug)]
+pub struct Layered<M, H> {
+    middleware: M,
+    handler: H,
+}
+
+impl<M, H> Layered<M, H> {
+    /// Wrap `handler` in `middleware`
+    #[must_use]
+    pub fn new(middleware: M, handler: H) -> Self {
+        Self {
+            middleware,
+            handler,
+        }
+    }
+
+    /// The middleware and the handler it wraps
+    pub fn into_parts(self) -> (M, H) {
+        (self.middleware, self.handler)
+    }
+}
+
+impl<M, H> ResponseHandler for Layered<M, H>
+where
+    M: Middleware,
+    H: ResponseHandler,
+{
+    async fn respond<'h>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+    ) -> Result<ServerResponse<'h>, Error> {
+        self.middleware
+            .handle(request, response, &mut self.handler)
+            .await
+    }
+}
+
+/// A `text/plain` response carrying the status text and one more header
+fn plain_response(status_code: StatusCode, header: HttpHeader<'_>) -> HttpResponse<'_> {
+    let mut headers = Vec::new();
+    let _ = headers.push(HttpHeader::content_type(mime_types::TEXT));
+    let _ = headers.push(header);
+    HttpResponse {
+        status_code,
+        headers,
+        body: ResponseBody::Text(status_code.text()),
+    }
+}
+
+/// Logs the method, target and status of every request
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Logger;
+
+impl Middleware for Logger {
+    async fn handle<'h, N>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+        next: &'h mut N,
+    ) -> Result<ServerResponse<'h>, Error>
+    where
+        N: ResponseHandler,
+    {
+        let result = next.respond(request, response).await;
+        match &result {
+            Ok(response) => info!(
+                "{} {} -> {}",
+                request.method.as_str(),
+                request.path,
+                response.status_code.as_u16()
+            ),
+            Err(e) => warn!(
+                "{} {} failed: {:?}",
+                request.method.as_str(),
+                request.path,
+                e
+            ),
+        }
+        result
+    }
+}
+
+/// Requires `Authorization: Bearer <token>`, answering other requests with `401 Unauthorized`
+#[derive(Debug, Clone, Copy)]
+pub struct BearerAuth<'a> {
+    token: &'a str,
+}
+
+impl<'a> BearerAuth<'a> {
+    /// Accept requests carrying `token`
+    #[must_use]
+    pub const fn new(token: &'a str) -> Self {
+        Self { token }
+    }
+
+    fn is_authorized(&self, request: &HttpRequest<'_>) -> bool {
+        request
+            .get_header(headers::AUTHORIZATION)
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+            .is_some_and(|(_, token)| constant_time_eq(token.trim(), self.token))
+    }
+}
+
+/// Compare without returning early, so the time taken does not reveal the matching prefix
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+impl Middleware for BearerAuth<'_> {
+    async fn handle<'h, N>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+        next: &'h mut N,
+    ) -> Result<ServerResponse<'h>, Error>
+    where
+        N: ResponseHandler,
+    {
+        if self.is_authorized(request) {
+            next.respond(request, response).await
+        } else {
+            Ok(plain_response(
+                StatusCode::Unauthorized,
+                HttpHeader::new("WWW-Authenticate", "Bearer"),
+            )
+            .into())
+        }
+    }
+}
+
+/// Adds CORS headers to responses and answers preflight requests
+///
+/// Preflight requests (`OPTIONS` with `Access-Control-Request-Method`) are answered with
+/// `204 No Content` without reaching the handler, so put `Cors` outside of any
+/// authentication.
+#[derive(Debug, Clone, Copy)]
+pub struct Cors<'a> {
+    origin: &'a str,
+    methods: &'a str,
+    headers: &'a str,
+}
+
+impl<'a> Cors<'a> {
+    /// Allow requests from `origin`, e.g. `https://dashboard.example.com`
+    #[must_use]
+    pub const fn new(origin: &'a str) -> Self {
+        Self {
+            origin,
+            methods: "GET, POST, PUT, PATCH, DELETE, OPTIONS",
+            headers: "Content-Type, Authorization",
+        }
+    }
+
+    /// Allow requests from any origin
+    #[must_use]
+    pub const fn any() -> Self {
+        Self::new("*")
+    }
+
+    /// Set the methods announced to preflight requests
+    #[must_use]
+    pub const fn with_methods(mut self, methods: &'a str) -> Self {
+        self.methods = methods;
+        self
+    }
+
+    /// Set the request headers announced to preflight requests
+    #[must_use]
+    pub const fn with_headers(mut self, headers: &'a str) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+impl Middleware for Cors<'_> {
+    async fn handle<'h, N>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+        next: &'h mut N,
+    ) -> Result<ServerResponse<'h>, Error>
+    where
+        N: ResponseHandler,
+    {
+        let origin = HttpHeader::new("Access-Control-Allow-Origin", self.origin);
+        // Responses depend on the request's origin unless every origin is allowed
+        let vary = (self.origin != "*").then(|| HttpHeader::new("Vary", "Origin"));
+
+        if request.method == HttpMethod::OPTIONS
+            && request
+                .get_header("Access-Control-Request-Method")
+                .is_some()
+        {
+            let mut headers = Vec::new();
+            let _ = headers.push(origin);
+            let _ = headers.push(HttpHeader::new(
+                "Access-Control-Allow-Methods",
+                self.methods,
+            ));
+            let _ = headers.push(HttpHeader::new(
+                "Access-Control-Allow-Headers",
+                self.headers,
+            ));
+            if let Some(vary) = vary {
+                let _ = headers.push(vary);
+            }
+            return Ok(HttpResponse {
+                status_code: StatusCode::NoContent,
+                headers,
+                body: ResponseBody::Empty,
+            }
+            .into());
+        }
+
+        let mut response = next.respond(request, response).await?;
+        let _ = response.headers.push(origin);
+        if let Some(vary) = vary {
+            let _ = response.headers.push(vary);
+        }
+        Ok(response)
+    }
+}
+
+/// Admits at most `max_requests` per time window, answering others with `429 Too Many
+/// Requests`
+///
+/// The count is kept per handler: with [`crate::HttpServer::serve_pool`] every worker has its
+/// own limit.
+#[derive(Debug)]
+pub struct RateLimit {
+    max_requests: u32,
+    window: Duration,
+    window_start: Option<Instant>,
+    count: u32,
+    retry_after: String<20>,
+}
+
+impl RateLimit {
+    /// Admit `max_requests` every `window`
+    #[must_use]
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            window_start: None,
+            count: 0,
+            retry_after: String::new(),
+        }
+    }
+
+    /// Count a request arriving at `now`, returning the wait until the next window if it is
+    /// over the limit
+    fn admit(&mut self, now: Instant) -> Result<(), Duration> {
+        let start = match self.window_start {
+            Some(start) if now.saturating_duration_since(start) < self.window => start,
+            _ => {
+                self.count = 0;
+                self.window_start = Some(now);
+                now
+            }
+        };
+        if self.count < self.max_requests {
+            self.count += 1;
+            Ok(())
+        } else {
+            Err(self.window - now.saturating_duration_since(start))
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    async fn handle<'h, N>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+        next: &'h mut N,
+    ) -> Result<ServerResponse<'h>, Error>
+    where
+        N: ResponseHandler,
+    {
+        match self.admit(Instant::now()) {
+            Ok(()) => next.respond(request, response).await,
+            Err(wait) => {
+                self.retry_after.clear();
+                // Rounded up, so clients do not come back too early; a u64 always fits
+                let _ = write!(self.retry_after, "{}", wait.as_millis().div_ceil(1000));
+                Ok(plain_response(
+                    StatusCode::TooManyRequests,
+                    HttpHeader::new("Retry-After", &self.retry_after),
+                )
+                .into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::HttpHandler;
+
+    /// Answers every request with `200 OK` and counts them
+    struct Counter(u32);
+
+    impl HttpHandler for Counter {
+        async fn handle_request(
+            &mut self,
+            _request: &HttpRequest<'_>,
+        ) -> Result<HttpResponse<'_>, Error> {
+            self.0 += 1;
+            Ok(HttpResponse {
+                status_code: StatusCode::Ok,
+                headers: Vec::new(),
+                body: ResponseBody::Text("ok"),
+            })
+        }
+    }
+
+    fn request<'a>(method: HttpMethod, headers: &[HttpHeader<'a>]) -> HttpRequest<'a> {
+        HttpRequest {
+            method,
+            path: "/api",
+            version: "HTTP/1.1",
+            headers: Vec::from_slice(headers).unwrap(),
+            body: b"",
+        }
+    }
+
+    fn send<H: ResponseHandler>(
+        handler: &mut H,
+        request: &HttpRequest<'_>,
+    ) -> (
+        StatusCode,
+        std::vec::Vec<(std::string::String, std::string::String)>,
+    ) {
+        let mut scratch = [0; 64];
+        let builder = ResponseBuilder::new(&mut scratch);
+        let response = futures_lite::future::block_on(handler.respond(request, builder)).unwrap();
+        let headers = response
+            .headers
+            .iter()
+            .map(|h| (h.name.into(), h.value.into()))
+            .collect();
+        (response.status_code, headers)
+    }
+
+    fn header<'v>(
+        headers: &'v [(std::string::String, std::string::String)],
+        name: &str,
+    ) -> Option<&'v str> {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_chain() {
+        let mut handler = Counter(0)
+            .with_middleware(BearerAuth::new("s3cret"))
+            .with_middleware(Cors::new("https://app.example"))
+            .with_middleware(Logger);
+
+        // Authorized requests reach the handler and get the CORS headers
+        let ok = request(
+            HttpMethod::GET,
+            &[HttpHeader::new("Authorization", "bearer s3cret")],
+        );
+        let (status, headers) = send(&mut handler, &ok);
+        assert_eq!(status, StatusCode::Ok);
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Origin"),
+            Some("https://app.example")
+        );
+        assert_eq!(header(&headers, "Vary"), Some("Origin"));
+
+        // Others are stopped by the authentication, still with CORS headers
+        for auth in [&[][..], &[HttpHeader::new("Authorization", "Bearer s3cre")]] {
+            let (status, headers) = send(&mut handler, &request(HttpMethod::GET, auth));
+            assert_eq!(status, StatusCode::Unauthorized);
+            assert_eq!(header(&headers, "WWW-Authenticate"), Some("Bearer"));
+            assert!(header(&headers, "Access-Control-Allow-Origin").is_some());
+        }
+
+        // Preflight requests are answered by CORS
+        let preflight = request(
+            HttpMethod::OPTIONS,
+            &[HttpHeader::new("Access-Control-Request-Method", "PUT")],
+        );
+        let (status, headers) = send(&mut handler, &preflight);
+        assert_eq!(status, StatusCode::NoContent);
+        assert!(
+            header(&headers, "Access-Control-Allow-Methods").is_some_and(|m| m.contains("PUT"))
+        );
+
+        let (_, layered) = handler.into_parts();
+        let (_, layered) = layered.into_parts();
+        let (_, counter) = layered.into_parts();
+        assert_eq!(counter.0, 1);
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let mut limit = RateLimit::new(2, Duration::from_secs(10));
+        assert_eq!(limit.admit(Instant::from_secs(100)), Ok(()));
+        assert_eq!(limit.admit(Instant::from_secs(101)), Ok(()));
+        assert_eq!(
+            limit.admit(Instant::from_secs(104)),
+            Err(Duration::from_secs(6))
+        );
+        // A new window starts once the old one has passed
+        assert_eq!(limit.admit(Instant::from_secs(110)), Ok(()));
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{
+    buf::{Counter, SliceWriter},
+    error::Error,
+};
+use core::fmt::Write as _;
+use heapless::String;
+
+/// Maximum length of a multipart boundary (RFC 2046)
+pub const MAX_BOUNDARY_LEN: usize = 70;
+
+/// Boundary used by [`Multipart::new`]
+pub const DEFAULT_BOUNDARY: &str = "nanofish-form-boundary-7MA4YWxkTrZu0gW";
+
+/// Headers of one part of a `multipart/form-data` body
+#[derive(Debug, Clone, Copy)]
+pub struct Part<'a> {
+    /// Form field name
+    pub name: &'a str,
+    /// File name, for file uploads
+    pub filename: Option<&'a str>,
+    /// Media type of the part's content; omitted means `text/plain`
+    pub content_type: Option<&'a str>,
+}
+
+impl<'a> Part<'a> {
+    /// A plain form field
+    #[must_use]
+    pub const fn field(name: &'a str) -> Self {
+        Self {
+            name,
+            filename: None,
+            content_type: None,
+        }
+    }
+
+    /// A file upload
+    #[must_use]
+    pub const fn file(name: &'a str, filename: &'a str, content_type: &'a str) -> Self {
+        Self {
+            name,
+            filename: Some(filename),
+            content_type: Some(content_type),
+        }
+    }
+
+    /// Write the delimiter and headers that precede the part's content into `out`
+    ///
+    /// Together with [`Part::write_tail`] and [`write_end`] this frames content streamed
+    /// from its source; [`encoded_len`] gives the matching `Content-Length`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidBoundary`] for a boundary [`Multipart::with_boundary`] would
+    /// reject, [`Error::InvalidForm`] if the content type contains control characters and
+    /// [`Error::FormTooLarge`] if the headers do not fit into `out`.
+    pub fn write_head(&self, boundary: &str, out: &mut [u8]) -> Result<usize, Error> {
+        check_boundary(boundary)?;
+        self.check()?;
+        let mut len = 0;
+        let mut cursor = SliceWriter {
+            buffer: out,
+            len: &mut len,
+        };
+        self.fmt_head(&mut cursor, boundary)
+            .map_err(|_| Error::FormTooLarge)?;
+        Ok(len)
+    }
+
+    /// Write the line break that ends the part's content into `out`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::FormTooLarge`] if `out` is shorter than two bytes.
+    pub fn write_tail(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let mut len = 0;
+        SliceWriter {
+            buffer: out,
+            len: &mut len,
+        }
+        .push(b"\r\n")
+        .map_err(|_| Error::FormTooLarge)?;
+        Ok(len)
+    }
+
+    /// Reject a content type that would break out of its header line
+    fn check(&self) -> Result<(), Error> {
+        match self.content_type {
+            Some(content_type) if content_type.bytes().any(|b| b.is_ascii_control()) => {
+                Err(Error::InvalidForm)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn fmt_head(&self, out: &mut impl core::fmt::Write, boundary: &str) -> core::fmt::Result {
+        write!(
+            out,
+            "--{boundary}\r\nContent-Disposition: form-data; name=\""
+        )?;
+        write_quoted(out, self.name)?;
+        out.write_str("\"")?;
+        if let Some(filename) = self.filename {
+            out.write_str("; filename=\"")?;
+            write_quoted(out, filename)?;
+            out.write_str("\"")?;
+        }
+        out.write_str("\r\n")?;
+        if let Some(content_type) = self.content_type {
+            write!(out, "Content-Type: {content_type}\r\n")?;
+        }
+        out.write_str("\r\n")
+    }
+}
+
+/// Write a quoted-string value, percent-encoding `"`, CR and LF as browsers do
+fn write_quoted(out: &mut impl core::fmt::Write, value: &str) -> core::fmt::Result {
+    for c in value.chars() {
+        match c {
+            '"' => out.write_str("%22")?,
+            '\r' => out.write_str("%0D")?,
+            '\n' => out.write_str("%0A")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Size of a `multipart/form-data` body with the given parts and content lengths
+///
+/// Use this to send `Content-Length` when the parts are streamed from their sources with
+/// [`Part::write_head`], [`Part::write_tail`] and [`write_end`] instead of being assembled
+/// with [`Multipart`].
+#[must_use]
+pub fn encoded_len(boundary: &str, parts: &[(Part<'_>, usize)]) -> usize {
+    let mut counter = Counter(0);
+    for (part, len) in parts {
+        // Writing to a counter cannot fail
+        let _ = part.fmt_head(&mut counter, boundary);
+        counter.0 += len + 2;
+    }
+    counter.0 + boundary.len() + 6
+}
+
+/// Write the closing delimiter of a streamed body into `out`
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidBoundary`] for an invalid boundary and [`Error::FormTooLarge`] if
+/// the delimiter does not fit into `out`.
+pub fn write_end(boundary: &str, out: &mut [u8]) -> Result<usize, Error> {
+    check_boundary(boundary)?;
+    let mut len = 0;
+    let mut cursor = SliceWriter {
+        buffer: out,
+        len: &mut len,
+    };
+    write!(cursor, "--{boundary}--\r\n").map_err(|_| Error::FormTooLarge)?;
+    Ok(len)
+}
+
+/// Check a boundary against RFC 2046
+fn check_boundary(boundary: &str) -> Result<(), Error> {
+    // Spaces are allowed by RFC 2046 but would need quoting in Content-Type
+    let valid = (1..=MAX_BOUNDARY_LEN).contains(&boundary.len())
+        && boundary
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"'()+_,-./:=?".contains(&b));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidBoundary)
+    }
+}
+
+/// Builder for a `multipart/form-data` body in a fixed buffer
+///
+/// Parts are written as they are added; [`Multipart::finish`] closes the body. Send it with
+/// [`crate::HttpClient::post_multipart`], or pass [`Multipart::content_type`] and the finished
+/// body to [`crate::HttpClient::request`] yourself.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use nanofish::Multipart;
+///
+/// let mut buffer = [0u8; 4096];
+/// let mut form = Multipart::new(&mut buffer);
+/// form.text("device", "sensor-7")?
+///     .file("log", "boot.log", "text/plain", log_bytes)?;
+/// let (response, _) = client.post_multipart(url, &[], form, &mut response_buffer).await?;
+/// ```
+pub struct Multipart<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+    boundary: String<MAX_BOUNDARY_LEN>,
+}
+
+impl<'b> Multipart<'b> {
+    /// Start an empty form in `buffer` with [`DEFAULT_BOUNDARY`]
+    #[must_use]
+    pub fn new(buffer: &'b mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            boundary: String::try_from(DEFAULT_BOUNDARY).unwrap_or_default(),
+        }
+    }
+
+    /// Start an empty form in `buffer` with a custom boundary
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidBoundary`] if `boundary` is empty, longer than
+    /// [`MAX_BOUNDARY_LEN`] or contains characters RFC 2046 does not allow.
+    pub fn with_boundary(buffer: &'b mut [u8], boundary: &str) -> Result<Self, Error> {
+        check_boundary(boundary)?;
+        Ok(Self {
+            buffer,
+            len: 0,
+            boundary: String::try_from(boundary).map_err(|_| Error::InvalidBoundary)?,
+        })
+    }
+
+    /// The boundary between parts
+    #[must_use]
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The `Content-Type` header value for this form, including the boundary
+    #[must_use]
+    pub fn content_type(&self) -> String<{ MAX_BOUNDARY_LEN + 30 }> {
+        let mut value = String::new();
+        // Sized for the longest boundary
+        let _ = write!(value, "multipart/form-data; boundary={}", self.boundary);
+        value
+    }
+
+    /// Number of bytes written so far
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether no part has been added yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a part with the given headers and content
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::FormTooLarge`] if the part does not fit into the buffer (the form is
+    /// left as it was), [`Error::InvalidBoundary`] if `data` contains the boundary and
+    /// [`Error::InvalidForm`] if the content type contains control characters.
+    pub fn add(&mut self, part: &Part<'_>, data: &[u8]) -> Result<&mut Self, Error> {
+        part.check()?;
+        let delimiter_len = self.boundary.len() + 2;
+        if data.len() >= delimiter_len
+            && data
+                .windows(delimiter_len)
+                .any(|w| w.starts_with(b"--") && &w[2..] == self.boundary.as_bytes())
+        {
+            return Err(Error::InvalidBoundary);
+        }
+
+        let start = self.len;
+        let result = self.write_part(part, data);
+        if result.is_err() {
+            self.len = start;
+        }
+        result.map(|()| self)
+    }
+
+    fn write_part(&mut self, part: &Part<'_>, data: &[u8]) -> Result<(), Error> {
+        let mut cursor = SliceWriter {
+            buffer: &mut *self.buffer,
+            len: &mut self.len,
+        };
+        part.fmt_head(&mut cursor, &self.boundary)
+            .map_err(|_| Error::FormTooLarge)?;
+        cursor
+            .push(data)
+            .and_then(|()| cursor.push(b"\r\n"))
+            .map_err(|_| Error::FormTooLarge)
+    }
+
+    /// Add a text field
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Multipart::add`].
+    pub fn text(&mut self, name: &str, value: &str) -> Result<&mut Self, Error> {
+        self.add(&Part::field(name), value.as_bytes())
+    }
+
+    /// Add a file
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Multipart::add`].
+    pub fn file(
+        &mut self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Result<&mut Self, Error> {
+        self.add(&Part::file(name, filename, content_type), data)
+    }
+
+    /// Write the closing delimiter and return the complete body
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::FormTooLarge`] if the closing delimiter does not fit.
+    pub fn finish(self) -> Result<&'b [u8], Error> {
+        let Self {
+            buffer,
+            mut len,
+            boundary,
+        } = self;
+        let mut cursor = SliceWriter {
+            buffer: &mut *buffer,
+            len: &mut len,
+        };
+        write!(cursor, "--{boundary}--\r\n").map_err(|_| Error::FormTooLarge)?;
+        Ok(&buffer[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_form() {
+        let mut buffer = [0u8; 512];
+        let mut form = Multipart::with_boundary(&mut buffer, "XyZ").unwrap();
+        form.text("device", "sensor-7")
+            .unwrap()
+            .file("log", "boot \"1\".log", "text/plain", b"line 1\nline 2")
+            .unwrap();
+        assert_eq!(
+            form.content_type().as_str(),
+            "multipart/form-data; boundary=XyZ"
+        );
+
+        let body = form.finish().unwrap();
+        let expected = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"device\"\r\n\r\n\
+            sensor-7\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"log\"; filename=\"boot %221%22.log\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\nline 2\r\n\
+            --XyZ--\r\n";
+        assert_eq!(core::str::from_utf8(body).unwrap(), expected);
+
+        let parts = [
+            (Part::field("device"), 8),
+            (Part::file("log", "boot \"1\".log", "text/plain"), 13),
+        ];
+        assert_eq!(encoded_len("XyZ", &parts), expected.len());
+    }
+
+    #[test]
+    fn test_overflow_keeps_form_intact() {
+        let mut buffer = [0u8; 96];
+        let mut form = Multipart::with_boundary(&mut buffer, "b").unwrap();
+        form.text("a", "1").unwrap();
+        let len = form.len();
+
+        assert!(matches!(
+            form.file("f", "big.bin", "application/octet-stream", &[0u8; 64]),
+            Err(Error::FormTooLarge)
+        ));
+        assert_eq!(form.len(), len);
+        assert!(form.finish().unwrap().ends_with(b"1\r\n--b--\r\n"));
+    }
+
+    #[test]
+    fn test_invalid_boundary() {
+        let mut buffer = [0u8; 128];
+        assert!(Multipart::with_boundary(&mut buffer, "").is_err());
+        assert!(Multipart::with_boundary(&mut buffer, "a\"b").is_err());
+        assert!(Multipart::with_boundary(&mut buffer, &"x".repeat(71)).is_err());
+
+        let mut form = Multipart::with_boundary(&mut buffer, "sep").unwrap();
+        assert!(matches!(
+            form.text("a", "x\r\n--sep\r\ny"),
+            Err(Error::InvalidBoundary)
+        ));
+        assert!(form.is_empty());
+        assert!(matches!(
+            write_end("a b", &mut [0u8; 16]),
+            Err(Error::InvalidBoundary)
+        ));
+    }
+
+    #[test]
+    fn test_content_type_injection() {
+        let part = Part::file("f", "a.txt", "text/plain\r\nX-Injected: 1");
+        let mut buffer = [0u8; 256];
+        assert!(matches!(
+            part.write_head("sep", &mut buffer),
+            Err(Error::InvalidForm)
+        ));
+
+        let mut form = Multipart::with_boundary(&mut buffer, "sep").unwrap();
+        assert!(matches!(form.add(&part, b"x"), Err(Error::InvalidForm)));
+        assert!(form.is_empty());
+    }
+
+    #[test]
+    fn test_streamed_framing() {
+        let parts = [
+            (Part::field("device"), &b"sensor-7"[..]),
+            (
+                Part::file("log", "boot.log", "text/plain"),
+                b"line 1\nline 2",
+            ),
+        ];
+
+        let mut streamed = std::vec::Vec::new();
+        let mut chunk = [0u8; 128];
+        for (part, data) in &parts {
+            let len = part.write_head("XyZ", &mut chunk).unwrap();
+            streamed.extend_from_slice(&chunk[..len]);
+            streamed.extend_from_slice(data);
+            let len = part.write_tail(&mut chunk).unwrap();
+            streamed.extend_from_slice(&chunk[..len]);
+        }
+        let len = write_end("XyZ", &mut chunk).unwrap();
+        streamed.extend_from_slice(&chunk[..len]);
+
+        let mut buffer = [0u8; 512];
+        let mut form = Multipart::with_boundary(&mut buffer, "XyZ").unwrap();
+        for (part, data) in &parts {
+            form.add(part, data).unwrap();
+        }
+        assert_eq!(streamed, form.finish().unwrap());
+
+        let lengths = parts.map(|(part, data)| (part, data.len()));
+        assert_eq!(encoded_len("XyZ", &lengths), streamed.len());
+        assert!(matches!(
+            parts[1].0.write_head("XyZ", &mut chunk[..8]),
+            Err(Error::FormTooLarge)
+        ));
+    }
+}
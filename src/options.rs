@@ -1,3 +1,4 @@
+use crate::proxy::Proxy;
 use embassy_time::Duration;
 use embassy_time_05 as embassy_time;
 
@@ -11,6 +12,36 @@ pub struct HttpClientOptions {
     pub retry_delay: Duration,
     /// Delay after closing a socket before proceeding
     pub socket_close_delay: Duration,
+    /// Timeout for resolving the host name via DNS
+    pub dns_timeout: Duration,
+    /// Timeout for establishing the TCP connection
+    pub connect_timeout: Duration,
+    /// Timeout for completing the TLS handshake
+    pub tls_handshake_timeout: Duration,
+    /// Timeout for receiving the first response byte after the request was sent
+    pub first_byte_timeout: Duration,
+    /// Overall deadline for a request, from DNS lookup to the last byte read
+    ///
+    /// This bounds the total time spent including retries and close delays.
+    pub request_timeout: Duration,
+    /// Return [`crate::Error::Status`] for responses with a non-2xx status code
+    ///
+    /// When disabled (the default) every parsed response is returned as `Ok`. A
+    /// `304 Not Modified` never counts as an error.
+    pub error_on_status: bool,
+    /// HTTP or SOCKS5 proxy that all requests are sent through
+    ///
+    /// The proxy is `'static` so that the options stay free of lifetimes, which suits
+    /// configuration compiled into the firmware. A proxy read at runtime, borrowing its host
+    /// or credentials from a buffer, is set with [`crate::HttpClient::with_proxy`] instead.
+    pub proxy: Option<Proxy<'static>>,
+    /// Send `Accept-Encoding: gzip, deflate` so that servers may compress responses
+    ///
+    /// On by default with the `compression` feature, and without effect when it is disabled.
+    /// Compressed bodies are decoded into the part of the response buffer the response did
+    /// not use, so [`crate::HttpClient::request`] and the methods built on it return plain
+    /// bodies either way; the buffer has to leave room for the decoded body.
+    pub accept_compressed: bool,
 }
 
 impl Default for HttpClientOptions {
@@ -20,6 +51,14 @@ impl Default for HttpClientOptions {
             socket_timeout: Duration::from_secs(60),
             retry_delay: Duration::from_millis(200),
             socket_close_delay: Duration::from_millis(100),
+            dns_timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(10),
+            tls_handshake_timeout: Duration::from_secs(20),
+            first_byte_timeout: Duration::from_secs(30),
+            request_timeout: Duration::from_secs(120),
+            error_on_status: false,
+            proxy: None,
+            accept_compressed: cfg!(feature = "compression"),
         }
     }
 }
@@ -27,6 +66,7 @@ impl Default for HttpClientOptions {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy::HttpProxy;
     use embassy_time::Duration;
 
     #[test]
@@ -36,6 +76,14 @@ mod tests {
         assert_eq!(opts.socket_timeout, Duration::from_secs(60));
         assert_eq!(opts.retry_delay, Duration::from_millis(200));
         assert_eq!(opts.socket_close_delay, Duration::from_millis(100));
+        assert_eq!(opts.dns_timeout, Duration::from_secs(10));
+        assert_eq!(opts.connect_timeout, Duration::from_secs(10));
+        assert_eq!(opts.tls_handshake_timeout, Duration::from_secs(20));
+        assert_eq!(opts.first_byte_timeout, Duration::from_secs(30));
+        assert_eq!(opts.request_timeout, Duration::from_secs(120));
+        assert!(!opts.error_on_status);
+        assert!(opts.proxy.is_none());
+        assert_eq!(opts.accept_compressed, cfg!(feature = "compression"));
     }
 
     #[test]
@@ -45,10 +93,26 @@ mod tests {
             socket_timeout: Duration::from_secs(10),
             retry_delay: Duration::from_millis(50),
             socket_close_delay: Duration::from_millis(20),
+            dns_timeout: Duration::from_secs(1),
+            connect_timeout: Duration::from_secs(2),
+            tls_handshake_timeout: Duration::from_secs(3),
+            first_byte_timeout: Duration::from_secs(4),
+            request_timeout: Duration::from_secs(15),
+            error_on_status: true,
+            proxy: Some(Proxy::Http(HttpProxy::new("proxy.local", 3128))),
+            accept_compressed: false,
         };
         assert_eq!(opts.max_retries, 2);
         assert_eq!(opts.socket_timeout, Duration::from_secs(10));
         assert_eq!(opts.retry_delay, Duration::from_millis(50));
         assert_eq!(opts.socket_close_delay, Duration::from_millis(20));
+        assert_eq!(opts.dns_timeout, Duration::from_secs(1));
+        assert_eq!(opts.connect_timeout, Duration::from_secs(2));
+        assert_eq!(opts.tls_handshake_timeout, Duration::from_secs(3));
+        assert_eq!(opts.first_byte_timeout, Duration::from_secs(4));
+        assert_eq!(opts.request_timeout, Duration::from_secs(15));
+        assert!(opts.error_on_status);
+        assert!(matches!(opts.proxy, Some(Proxy::Http(p)) if p.port == 3128));
+        assert!(!opts.accept_compressed);
     }
 }
@@ -0,0 +1,324 @@
+use crate::{
+    error::Error, request::find_double_crlf, response::parse_status_line, socks::Socks5Proxy,
+};
+use core::fmt::Write as _;
+use embedded_io_async::{Read as EmbeddedRead, Write as EmbeddedWrite};
+use embedded_io_async_07 as embedded_io_async;
+
+/// Maximum size of a `CONNECT` request sent to an HTTP proxy
+const CONNECT_REQUEST_SIZE: usize = 512;
+
+/// Username and password for proxy authentication
+#[derive(Clone, Copy, Debug)]
+pub struct ProxyCredentials<'a> {
+    /// The proxy user name
+    pub username: &'a str,
+    /// The proxy password
+    pub password: &'a str,
+}
+
+/// HTTP proxy configuration
+///
+/// Plain HTTP requests are sent to the proxy using absolute-form request targets
+/// (`GET http://host/path HTTP/1.1`). HTTPS requests first open a `CONNECT host:port`
+/// tunnel through the proxy and then run the TLS handshake inside it.
+#[derive(Clone, Copy, Debug)]
+pub struct HttpProxy<'a> {
+    /// Host name or IP address of the proxy
+    pub host: &'a str,
+    /// TCP port of the proxy
+    pub port: u16,
+    /// Optional credentials sent as `Proxy-Authorization: Basic ...`
+    pub credentials: Option<ProxyCredentials<'a>>,
+}
+
+impl<'a> HttpProxy<'a> {
+    /// Create a proxy configuration without authentication
+    #[must_use]
+    pub const fn new(host: &'a str, port: u16) -> Self {
+        Self {
+            host,
+            port,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate against the proxy with Basic credentials
+    #[must_use]
+    pub const fn with_credentials(mut self, username: &'a str, password: &'a str) -> Self {
+        self.credentials = Some(ProxyCredentials { username, password });
+        self
+    }
+}
+
+/// Proxy that the client routes its connections through
+#[derive(Clone, Copy, Debug)]
+pub enum Proxy<'a> {
+    /// HTTP proxy, using absolute-form requests and `CONNECT` tunnels for HTTPS
+    Http(HttpProxy<'a>),
+    /// SOCKS5 proxy, used as a tunnel for both HTTP and HTTPS
+    Socks5(Socks5Proxy<'a>),
+}
+
+impl<'a> From<HttpProxy<'a>> for Proxy<'a> {
+    fn from(proxy: HttpProxy<'a>) -> Self {
+        Proxy::Http(proxy)
+    }
+}
+
+impl<'a> From<Socks5Proxy<'a>> for Proxy<'a> {
+    fn from(proxy: Socks5Proxy<'a>) -> Self {
+        Proxy::Socks5(proxy)
+    }
+}
+
+/// Write `username:password` as standard base64, as used by Basic authentication
+pub(crate) fn write_basic_credentials(
+    out: &mut impl core::fmt::Write,
+    credentials: &ProxyCredentials<'_>,
+) -> core::fmt::Result {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut bytes = credentials
+        .username
+        .bytes()
+        .chain(core::iter::once(b':'))
+        .chain(credentials.password.bytes());
+
+    loop {
+        let Some(b0) = bytes.next() else {
+            return Ok(());
+        };
+        let b1 = bytes.next();
+        let b2 = bytes.next();
+
+        let n =
+            (u32::from(b0) << 16) | (u32::from(b1.unwrap_or(0)) << 8) | u32::from(b2.unwrap_or(0));
+        let sextet = |shift: u32| char::from(ALPHABET[((n >> shift) & 0x3f) as usize]);
+
+        out.write_char(sextet(18))?;
+        out.write_char(sextet(12))?;
+        out.write_char(if b1.is_some() { sextet(6) } else { '=' })?;
+        out.write_char(if b2.is_some() { sextet(0) } else { '=' })?;
+
+        if b2.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// Establish a `CONNECT` tunnel to `host:port` through an HTTP proxy
+///
+/// `conn` must already be connected to the proxy. `buffer` is used as scratch space for
+/// the proxy's response head. On success the connection carries raw bytes to the target.
+pub(crate) async fn open_connect_tunnel<C>(
+    conn: &mut C,
+    proxy: &HttpProxy<'_>,
+    host: &str,
+    port: u16,
+    buffer: &mut [u8],
+) -> Result<(), Error>
+where
+    C: EmbeddedRead + EmbeddedWrite,
+    Error: From<C::Error>,
+{
+    // IPv6 literals are bracketed in the authority, as in a URL
+    let (open, close) = if host.contains(':') {
+        ("[", "]")
+    } else {
+        ("", "")
+    };
+    let mut request = heapless::String::<CONNECT_REQUEST_SIZE>::new();
+    write!(
+        request,
+        "CONNECT {open}{host}{close}:{port} HTTP/1.1\r\nHost: {open}{host}{close}:{port}\r\n"
+    )
+    .map_err(|_| Error::RequestTooLarge)?;
+    if let Some(credentials) = &proxy.credentials {
+        request
+            .push_str("Proxy-Authorization: Basic ")
+            .map_err(|_| Error::RequestTooLarge)?;
+        write_basic_credentials(&mut request, credentials).map_err(|_| Error::RequestTooLarge)?;
+        request
+            .push_str("\r\n")
+            .map_err(|_| Error::RequestTooLarge)?;
+    }
+    request
+        .push_str("\r\n")
+        .map_err(|_| Error::RequestTooLarge)?;
+
+    conn.write_all(request.as_bytes()).await?;
+    conn.flush().await?;
+
+    let mut total_read = 0;
+    let head_end = loop {
+        if let Some(end) = find_double_crlf(&buffer[..total_read]) {
+            break end;
+        }
+        if total_read == buffer.len() {
+            return Err(Error::HeaderTooLong);
+        }
+        match conn.read(&mut buffer[total_read..]).await? {
+            0 => return Err(Error::PrematureEof),
+            n => total_read += n,
+        }
+    };
+
+    let head = core::str::from_utf8(&buffer[..head_end])
+        .map_err(|_| Error::InvalidResponse("Invalid proxy response encoding"))?;
+    let status_line = head.split("\r\n").next().unwrap_or_default();
+    let status = parse_status_line(status_line)?;
+
+    if !status.is_success() {
+        return Err(Error::ProxyRejected(status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatusCode;
+    use embassy_net_08::tcp;
+
+    /// In-memory connection that replays canned proxy output and records what was sent
+    struct MockStream<'a> {
+        input: &'a [u8],
+        output: std::vec::Vec<u8>,
+    }
+
+    impl embedded_io_async::ErrorType for MockStream<'_> {
+        type Error = tcp::Error;
+    }
+
+    impl EmbeddedRead for MockStream<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            // Hand out at most a few bytes per call to exercise partial reads
+            let n = buf.len().min(self.input.len()).min(7);
+            buf[..n].copy_from_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            Ok(n)
+        }
+    }
+
+    impl EmbeddedWrite for MockStream<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn basic(username: &str, password: &str) -> std::string::String {
+        let mut out = std::string::String::new();
+        write_basic_credentials(&mut out, &ProxyCredentials { username, password }).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_basic_credentials_encoding() {
+        assert_eq!(
+            basic("Aladdin", "open sesame"),
+            "QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(basic("user", "pass"), "dXNlcjpwYXNz");
+        assert_eq!(basic("ab", ""), "YWI6");
+        assert_eq!(basic("a", ""), "YTo=");
+    }
+
+    #[test]
+    fn test_connect_tunnel_success() {
+        let proxy = HttpProxy::new("proxy.local", 3128).with_credentials("user", "pass");
+        let mut conn = MockStream {
+            input: b"HTTP/1.1 200 Connection established\r\nProxy-Agent: test\r\n\r\n",
+            output: std::vec::Vec::new(),
+        };
+        let mut buffer = [0u8; 128];
+
+        futures_lite::future::block_on(open_connect_tunnel(
+            &mut conn,
+            &proxy,
+            "example.com",
+            443,
+            &mut buffer,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            core::str::from_utf8(&conn.output).unwrap(),
+            "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\
+             Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_connect_tunnel_ipv6() {
+        let proxy = HttpProxy::new("proxy.local", 3128);
+        let mut conn = MockStream {
+            input: b"HTTP/1.1 200 Connection established\r\n\r\n",
+            output: std::vec::Vec::new(),
+        };
+        let mut buffer = [0u8; 128];
+
+        futures_lite::future::block_on(open_connect_tunnel(
+            &mut conn,
+            &proxy,
+            "2001:db8::1",
+            443,
+            &mut buffer,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            core::str::from_utf8(&conn.output).unwrap(),
+            "CONNECT [2001:db8::1]:443 HTTP/1.1\r\nHost: [2001:db8::1]:443\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_connect_tunnel_rejected() {
+        let proxy = HttpProxy::new("proxy.local", 3128);
+        let mut conn = MockStream {
+            input: b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n",
+            output: std::vec::Vec::new(),
+        };
+        let mut buffer = [0u8; 128];
+
+        let result = futures_lite::future::block_on(open_connect_tunnel(
+            &mut conn,
+            &proxy,
+            "example.com",
+            443,
+            &mut buffer,
+        ));
+        assert!(matches!(
+            result,
+            Err(Error::ProxyRejected(
+                StatusCode::ProxyAuthenticationRequired
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_connect_tunnel_truncated_response() {
+        let proxy = HttpProxy::new("proxy.local", 3128);
+        let mut conn = MockStream {
+            input: b"HTTP/1.1 200 OK\r\n",
+            output: std::vec::Vec::new(),
+        };
+        let mut buffer = [0u8; 128];
+
+        let result = futures_lite::future::block_on(open_connect_tunnel(
+            &mut conn,
+            &proxy,
+            "example.com",
+            443,
+            &mut buffer,
+        ));
+        assert!(matches!(result, Err(Error::PrematureEof)));
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{error::Error, header::HttpHeader, method::HttpMethod};
+use crate::{
+    error::Error,
+    form::{FormPairs, percent_decode},
+    header::{HttpHeader, has_token, headers},
+    method::HttpMethod,
+};
 use heapless::Vec;
 
 /// Maximum number of headers allowed in a request
@@ -9,7 +14,9 @@ pub const MAX_HEADERS: usize = 16;
 pub struct HttpRequest<'a> {
     /// HTTP method
     pub method: HttpMethod,
-    /// Request path
+    /// Request target as sent, including any query string and percent-escapes
+    ///
+    /// See [`HttpRequest::path_only`], [`HttpRequest::query`] and [`HttpRequest::decode_path`].
     pub path: &'a str,
     /// HTTP version (e.g., "HTTP/1.1")
     pub version: &'a str,
@@ -19,8 +26,22 @@ pub struct HttpRequest<'a> {
     pub body: &'a [u8],
 }
 
+/// Percent-decode a request path or path segment into `scratch`
+///
+/// `+` is kept as it is. Paths without escapes are returned as they are.
+pub(crate) fn decode_path<'s>(raw: &'s str, scratch: &'s mut [u8]) -> Result<&'s str, Error> {
+    let path = percent_decode(raw.as_bytes(), scratch, false).map_err(|e| match e {
+        Error::InvalidForm => Error::InvalidPath,
+        e => e,
+    })?;
+    if path.split('/').any(|segment| segment == "..") {
+        return Err(Error::InvalidPath);
+    }
+    Ok(path)
+}
+
 /// Find the position of the double CRLF sequence that separates headers from body
-fn find_double_crlf(data: &[u8]) -> Option<usize> {
+pub(crate) fn find_double_crlf(data: &[u8]) -> Option<usize> {
     const DOUBLE_CRLF: &[u8] = b"\r\n\r\n";
     (0..data.len().saturating_sub(3)).find(|&i| &data[i..i + 4] == DOUBLE_CRLF)
 }
@@ -39,18 +60,12 @@ impl<'a> HttpRequest<'a> {
         let mut lines = headers_str.lines();
 
         // Parse request line
-        let request_line = lines
-            .next()
-            .ok_or(Error::InvalidResponse("Missing request line"))?;
+        let request_line = lines.next().ok_or(Error::MalformedRequestLine)?;
         let mut parts = request_line.split_whitespace();
 
-        let method_str = parts
-            .next()
-            .ok_or(Error::InvalidResponse("Missing method"))?;
-        let path = parts.next().ok_or(Error::InvalidResponse("Missing path"))?;
-        let version = parts
-            .next()
-            .ok_or(Error::InvalidResponse("Missing version"))?;
+        let method_str = parts.next().ok_or(Error::MalformedRequestLine)?;
+        let path = parts.next().ok_or(Error::MalformedRequestLine)?;
+        let version = parts.next().ok_or(Error::MalformedRequestLine)?;
 
         let method = HttpMethod::try_from(method_str)
             .map_err(|_| Error::InvalidResponse("Unknown HTTP method"))?;
@@ -67,9 +82,7 @@ impl<'a> HttpRequest<'a> {
                 let value = line[colon_pos + 1..].trim();
 
                 let header = HttpHeader::new(name, value);
-                headers
-                    .push(header)
-                    .map_err(|_| Error::InvalidResponse("Too many headers"))?;
+                headers.push(header).map_err(|_| Error::TooManyHeaders)?;
             }
         }
 
@@ -81,6 +94,109 @@ impl<'a> HttpRequest<'a> {
             body,
         })
     }
+
+    /// Get a header value by name (case-insensitive)
+    #[must_use]
+    pub fn get_header(&self, name: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value)
+    }
+
+    /// Check whether the client wants the connection kept open after the response
+    ///
+    /// HTTP/1.1 connections persist unless the client sends `Connection: close`; HTTP/1.0
+    /// clients have to ask for `Connection: keep-alive`.
+    #[must_use]
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.get_header(headers::CONNECTION);
+        if self.version.eq_ignore_ascii_case("HTTP/1.1") {
+            !connection.is_some_and(|value| has_token(value, "close"))
+        } else {
+            connection.is_some_and(|value| has_token(value, "keep-alive"))
+        }
+    }
+
+    /// Iterate over the pairs of an `application/x-www-form-urlencoded` body
+    ///
+    /// The pairs are still encoded; see [`crate::form::decode`] and [`FormPairs::get`].
+    #[must_use]
+    pub fn form(&self) -> FormPairs<'a> {
+        FormPairs::new(self.body)
+    }
+
+    /// The path of the request target, without the query string
+    ///
+    /// The path is still percent-encoded; see [`HttpRequest::decode_path`].
+    #[must_use]
+    pub fn path_only(&self) -> &'a str {
+        self.path
+            .split_once('?')
+            .map_or(self.path, |(path, _)| path)
+    }
+
+    /// The query string of the request target, without the `?`
+    #[must_use]
+    pub fn query(&self) -> Option<&'a str> {
+        self.path.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Iterate over the query parameters
+    ///
+    /// The pairs are still encoded; decode them with [`FormPairs::next_decoded`] or look one
+    /// up with [`FormPairs::get`]. A request without a query string has no parameters.
+    #[must_use]
+    pub fn query_params(&self) -> FormPairs<'a> {
+        FormPairs::new(self.query().unwrap_or_default().as_bytes())
+    }
+
+    /// Percent-decode the path of the request target into `scratch`
+    ///
+    /// The query string is left out and `+` is kept as it is. Paths without escapes are
+    /// returned as they are; otherwise `scratch` never needs to be longer than the path.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidPath`] for malformed escapes, invalid UTF-8 and paths with a
+    /// `..` segment, even an encoded one, and [`Error::FormTooLarge`] if the decoded path does
+    /// not fit into `scratch`.
+    pub fn decode_path<'s>(&self, scratch: &'s mut [u8]) -> Result<&'s str, Error>
+    where
+        'a: 's,
+    {
+        decode_path(self.path_only(), scratch)
+    }
+
+    /// Decode the body with `codec`, e.g. `request.parse_body(Cbor)`
+    ///
+    /// The value may borrow from the request buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BodyDecode`] if the request has a different `Content-Type` or the
+    /// body is not a valid encoding of `T`.
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    pub fn parse_body<C, T>(&self, _codec: C) -> Result<T, Error>
+    where
+        C: crate::codec::BodyCodec,
+        T: crate::codec::DecodeBody<'a, C>,
+    {
+        if !crate::codec::has_content_type(&self.headers, C::CONTENT_TYPE) {
+            return Err(Error::BodyDecode("Unexpected Content-Type"));
+        }
+        T::decode_body(self.body)
+    }
+
+    /// Check whether the client's `Accept` header allows responses in `codec`'s format
+    ///
+    /// A request without `Accept` accepts anything.
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    #[must_use]
+    pub fn accepts<C: crate::codec::BodyCodec>(&self, _codec: C) -> bool {
+        self.get_header("Accept")
+            .is_none_or(|accept| crate::codec::accepts(accept, C::CONTENT_TYPE))
+    }
 }
 
 impl<'a> TryFrom<&'a [u8]> for HttpRequest<'a> {
@@ -107,6 +223,49 @@ mod tests {
     use super::*;
     use crate::HttpMethod;
 
+    #[test]
+    fn test_query_and_path() {
+        let request = HttpRequest::parse_from(
+            "GET /files/a%20b+c.txt?name=J%C3%BCrgen&x HTTP/1.1\r\n",
+            b"",
+        )
+        .unwrap();
+        assert_eq!(request.path_only(), "/files/a%20b+c.txt");
+        assert_eq!(request.query(), Some("name=J%C3%BCrgen&x"));
+
+        let mut scratch = [0u8; 32];
+        assert_eq!(
+            request.query_params().get("name", &mut scratch).unwrap(),
+            Some("Jürgen")
+        );
+        assert_eq!(request.query_params().count(), 2);
+        assert_eq!(
+            request.decode_path(&mut scratch).unwrap(),
+            "/files/a b+c.txt"
+        );
+
+        let request = HttpRequest::parse_from("GET /status HTTP/1.1\r\n", b"").unwrap();
+        assert_eq!(request.path_only(), "/status");
+        assert_eq!(request.query(), None);
+        assert_eq!(request.query_params().count(), 0);
+        assert_eq!(request.decode_path(&mut []).unwrap(), "/status");
+
+        for path in [
+            "/a/../etc",
+            "/a/%2e%2E/etc",
+            "/a%2F..%2Fetc",
+            "/..",
+            "/bad%zz",
+            "/%FF",
+        ] {
+            assert!(matches!(
+                decode_path(path, &mut scratch),
+                Err(Error::InvalidPath)
+            ));
+        }
+        assert!(decode_path("/a..b/.x", &mut scratch).is_ok());
+    }
+
     #[test]
     fn test_parse_request_get() {
         let request_str =
@@ -266,4 +425,55 @@ mod tests {
         let result = HttpRequest::try_from(buffer.as_slice());
         assert!(result.is_err());
     }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_parse_cbor_body() {
+        use crate::codec::Cbor;
+
+        let request_str = "POST /readings HTTP/1.1\r\nContent-Type: application/cbor\r\n\
+                           Accept: application/json, application/cbor\r\n\r\n";
+        // CBOR array [1, 2]
+        let body = [0x82, 0x01, 0x02];
+        let request = HttpRequest::parse_from(request_str, &body).unwrap();
+
+        let values: [u8; 2] = request.parse_body(Cbor).unwrap();
+        assert_eq!(values, [1, 2]);
+        assert!(request.accepts(Cbor));
+
+        let request = HttpRequest::parse_from(
+            "POST / HTTP/1.1\r\nContent-Type: application/json\r\nAccept: text/plain\r\n\r\n",
+            &body,
+        )
+        .unwrap();
+        assert!(matches!(
+            request.parse_body::<_, [u8; 2]>(Cbor),
+            Err(Error::BodyDecode(_))
+        ));
+        assert!(!request.accepts(Cbor));
+    }
+
+    #[test]
+    fn test_form_body() {
+        let request = HttpRequest::parse_from(
+            "POST /config HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\n",
+            b"ssid=Home+WiFi&channel=6",
+        )
+        .unwrap();
+        let mut scratch = [0u8; 16];
+        assert_eq!(
+            request.form().get("ssid", &mut scratch).unwrap(),
+            Some("Home WiFi")
+        );
+        assert_eq!(request.form().count(), 2);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let parse = |head: &'static [u8]| HttpRequest::try_from(head).unwrap().keep_alive();
+        assert!(parse(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+        assert!(!parse(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n"));
+        assert!(!parse(b"GET / HTTP/1.0\r\n\r\n"));
+        assert!(parse(b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n"));
+    }
 }
@@ -1,6 +1,146 @@
-use crate::{HttpHeader, StatusCode};
+use crate::{
+    HttpHeader, StatusCode,
+    buf::{Counter, SliceWriter},
+};
 use heapless::Vec;
 
+/// Producer of a response body that is too large to build in memory
+///
+/// The server asks for the body piece by piece and writes each piece to the socket, so a
+/// log file or a large JSON document can be served straight from flash. Closures
+/// `FnMut(&mut [u8]) -> Result<usize, Error>` are sources too.
+pub trait BodySource {
+    /// Write the next piece of the body into `buf` and return its length
+    ///
+    /// Returning `0` ends the body.
+    ///
+    /// # Errors
+    ///
+    /// An error aborts the response; the server closes the connection.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, crate::Error>;
+}
+
+impl<F> BodySource for F
+where
+    F: FnMut(&mut [u8]) -> Result<usize, crate::Error>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, crate::Error> {
+        self(buf)
+    }
+}
+
+/// A response body produced while it is sent, see [`ServerResponse::streamed`]
+///
+/// With a known length the server sends `Content-Length`; otherwise it uses
+/// `Transfer-Encoding: chunked` (or, for HTTP/1.0 clients, ends the body by closing the
+/// connection).
+pub struct BodyStream<'a> {
+    source: &'a mut dyn BodySource,
+    length: Option<usize>,
+}
+
+impl<'a> BodyStream<'a> {
+    /// A body of unknown length, sent chunked
+    pub fn new(source: &'a mut dyn BodySource) -> Self {
+        Self {
+            source,
+            length: None,
+        }
+    }
+
+    /// A body of exactly `length` bytes
+    ///
+    /// The source must produce that many bytes; if it ends early the connection is closed.
+    pub fn with_length(source: &'a mut dyn BodySource, length: usize) -> Self {
+        Self {
+            source,
+            length: Some(length),
+        }
+    }
+
+    /// The announced length, if known
+    #[must_use]
+    pub fn length(&self) -> Option<usize> {
+        self.length
+    }
+
+    /// Produce the next piece of the body
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> Result<usize, crate::Error> {
+        self.source.read(buf)
+    }
+}
+
+impl core::fmt::Debug for BodyStream<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BodyStream")
+            .field("length", &self.length)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Response returned by server handlers, whose body may be produced while it is sent
+///
+/// Wraps the [`HttpResponse`] with the status and headers and dereferences to it, so
+/// middleware can inspect and extend either kind of response. A buffered response converts
+/// into it with `into()`; [`ServerResponse::streamed`] creates one with a [`BodyStream`].
+pub struct ServerResponse<'a> {
+    response: HttpResponse<'a>,
+    stream: Option<BodyStream<'a>>,
+}
+
+impl<'a> ServerResponse<'a> {
+    /// A response whose body is produced by `stream` while the server sends it
+    #[must_use]
+    pub fn streamed(
+        status_code: StatusCode,
+        headers: Vec<HttpHeader<'a>, 16>,
+        stream: BodyStream<'a>,
+    ) -> Self {
+        Self {
+            response: HttpResponse {
+                status_code,
+                headers,
+                body: ResponseBody::Empty,
+            },
+            stream: Some(stream),
+        }
+    }
+
+    /// The streamed body, if the response has one
+    #[must_use]
+    pub fn stream(&self) -> Option<&BodyStream<'a>> {
+        self.stream.as_ref()
+    }
+
+    /// The status and headers, with the in-memory body, and the streamed body
+    pub(crate) fn into_parts(self) -> (HttpResponse<'a>, Option<BodyStream<'a>>) {
+        (self.response, self.stream)
+    }
+}
+
+impl<'a> From<HttpResponse<'a>> for ServerResponse<'a> {
+    fn from(response: HttpResponse<'a>) -> Self {
+        Self {
+            response,
+            stream: None,
+        }
+    }
+}
+
+impl<'a> core::ops::Deref for ServerResponse<'a> {
+    type Target = HttpResponse<'a>;
+
+    fn deref(&self) -> &HttpResponse<'a> {
+        &self.response
+    }
+}
+
+impl core::ops::DerefMut for ServerResponse<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.response
+    }
+}
+
 /// HTTP Response body that can handle both text and binary data using zero-copy references
 #[derive(Debug)]
 pub enum ResponseBody<'a> {
@@ -90,6 +230,49 @@ impl HttpResponse<'_> {
         self.get_header("Content-Length")?.parse().ok()
     }
 
+    /// Get the Content-Encoding header value
+    #[must_use]
+    pub fn content_encoding(&self) -> Option<&str> {
+        self.get_header("Content-Encoding")
+    }
+
+    /// Check whether the body has a `Content-Encoding` other than `identity`
+    #[cfg(any(
+        feature = "compression",
+        feature = "json",
+        feature = "cbor",
+        feature = "msgpack"
+    ))]
+    pub(crate) fn is_encoded(&self) -> bool {
+        self.content_encoding()
+            .is_some_and(|encoding| !encoding.trim().eq_ignore_ascii_case("identity"))
+    }
+
+    /// Decode a `gzip` or `deflate` encoded body into `out`
+    ///
+    /// Returns the decoded bytes. A body without `Content-Encoding` is copied as is, so this
+    /// can be called on every response. The whole decoded body must fit into `out`; see
+    /// [`crate::compression::decode`] for the memory needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidResponse`] for encodings other than `gzip` and `deflate`,
+    /// [`crate::Error::DecodedBodyTooLarge`] if `out` is too small and
+    /// [`crate::Error::InvalidCompressedBody`] if the body cannot be decoded.
+    #[cfg(feature = "compression")]
+    pub fn decode_body<'o>(&self, out: &'o mut [u8]) -> Result<&'o [u8], crate::Error> {
+        use crate::compression::{ContentEncoding, decode};
+
+        let encoding = match self.content_encoding() {
+            Some(value) => ContentEncoding::from_header(value).ok_or(
+                crate::Error::InvalidResponse("Unsupported Content-Encoding"),
+            )?,
+            None => ContentEncoding::Identity,
+        };
+        let len = decode(encoding, self.body.as_bytes(), out)?;
+        Ok(&out[..len])
+    }
+
     /// Check if the response indicates success (2xx status codes)
     #[must_use]
     pub fn is_success(&self) -> bool {
@@ -109,82 +292,407 @@ impl HttpResponse<'_> {
     }
 
     /// Build HTTP response bytes from this `HttpResponse`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ResponseTooLarge`] if the response does not fit into
+    /// `MAX_RESPONSE_SIZE` bytes; [`HttpResponse::encoded_len`] tells how many it needs.
+    pub fn build_bytes<const MAX_RESPONSE_SIZE: usize>(
+        &self,
+    ) -> Result<Vec<u8, MAX_RESPONSE_SIZE>, crate::Error> {
+        let mut out = ResponseWriter::new();
+        self.write_to(&mut out);
+        out.finish().map_err(|_| crate::Error::ResponseTooLarge)
+    }
+
+    /// Number of bytes [`HttpResponse::build_bytes`] needs for this response
     #[must_use]
-    pub fn build_bytes<const MAX_RESPONSE_SIZE: usize>(&self) -> Vec<u8, MAX_RESPONSE_SIZE> {
-        let mut bytes = Vec::new();
+    pub fn encoded_len(&self) -> usize {
+        let mut counter = ResponseWriter::<0>::new();
+        self.write_to(&mut counter);
+        counter.len()
+    }
 
-        // Status line: HTTP/1.1 <code> <reason>\r\n
-        write_status_line(&mut bytes, self.status_code);
+    /// Write the whole response, framing the body with `Content-Length`
+    pub(crate) fn write_to<const MAX_RESPONSE_SIZE: usize>(
+        &self,
+        out: &mut ResponseWriter<MAX_RESPONSE_SIZE>,
+    ) {
+        self.write_header_section(out);
 
-        // Headers
-        for header in &self.headers {
-            let _ = bytes.extend_from_slice(header.name.as_bytes());
-            let _ = bytes.extend_from_slice(b": ");
-            let _ = bytes.extend_from_slice(header.value.as_bytes());
-            let _ = bytes.extend_from_slice(b"\r\n");
-        }
+        // Body
+        out.write(self.body.as_bytes());
+    }
+
+    /// Write everything but the body, as the answer to a HEAD request
+    pub(crate) fn write_header_section<const MAX_RESPONSE_SIZE: usize>(
+        &self,
+        out: &mut ResponseWriter<MAX_RESPONSE_SIZE>,
+    ) {
+        self.write_head(out);
 
         // Content-Length header if body is present
         let body_bytes = self.body.as_bytes();
         if !body_bytes.is_empty() {
-            let _ = bytes.extend_from_slice(b"Content-Length: ");
-            write_decimal_to_buffer(&mut bytes, body_bytes.len());
-            let _ = bytes.extend_from_slice(b"\r\n");
+            out.write(b"Content-Length: ");
+            write_decimal_to_buffer(out, body_bytes.len());
+            out.write(b"\r\n");
         }
 
         // End of headers
-        let _ = bytes.extend_from_slice(b"\r\n");
+        out.write(b"\r\n");
+    }
 
-        // Body
-        let _ = bytes.extend_from_slice(body_bytes);
+    /// Write the status line and headers, without the blank line ending them
+    pub(crate) fn write_head<const MAX_RESPONSE_SIZE: usize>(
+        &self,
+        out: &mut ResponseWriter<MAX_RESPONSE_SIZE>,
+    ) {
+        // Status line: HTTP/1.1 <code> <reason>\r\n
+        write_status_line(out, self.status_code);
 
-        bytes
+        // Headers
+        for header in &self.headers {
+            out.write(header.name.as_bytes());
+            out.write(b": ");
+            out.write(header.value.as_bytes());
+            out.write(b"\r\n");
+        }
     }
 }
 
+/// Collects a serialized response, counting the bytes that do not fit
+///
+/// Once something does not fit, nothing more is stored, but the count keeps growing so the
+/// size the response needs can be reported.
+pub(crate) struct ResponseWriter<const MAX_RESPONSE_SIZE: usize> {
+    bytes: Vec<u8, MAX_RESPONSE_SIZE>,
+    len: usize,
+}
+
+impl<const MAX_RESPONSE_SIZE: usize> ResponseWriter<MAX_RESPONSE_SIZE> {
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Append `data`
+    pub(crate) fn write(&mut self, data: &[u8]) {
+        if self.len == self.bytes.len() {
+            // A failed extend leaves the buffer untouched
+            let _ = self.bytes.extend_from_slice(data);
+        }
+        self.len += data.len();
+    }
+
+    /// Number of bytes written, including those that did not fit
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The written bytes, or the number of bytes needed if they did not fit
+    pub(crate) fn finish(self) -> Result<Vec<u8, MAX_RESPONSE_SIZE>, usize> {
+        if self.len == self.bytes.len() {
+            Ok(self.bytes)
+        } else {
+            Err(self.len)
+        }
+    }
+}
+
+#[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+impl<'a> HttpResponse<'a> {
+    /// The body bytes, borrowed for the lifetime of the response buffer
+    fn body_bytes(&self) -> &'a [u8] {
+        match self.body {
+            ResponseBody::Text(text) => text.as_bytes(),
+            ResponseBody::Binary(bytes) => bytes,
+            ResponseBody::Empty => &[],
+        }
+    }
+
+    /// The body bytes, unless they still have a `Content-Encoding`
+    pub(crate) fn plain_body(&self) -> Result<&'a [u8], crate::Error> {
+        if self.is_encoded() {
+            return Err(crate::Error::InvalidResponse(
+                "Unsupported Content-Encoding",
+            ));
+        }
+        Ok(self.body_bytes())
+    }
+
+    /// Deserialize the JSON body into `T`
+    ///
+    /// String fields may borrow from the response buffer, so `T` can hold `&'a str` without
+    /// copying. Escaped strings cannot be borrowed and fail to deserialize into `&str`.
+    ///
+    /// A compressed body has to be decoded first, e.g. with [`HttpResponse::decode_body`];
+    /// the client's JSON helpers such as `get_json` do that themselves.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidResponse`] if the body has a `Content-Encoding` and
+    /// [`crate::Error::JsonDeserialize`] if the body is not valid JSON for `T`.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Deserialize<'a>>(&self) -> Result<T, crate::Error> {
+        if self.is_encoded() {
+            return Err(crate::Error::InvalidResponse("Body has a Content-Encoding"));
+        }
+        let (value, _) = serde_json_core_06::from_slice(self.body_bytes())?;
+        Ok(value)
+    }
+
+    /// Decode the body with `codec`, e.g. `response.parse_body(Cbor)`
+    ///
+    /// Like [`HttpResponse::json`], the value may borrow from the response buffer, and a
+    /// compressed body has to be decoded first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidResponse`] if the body has a `Content-Encoding` and
+    /// [`crate::Error::BodyDecode`] if the response has a different `Content-Type` or the body
+    /// is not a valid encoding of `T`.
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    pub fn parse_body<C, T>(&self, _codec: C) -> Result<T, crate::Error>
+    where
+        C: crate::codec::BodyCodec,
+        T: crate::codec::DecodeBody<'a, C>,
+    {
+        if self.is_encoded() {
+            return Err(crate::Error::InvalidResponse("Body has a Content-Encoding"));
+        }
+        if !crate::codec::has_content_type(&self.headers, C::CONTENT_TYPE) {
+            return Err(crate::Error::BodyDecode("Unexpected Content-Type"));
+        }
+        T::decode_body(self.body_bytes())
+    }
+}
+
+/// Builder for a response whose body and header values are formatted at runtime
+///
+/// The builder writes into a buffer, usually the scratch buffer the server hands to
+/// [`crate::ResponseHandler::respond`]. The body is written from the front of the buffer
+/// with `write!` and formatted header values are stored at its back. The finished
+/// [`HttpResponse`] borrows from the buffer, so the compiler checks that it does not
+/// outlive it.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let mut response = ResponseBuilder::new(&mut scratch);
+/// response
+///     .header(headers::CONTENT_TYPE, mime_types::JSON)?
+///     .header_fmt("X-Sensor-Age", format_args!("{}", age_secs))?;
+/// write!(response, "{{\"temperature\":{:.1}}}", celsius)?;
+/// let response = response.finish();
+/// ```
+pub struct ResponseBuilder<'b> {
+    /// The body written so far, followed by the free space
+    buffer: &'b mut [u8],
+    body_len: usize,
+    status_code: StatusCode,
+    headers: Vec<HttpHeader<'b>, 16>,
+}
+
+impl<'b> ResponseBuilder<'b> {
+    /// Start a `200 OK` response without headers or body in `buffer`
+    #[must_use]
+    pub fn new(buffer: &'b mut [u8]) -> Self {
+        Self {
+            buffer,
+            body_len: 0,
+            status_code: StatusCode::Ok,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Set the status code
+    pub fn status(&mut self, status_code: StatusCode) -> &mut Self {
+        self.status_code = status_code;
+        self
+    }
+
+    /// Add a header
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::TooManyHeaders`] if the response has no room for another header.
+    pub fn header(&mut self, name: &'b str, value: &'b str) -> Result<&mut Self, crate::Error> {
+        self.headers
+            .push(HttpHeader::new(name, value))
+            .map_err(|_| crate::Error::TooManyHeaders)?;
+        Ok(self)
+    }
+
+    /// Add a header whose value is formatted into the buffer, e.g.
+    /// `header_fmt("Retry-After", format_args!("{}", secs))`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::TooManyHeaders`] if the response has no room for another header
+    /// and [`crate::Error::ResponseTooLarge`] if the value does not fit into the buffer.
+    pub fn header_fmt(
+        &mut self,
+        name: &'b str,
+        value: core::fmt::Arguments<'_>,
+    ) -> Result<&mut Self, crate::Error> {
+        use core::fmt::Write as _;
+
+        if self.headers.is_full() {
+            return Err(crate::Error::TooManyHeaders);
+        }
+        let mut counter = Counter(0);
+        // Writing to a counter cannot fail
+        let _ = counter.write_fmt(value);
+        let len = counter.0;
+        let Some(split) = self
+            .buffer
+            .len()
+            .checked_sub(len)
+            .filter(|&split| split >= self.body_len)
+        else {
+            return Err(crate::Error::ResponseTooLarge);
+        };
+
+        let (rest, tail) = core::mem::take(&mut self.buffer).split_at_mut(split);
+        self.buffer = rest;
+        let mut written = 0;
+        let formatted = SliceWriter {
+            buffer: &mut *tail,
+            len: &mut written,
+        }
+        .write_fmt(value);
+        let tail: &'b [u8] = tail;
+        let value = core::str::from_utf8(tail)
+            .ok()
+            .filter(|_| formatted.is_ok() && written == len)
+            .ok_or(crate::Error::HeaderError(
+                "Header value formatted differently twice",
+            ))?;
+        self.header(name, value)
+    }
+
+    /// Append formatted text to the body; this is what `write!` calls
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ResponseTooLarge`] if the text does not fit into the buffer; the
+    /// body is left as it was.
+    pub fn write_fmt(&mut self, args: core::fmt::Arguments<'_>) -> Result<(), crate::Error> {
+        use core::fmt::Write as _;
+
+        let start = self.body_len;
+        SliceWriter {
+            buffer: &mut *self.buffer,
+            len: &mut self.body_len,
+        }
+        .write_fmt(args)
+        .map_err(|_| {
+            self.body_len = start;
+            crate::Error::ResponseTooLarge
+        })
+    }
+
+    /// Append bytes to the body
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ResponseTooLarge`] if the bytes do not fit into the buffer; the
+    /// body is left as it was.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), crate::Error> {
+        SliceWriter {
+            buffer: &mut *self.buffer,
+            len: &mut self.body_len,
+        }
+        .push(bytes)
+        .map_err(|_| crate::Error::ResponseTooLarge)
+    }
+
+    /// Number of body bytes written so far
+    #[must_use]
+    pub fn body_len(&self) -> usize {
+        self.body_len
+    }
+
+    /// Finish the response
+    #[must_use]
+    pub fn finish(self) -> HttpResponse<'b> {
+        let Self {
+            buffer,
+            body_len,
+            status_code,
+            headers,
+        } = self;
+        let buffer: &'b [u8] = buffer;
+        HttpResponse {
+            status_code,
+            headers,
+            body: if body_len == 0 {
+                ResponseBody::Empty
+            } else {
+                ResponseBody::Binary(&buffer[..body_len])
+            },
+        }
+    }
+}
+
+/// Parse the status code out of a status line such as `HTTP/1.1 200 OK`
+pub(crate) fn parse_status_line(line: &str) -> Result<StatusCode, crate::Error> {
+    let mut parts = line.split_whitespace();
+    if !parts
+        .next()
+        .is_some_and(|version| version.starts_with("HTTP/"))
+    {
+        return Err(crate::Error::MalformedStatusLine);
+    }
+    parts
+        .next()
+        .ok_or(crate::Error::MalformedStatusLine)?
+        .try_into()
+}
+
 /// Write HTTP status line to the given buffer
 fn write_status_line<const MAX_RESPONSE_SIZE: usize>(
-    bytes: &mut Vec<u8, MAX_RESPONSE_SIZE>,
+    out: &mut ResponseWriter<MAX_RESPONSE_SIZE>,
     status_code: StatusCode,
 ) {
     // Write "HTTP/1.1 "
-    let _ = bytes.extend_from_slice(b"HTTP/1.1 ");
+    out.write(b"HTTP/1.1 ");
 
     // Write status code as decimal
-    write_decimal_to_buffer(bytes, status_code.as_u16() as usize);
+    write_decimal_to_buffer(out, status_code.as_u16() as usize);
 
     // Write " <reason>\r\n"
-    let _ = bytes.push(b' ');
-    let _ = bytes.extend_from_slice(status_code.text().as_bytes());
-    let _ = bytes.extend_from_slice(b"\r\n");
+    out.write(b" ");
+    out.write(status_code.text().as_bytes());
+    out.write(b"\r\n");
 }
 
 /// Write a decimal number to the buffer
-fn write_decimal_to_buffer<const MAX_RESPONSE_SIZE: usize>(
-    bytes: &mut Vec<u8, MAX_RESPONSE_SIZE>,
+pub(crate) fn write_decimal_to_buffer<const MAX_RESPONSE_SIZE: usize>(
+    out: &mut ResponseWriter<MAX_RESPONSE_SIZE>,
     mut num: usize,
 ) {
     if num == 0 {
-        let _ = bytes.push(b'0');
+        out.write(b"0");
         return;
     }
 
-    let mut digits = [0u8; 10];
-    let mut i = 0;
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
 
     while num > 0 {
+        i -= 1;
         #[allow(clippy::cast_possible_truncation)]
         {
             digits[i] = (num % 10) as u8 + b'0';
         }
         num /= 10;
-        i += 1;
     }
 
-    // Write digits in reverse order
-    for j in (0..i).rev() {
-        let _ = bytes.push(digits[j]);
-    }
+    out.write(&digits[i..]);
 }
 
 #[cfg(test)]
@@ -249,7 +757,7 @@ mod tests {
             body: ResponseBody::Text("Hello World!"),
         };
 
-        let bytes = response.build_bytes::<4096>();
+        let bytes = response.build_bytes::<4096>().unwrap();
         let response_str = core::str::from_utf8(&bytes).unwrap();
 
         assert!(response_str.starts_with("HTTP/1.1 200 OK\r\n"));
@@ -266,7 +774,7 @@ mod tests {
             body: ResponseBody::Text("Not Found"),
         };
 
-        let bytes = response.build_bytes::<4096>();
+        let bytes = response.build_bytes::<4096>().unwrap();
         let response_str = core::str::from_utf8(&bytes).unwrap();
 
         assert!(response_str.starts_with("HTTP/1.1 404 Not Found\r\n"));
@@ -282,7 +790,7 @@ mod tests {
             body: ResponseBody::Empty,
         };
 
-        let bytes = response.build_bytes::<4096>();
+        let bytes = response.build_bytes::<4096>().unwrap();
         let response_str = core::str::from_utf8(&bytes).unwrap();
 
         assert!(response_str.starts_with("HTTP/1.1 204 No Content\r\n"));
@@ -299,7 +807,7 @@ mod tests {
             body: ResponseBody::Binary(binary_data),
         };
 
-        let bytes = response.build_bytes::<4096>();
+        let bytes = response.build_bytes::<4096>().unwrap();
 
         // Check that the response contains the binary data at the end
         assert!(bytes.ends_with(binary_data));
@@ -311,50 +819,134 @@ mod tests {
 
     #[test]
     fn test_write_decimal_to_buffer() {
-        let mut bytes: Vec<u8, 64> = Vec::new();
+        let decimal = |num| {
+            let mut out = ResponseWriter::<64>::new();
+            write_decimal_to_buffer(&mut out, num);
+            out.finish().unwrap()
+        };
 
         // Test zero
-        write_decimal_to_buffer(&mut bytes, 0);
-        assert_eq!(bytes, b"0");
+        assert_eq!(decimal(0), b"0");
 
         // Test single digit
-        bytes.clear();
-        write_decimal_to_buffer(&mut bytes, 5);
-        assert_eq!(bytes, b"5");
+        assert_eq!(decimal(5), b"5");
 
         // Test multi-digit numbers
-        bytes.clear();
-        write_decimal_to_buffer(&mut bytes, 42);
-        assert_eq!(bytes, b"42");
-
-        bytes.clear();
-        write_decimal_to_buffer(&mut bytes, 123);
-        assert_eq!(bytes, b"123");
+        assert_eq!(decimal(42), b"42");
+        assert_eq!(decimal(123), b"123");
+        assert_eq!(decimal(9999), b"9999");
+    }
 
-        bytes.clear();
-        write_decimal_to_buffer(&mut bytes, 9999);
-        assert_eq!(bytes, b"9999");
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(
+            parse_status_line("HTTP/1.1 200 OK").unwrap(),
+            StatusCode::Ok
+        );
+        assert_eq!(
+            parse_status_line("HTTP/1.0 404 Not Found").unwrap(),
+            StatusCode::NotFound
+        );
+        assert!(matches!(
+            parse_status_line("HTTP/1.1"),
+            Err(crate::Error::MalformedStatusLine)
+        ));
+        assert!(matches!(
+            parse_status_line("ICY 200 OK"),
+            Err(crate::Error::MalformedStatusLine)
+        ));
+        assert!(matches!(
+            parse_status_line("HTTP/1.1 abc"),
+            Err(crate::Error::InvalidStatusCode)
+        ));
     }
 
     #[test]
     fn test_write_status_line() {
-        let mut bytes: Vec<u8, 64> = Vec::new();
+        let status_line = |status_code| {
+            let mut out = ResponseWriter::<64>::new();
+            write_status_line(&mut out, status_code);
+            out.finish().unwrap()
+        };
 
         // Test common status codes
-        write_status_line(&mut bytes, StatusCode::Ok);
-        assert_eq!(bytes, b"HTTP/1.1 200 OK\r\n");
+        assert_eq!(status_line(StatusCode::Ok), b"HTTP/1.1 200 OK\r\n");
+        assert_eq!(
+            status_line(StatusCode::NotFound),
+            b"HTTP/1.1 404 Not Found\r\n"
+        );
+        assert_eq!(
+            status_line(StatusCode::InternalServerError),
+            b"HTTP/1.1 500 Internal Server Error\r\n"
+        );
+        assert_eq!(
+            status_line(StatusCode::Created),
+            b"HTTP/1.1 201 Created\r\n"
+        );
+    }
+
+    #[test]
+    fn test_build_bytes_overflow() {
+        let response = HttpResponse {
+            status_code: StatusCode::Ok,
+            headers: Vec::new(),
+            body: ResponseBody::Text("Hello World!"),
+        };
+        let expected = "HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nHello World!";
+        assert_eq!(response.encoded_len(), expected.len());
+        assert_eq!(response.build_bytes::<51>().unwrap(), expected.as_bytes());
+        assert!(matches!(
+            response.build_bytes::<50>(),
+            Err(crate::Error::ResponseTooLarge)
+        ));
+
+        // Nothing after an overflow is stored, even if it would fit
+        let mut out = ResponseWriter::<4>::new();
+        out.write(b"abc");
+        out.write(b"de");
+        out.write(b"f");
+        assert_eq!(out.finish(), Err(6));
+    }
 
-        bytes.clear();
-        write_status_line(&mut bytes, StatusCode::NotFound);
-        assert_eq!(bytes, b"HTTP/1.1 404 Not Found\r\n");
+    #[test]
+    fn test_response_builder() {
+        let mut scratch = [0; 32];
+        let mut builder = ResponseBuilder::new(&mut scratch);
+        builder
+            .status(StatusCode::Created)
+            .header("Content-Type", "text/plain")
+            .unwrap()
+            .header_fmt("X-Count", format_args!("{}", 42))
+            .unwrap();
+        write!(builder, "id={}", 7).unwrap();
+        builder.write_bytes(b"!").unwrap();
+
+        // Running out of space leaves what was written so far intact
+        assert!(matches!(
+            write!(builder, "{:>40}", "x"),
+            Err(crate::Error::ResponseTooLarge)
+        ));
+        assert!(matches!(
+            builder.header_fmt("X-Pad", format_args!("{:>40}", "x")),
+            Err(crate::Error::ResponseTooLarge)
+        ));
+        assert_eq!(builder.body_len(), 5);
 
-        bytes.clear();
-        write_status_line(&mut bytes, StatusCode::InternalServerError);
-        assert_eq!(bytes, b"HTTP/1.1 500 Internal Server Error\r\n");
+        let response = builder.finish();
+        assert_eq!(response.status_code, StatusCode::Created);
+        assert_eq!(response.get_header("X-Count"), Some("42"));
+        assert_eq!(response.body.as_bytes(), b"id=7!");
 
-        bytes.clear();
-        write_status_line(&mut bytes, StatusCode::Created);
-        assert_eq!(bytes, b"HTTP/1.1 201 Created\r\n");
+        let mut scratch = [0; 4];
+        let mut builder = ResponseBuilder::new(&mut scratch);
+        for _ in 0..16 {
+            builder.header("X-A", "a").unwrap();
+        }
+        assert!(matches!(
+            builder.header_fmt("X-B", format_args!("b")),
+            Err(crate::Error::TooManyHeaders)
+        ));
+        assert!(builder.finish().body.is_empty());
     }
 
     #[test]
@@ -379,7 +971,7 @@ mod tests {
                 body: ResponseBody::Text(body_text),
             };
 
-            let bytes = response.build_bytes::<4096>();
+            let bytes = response.build_bytes::<4096>().unwrap();
             let response_str = core::str::from_utf8(&bytes).unwrap();
 
             if *expected_len > 0 {
@@ -391,4 +983,58 @@ mod tests {
             }
         }
     }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decode_body() {
+        // "hello" as a raw deflate stream
+        let deflated: &[u8] = &[0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00];
+        let mut out = [0u8; 16];
+
+        let mut response = HttpResponse {
+            status_code: StatusCode::Ok,
+            headers: Vec::new(),
+            body: ResponseBody::Binary(deflated),
+        };
+        response
+            .headers
+            .push(HttpHeader::new("Content-Encoding", "deflate"))
+            .unwrap();
+        assert_eq!(response.decode_body(&mut out).unwrap(), b"hello");
+
+        response.headers[0] = HttpHeader::new("Content-Encoding", "br");
+        assert!(matches!(
+            response.decode_body(&mut out),
+            Err(crate::Error::InvalidResponse(_))
+        ));
+
+        response.headers.clear();
+        response.body = ResponseBody::Text("plain");
+        assert_eq!(response.decode_body(&mut out).unwrap(), b"plain");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_body() {
+        #[derive(serde::Deserialize)]
+        struct Reading<'a> {
+            sensor: &'a str,
+            value: f32,
+        }
+
+        let mut response = HttpResponse {
+            status_code: StatusCode::Ok,
+            headers: Vec::new(),
+            body: ResponseBody::Text(r#"{"sensor":"t1","value":21.5}"#),
+        };
+        let reading: Reading<'_> = response.json().unwrap();
+        assert_eq!(reading.sensor, "t1");
+        assert!((reading.value - 21.5).abs() < f32::EPSILON);
+
+        response.body = ResponseBody::Text(r#"{"sensor":"t1"}"#);
+        assert!(matches!(
+            response.json::<Reading<'_>>(),
+            Err(crate::Error::JsonDeserialize(_))
+        ));
+    }
 }
@@ -0,0 +1,542 @@
+use crate::{
+    error::Error,
+    handler::ResponseHandler,
+    header::{HttpHeader, headers, mime_types},
+    method::HttpMethod,
+    request::{HttpRequest, decode_path},
+    response::{HttpResponse, ResponseBody, ResponseBuilder, ServerResponse},
+    status_code::StatusCode,
+};
+use heapless::{String, Vec};
+
+/// Maximum number of `:name` parameters in a route pattern
+pub const MAX_PARAMS: usize = 8;
+
+/// Length of an `Allow` header value listing every method
+const MAX_ALLOW_LEN: usize = 60;
+
+/// A route: requests with `method` whose path matches `pattern` go to `target`
+///
+/// Patterns are matched segment by segment. A `:name` segment matches any non-empty segment
+/// and captures it as a parameter, a `*` segment matches any one segment and a `*` as the
+/// last segment matches the rest of the path, including nothing at all.
+#[derive(Debug, Clone, Copy)]
+pub struct Route<'r, T> {
+    /// Method the route answers
+    pub method: HttpMethod,
+    /// Path pattern, e.g. `/devices/:id/config`
+    pub pattern: &'r str,
+    /// What the route leads to, e.g. a [`RouteHandler`]
+    pub target: T,
+}
+
+impl<'r, T> Route<'r, T> {
+    /// Create a route
+    #[must_use]
+    pub const fn new(method: HttpMethod, pattern: &'r str, target: T) -> Self {
+        Self {
+            method,
+            pattern,
+            target,
+        }
+    }
+}
+
+/// Segments captured while matching a path against a route pattern
+///
+/// Captured segments borrow from the request path and are still percent-encoded; see
+/// [`Params::decode`].
+#[derive(Debug, Clone, Default)]
+pub struct Params<'a> {
+    params: Vec<(&'a str, &'a str), MAX_PARAMS>,
+    wildcard: Option<&'a str>,
+}
+
+impl<'a> Params<'a> {
+    /// The segment captured by the `:name` parameter
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.params
+            .iter()
+            .find(|(param, _)| *param == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// The segment captured by the `:name` parameter, percent-decoded into `scratch`
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`HttpRequest::decode_path`]; a segment decoding to `..` is
+    /// rejected.
+    pub fn decode<'s>(&self, name: &str, scratch: &'s mut [u8]) -> Result<Option<&'s str>, Error>
+    where
+        'a: 's,
+    {
+        self.get(name)
+            .map(|segment| decode_path(segment, scratch))
+            .transpose()
+    }
+
+    /// The rest of the path matched by a trailing `*`, without its leading `/`
+    #[must_use]
+    pub fn wildcard(&self) -> Option<&'a str> {
+        self.wildcard
+    }
+
+    /// Iterate over the `(name, segment)` pairs in pattern order
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.params.iter().copied()
+    }
+
+    /// Number of captured parameters
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Check whether no parameter was captured
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+/// Methods of the routes matching a path, for an `Allow` header
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AllowedMethods {
+    methods: Vec<HttpMethod, 9>,
+}
+
+impl AllowedMethods {
+    fn add(&mut self, method: HttpMethod) {
+        if !self.methods.contains(&method) {
+            // There are only nine methods
+            let _ = self.methods.push(method);
+        }
+        if method == HttpMethod::GET {
+            // GET routes also answer HEAD requests
+            self.add(HttpMethod::HEAD);
+        }
+    }
+
+    /// The allowed methods, in route order
+    #[must_use]
+    pub fn as_slice(&self) -> &[HttpMethod] {
+        &self.methods
+    }
+
+    /// Write the `Allow` header value, e.g. `GET, PUT`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `out` fails.
+    pub fn write_to(&self, out: &mut impl core::fmt::Write) -> core::fmt::Result {
+        for (i, method) in self.methods.iter().enumerate() {
+            if i > 0 {
+                out.write_str(", ")?;
+            }
+            out.write_str(method.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of looking a request up in a [`Router`]
+#[derive(Debug)]
+// Boxing is not an option without a heap
+#[allow(clippy::large_enum_variant)]
+pub enum RouteMatch<'a, T> {
+    /// A route matches the method and path
+    Found(&'a T, Params<'a>),
+    /// No route matches the path
+    NotFound,
+    /// Routes match the path, but none of them the method
+    MethodNotAllowed(AllowedMethods),
+}
+
+/// Table of routes matched against request paths
+///
+/// Routes are tried in order and the first one matching both method and path wins. A HEAD
+/// request without a HEAD route of its own is matched against the GET routes; the server
+/// leaves the body out when sending the response. The query string is ignored. A router only finds the target of a request; wrap it in a
+/// [`RouterHandler`] to call [`RouteHandler`] functions, or match on your own targets (such
+/// as an enum) from an async [`crate::ResponseHandler`].
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use nanofish::{HttpMethod, Route, RouteMatch, Router};
+///
+/// #[derive(Clone, Copy)]
+/// enum Api {
+///     GetConfig,
+///     PutConfig,
+/// }
+///
+/// static ROUTER: Router<'static, Api> = Router::new(&[
+///     Route::new(HttpMethod::GET, "/devices/:id/config", Api::GetConfig),
+///     Route::new(HttpMethod::PUT, "/devices/:id/config", Api::PutConfig),
+/// ]);
+///
+/// match ROUTER.find(request.method, request.path) {
+///     RouteMatch::Found(Api::GetConfig, params) => read_config(params.get("id")).await,
+///     RouteMatch::Found(Api::PutConfig, params) => write_config(params.get("id")).await,
+///     _ => not_found(),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Router<'r, T> {
+    routes: &'r [Route<'r, T>],
+}
+
+impl<'r, T> Router<'r, T> {
+    /// Create a router over `routes`
+    #[must_use]
+    pub const fn new(routes: &'r [Route<'r, T>]) -> Self {
+        Self { routes }
+    }
+
+    /// Find the route for a request with `method` and `path`
+    #[must_use]
+    pub fn find<'a>(&self, method: HttpMethod, path: &'a str) -> RouteMatch<'a, T>
+    where
+        'r: 'a,
+    {
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let mut allowed = AllowedMethods::default();
+        let mut fallback = None;
+        for route in self.routes {
+            if let Some(params) = match_pattern(route.pattern, path) {
+                if route.method == method {
+                    return RouteMatch::Found(&route.target, params);
+                }
+                if method == HttpMethod::HEAD
+                    && route.method == HttpMethod::GET
+                    && fallback.is_none()
+                {
+                    fallback = Some((&route.target, params));
+                }
+                allowed.add(route.method);
+            }
+        }
+        if let Some((target, params)) = fallback {
+            RouteMatch::Found(target, params)
+        } else if allowed.methods.is_empty() {
+            RouteMatch::NotFound
+        } else {
+            RouteMatch::MethodNotAllowed(allowed)
+        }
+    }
+}
+
+/// Match `path` against `pattern`, capturing its parameters
+fn match_pattern<'a>(pattern: &'a str, path: &'a str) -> Option<Params<'a>> {
+    let mut params = Params::default();
+    let mut segments = pattern.split('/');
+    let mut rest = path;
+    while let Some(expected) = segments.next() {
+        if expected == "*" && segments.clone().next().is_none() {
+            params.wildcard = Some(rest.strip_prefix('/').unwrap_or(rest));
+            return Some(params);
+        }
+        let (segment, tail) = match rest.find('/') {
+            Some(i) => (&rest[..i], Some(&rest[i..])),
+            None => (rest, None),
+        };
+        if let Some(name) = expected.strip_prefix(':') {
+            if segment.is_empty() {
+                return None;
+            }
+            params.params.push((name, segment)).ok()?;
+        } else if expected != "*" && expected != segment {
+            return None;
+        }
+        match (tail, segments.clone().next()) {
+            (Some(tail), Some(_)) => rest = &tail[1..],
+            // `/a/*` also matches `/a`
+            (None, Some("*")) => rest = "",
+            (None, None) => return Some(params),
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Function answering the requests of a route
+///
+/// It gets mutable access to the state of the [`RouterHandler`], the parameters captured
+/// from the path and the server's response buffer. Name the state lifetime, as the response
+/// may borrow from the state or the buffer:
+///
+/// ```rust,ignore
+/// fn get_config<'s>(
+///     app: &'s mut App,
+///     _request: &HttpRequest<'_>,
+///     params: &Params<'_>,
+///     mut response: ResponseBuilder<'s>,
+/// ) -> Result<HttpResponse<'s>, Error> {
+///     write!(response, "{{\"name\":\"{}\"}}", app.name)?;
+///     Ok(response.finish())
+/// }
+/// ```
+pub type RouteHandler<S> = for<'s> fn(
+    &'s mut S,
+    &HttpRequest<'_>,
+    &Params<'_>,
+    ResponseBuilder<'s>,
+) -> Result<HttpResponse<'s>, Error>;
+
+/// A [`ResponseHandler`] dispatching requests to the [`RouteHandler`] of their route
+///
+/// Requests without a route get `404 Not Found`; requests whose path has routes for other
+/// methods only get `405 Method Not Allowed` with an `Allow` header listing them.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// static ROUTES: &[Route<'static, RouteHandler<App>>] = &[
+///     Route::new(HttpMethod::GET, "/devices/:id/config", get_config),
+///     Route::new(HttpMethod::PUT, "/devices/:id/config", put_config),
+///     Route::new(HttpMethod::GET, "/static/*", get_file),
+/// ];
+///
+/// let handler = RouterHandler::new(Router::new(ROUTES), App::default());
+/// server.serve(stack, handler).await;
+/// ```
+pub struct RouterHandler<'r, S> {
+    router: Router<'r, RouteHandler<S>>,
+    state: S,
+    allow: String<MAX_ALLOW_LEN>,
+}
+
+impl<'r, S> RouterHandler<'r, S> {
+    /// Dispatch requests through `router`, handing `state` to every route handler
+    #[must_use]
+    pub fn new(router: Router<'r, RouteHandler<S>>, state: S) -> Self {
+        Self {
+            router,
+            state,
+            allow: String::new(),
+        }
+    }
+
+    /// The state handed to the route handlers
+    #[must_use]
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Mutable access to the state handed to the route handlers
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+}
+
+impl<S> ResponseHandler for RouterHandler<'_, S> {
+    async fn respond<'h>(
+        &'h mut self,
+        request: &HttpRequest<'_>,
+        response: ResponseBuilder<'h>,
+    ) -> Result<ServerResponse<'h>, Error> {
+        let mut headers = Vec::new();
+        let _ = headers.push(HttpHeader::content_type(mime_types::TEXT));
+        let status_code = match self.router.find(request.method, request.path) {
+            RouteMatch::Found(handler, params) => {
+                let handler = *handler;
+                return handler(&mut self.state, request, &params, response).map(Into::into);
+            }
+            RouteMatch::NotFound => StatusCode::NotFound,
+            RouteMatch::MethodNotAllowed(allowed) => {
+                self.allow.clear();
+                // Sized for every method
+                let _ = allowed.write_to(&mut self.allow);
+                let _ = headers.push(HttpHeader::new(headers::ALLOW, &self.allow));
+                StatusCode::MethodNotAllowed
+            }
+        };
+        Ok(HttpResponse {
+            status_code,
+            headers,
+            body: ResponseBody::Text(status_code.text()),
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(router: &Router<'a, u8>, method: HttpMethod, path: &'a str) -> Option<u8> {
+        match router.find(method, path) {
+            RouteMatch::Found(target, _) => Some(*target),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_match_pattern() {
+        let params = match_pattern("/devices/:id/config", "/devices/4%202/config").unwrap();
+        assert_eq!(params.get("id"), Some("4%202"));
+        assert_eq!(params.decode("id", &mut [0u8; 8]).unwrap(), Some("4 2"));
+        assert_eq!(params.len(), 1);
+        assert!(match_pattern("/devices/:id/config", "/devices//config").is_none());
+        assert!(match_pattern("/devices/:id/config", "/devices/42").is_none());
+        assert!(match_pattern("/devices/:id", "/devices/42/config").is_none());
+
+        let params = match_pattern("/static/*", "/static/css/site.css").unwrap();
+        assert_eq!(params.wildcard(), Some("css/site.css"));
+        assert_eq!(
+            match_pattern("/static/*", "/static").unwrap().wildcard(),
+            Some("")
+        );
+        assert!(match_pattern("/static/*", "/other/file").is_none());
+
+        let params = match_pattern("/:kind/*/:id", "/sensor/any/7").unwrap();
+        assert_eq!(
+            params.iter().collect::<std::vec::Vec<_>>(),
+            [("kind", "sensor"), ("id", "7")]
+        );
+        assert!(params.wildcard().is_none());
+
+        assert!(match_pattern("/", "/").is_some());
+        assert!(match_pattern("/a", "/a/").is_none());
+    }
+
+    #[test]
+    fn test_router_find() {
+        let table = [
+            Route::new(HttpMethod::GET, "/devices/:id/config", 1),
+            Route::new(HttpMethod::PUT, "/devices/:id/config", 2),
+            Route::new(HttpMethod::GET, "/*", 3),
+        ];
+        let router = Router::new(&table);
+        assert_eq!(find(&router, HttpMethod::GET, "/devices/7/config"), Some(1));
+        assert_eq!(
+            find(&router, HttpMethod::PUT, "/devices/7/config?force=1"),
+            Some(2)
+        );
+        assert_eq!(find(&router, HttpMethod::GET, "/index.html"), Some(3));
+
+        match router.find(HttpMethod::DELETE, "/devices/7/config") {
+            RouteMatch::MethodNotAllowed(allowed) => {
+                assert_eq!(
+                    allowed.as_slice(),
+                    [HttpMethod::GET, HttpMethod::HEAD, HttpMethod::PUT]
+                );
+            }
+            other => panic!("unexpected match: {other:?}"),
+        }
+
+        let router = Router::new(&table[..2]);
+        assert!(matches!(
+            router.find(HttpMethod::HEAD, "/devices"),
+            RouteMatch::NotFound
+        ));
+        assert!(matches!(
+            router.find(HttpMethod::GET, "/devices"),
+            RouteMatch::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_router_head() {
+        let table = [
+            Route::new(HttpMethod::GET, "/status", 1),
+            Route::new(HttpMethod::HEAD, "/status", 2),
+            Route::new(HttpMethod::GET, "/devices/:id", 3),
+            Route::new(HttpMethod::POST, "/upload", 4),
+        ];
+        let router = Router::new(&table);
+
+        // An explicit HEAD route wins over the GET route before it
+        assert_eq!(find(&router, HttpMethod::HEAD, "/status"), Some(2));
+        match router.find(HttpMethod::HEAD, "/devices/7") {
+            RouteMatch::Found(target, params) => {
+                assert_eq!(*target, 3);
+                assert_eq!(params.get("id"), Some("7"));
+            }
+            other => panic!("unexpected match: {other:?}"),
+        }
+
+        // POST routes do not answer HEAD requests
+        match router.find(HttpMethod::HEAD, "/upload") {
+            RouteMatch::MethodNotAllowed(allowed) => {
+                assert_eq!(allowed.as_slice(), [HttpMethod::POST]);
+            }
+            other => panic!("unexpected match: {other:?}"),
+        }
+
+        match router.find(HttpMethod::PUT, "/status") {
+            RouteMatch::MethodNotAllowed(allowed) => {
+                let mut header = std::string::String::new();
+                allowed.write_to(&mut header).unwrap();
+                assert_eq!(header, "GET, HEAD");
+            }
+            other => panic!("unexpected match: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_router_handler() {
+        fn get_config<'s>(
+            hits: &'s mut u32,
+            _request: &HttpRequest<'_>,
+            params: &Params<'_>,
+            mut response: ResponseBuilder<'s>,
+        ) -> Result<HttpResponse<'s>, Error> {
+            *hits += 1;
+            write!(response, "config {}", params.get("id").unwrap_or_default())?;
+            Ok(response.finish())
+        }
+
+        static ROUTES: &[Route<'static, RouteHandler<u32>>] = &[Route::new(
+            HttpMethod::GET,
+            "/devices/:id/config",
+            get_config,
+        )];
+
+        let mut handler = RouterHandler::new(Router::new(ROUTES), 0);
+        let mut request = |method, path| {
+            let request = HttpRequest {
+                method,
+                path,
+                version: "HTTP/1.1",
+                headers: Vec::new(),
+                body: b"",
+            };
+            let mut scratch = [0u8; 32];
+            let response = futures_lite::future::block_on(
+                handler.respond(&request, ResponseBuilder::new(&mut scratch)),
+            )
+            .unwrap();
+            (
+                response.status_code,
+                response.get_header("Allow").map(std::string::String::from),
+                response.body.as_str().map(std::string::String::from),
+            )
+        };
+
+        assert_eq!(
+            request(HttpMethod::GET, "/devices/7/config"),
+            (StatusCode::Ok, None, Some("config 7".into()))
+        );
+        assert_eq!(
+            request(HttpMethod::GET, "/devices"),
+            (StatusCode::NotFound, None, Some("Not Found".into()))
+        );
+        assert_eq!(
+            request(HttpMethod::HEAD, "/devices/7/config"),
+            (StatusCode::Ok, None, Some("config 7".into()))
+        );
+        assert_eq!(
+            request(HttpMethod::POST, "/devices/7/config"),
+            (
+                StatusCode::MethodNotAllowed,
+                Some("GET, HEAD".into()),
+                Some("Method Not Allowed".into())
+            )
+        );
+        assert_eq!(*handler.state(), 2);
+    }
+}
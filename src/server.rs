@@ -1,23 +1,32 @@
 use crate::{
+    body::{BodyFraming, ChunkedDecoder, RequestBody, read_some},
     error::Error,
-    handler::HttpHandler,
-    header::HttpHeader,
-    request::HttpRequest,
-    response::{HttpResponse, ResponseBody},
+    handler::{HandlerFactory, ResponseHandler, StreamingHandler},
+    header::{HttpHeader, has_token, headers},
+    method::HttpMethod,
+    request::{HttpRequest, find_double_crlf},
+    response::{
+        BodyStream, HttpResponse, ResponseBody, ResponseBuilder, ResponseWriter, ServerResponse,
+        write_decimal_to_buffer,
+    },
     status_code::StatusCode,
 };
+use core::{fmt::Write as _, ops::Range};
+use embassy_futures::join::join_array;
+use embassy_futures_01 as embassy_futures;
 use embassy_net::{Stack, tcp::TcpSocket};
 use embassy_net_08 as embassy_net;
 use embassy_time_05 as embassy_time;
 use embedded_io_async_07 as embedded_io_async;
 
-use embassy_time::{Duration, Timer, with_timeout};
-use embedded_io_async::Write as EmbeddedWrite;
+use embassy_time::{Duration, TimeoutError, Timer, with_timeout};
+use embedded_io_async::{Read as EmbeddedRead, Write as EmbeddedWrite};
 use heapless::Vec;
 
 const SERVER_BUFFER_SIZE: usize = 4096;
 const MAX_REQUEST_SIZE: usize = 4096;
 const DEFAULT_MAX_RESPONSE_SIZE: usize = 4096;
+const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 100;
 
 /// HTTP server timeout configuration
 #[derive(Debug, Clone, Copy)]
@@ -28,6 +37,10 @@ pub struct ServerTimeouts {
     pub read_timeout: u64,
     /// Request handler timeout in seconds
     pub handler_timeout: u64,
+    /// How long an idle keep-alive connection waits for its next request, in seconds
+    ///
+    /// `0` disables keep-alive: every connection is closed after one response.
+    pub keep_alive_timeout: u64,
 }
 
 impl Default for ServerTimeouts {
@@ -36,24 +49,33 @@ impl Default for ServerTimeouts {
             accept_timeout: 10,
             read_timeout: 30,
             handler_timeout: 60,
+            keep_alive_timeout: 5,
         }
     }
 }
 
 impl ServerTimeouts {
     /// Create new server timeouts with custom values
+    ///
+    /// The keep-alive timeout keeps its default; set [`ServerTimeouts::keep_alive_timeout`]
+    /// to change it.
     #[must_use]
     pub fn new(accept_timeout: u64, read_timeout: u64, handler_timeout: u64) -> Self {
         Self {
             accept_timeout,
             read_timeout,
             handler_timeout,
+            ..Self::default()
         }
     }
 }
 
 /// Simple HTTP server implementation
 ///
+/// Connections are kept open for further requests (HTTP/1.1 keep-alive, including pipelined
+/// requests) until the client asks to close, the idle [`ServerTimeouts::keep_alive_timeout`]
+/// elapses or the per-connection request limit is reached.
+///
 /// **Note**: This server only supports HTTP connections, not HTTPS/TLS.
 /// For secure connections, consider using a reverse proxy or load balancer
 /// that handles TLS termination.
@@ -65,6 +87,7 @@ pub struct HttpServer<
 > {
     port: u16,
     timeouts: ServerTimeouts,
+    max_requests: usize,
 }
 
 impl<
@@ -77,129 +100,733 @@ impl<
     /// Create a new HTTP server with default timeouts
     #[must_use]
     pub fn new(port: u16) -> Self {
+        Self::with_timeouts(port, ServerTimeouts::default())
+    }
+
+    /// Create a new HTTP server with custom timeouts
+    #[must_use]
+    pub fn with_timeouts(port: u16, timeouts: ServerTimeouts) -> Self {
         Self {
             port,
-            timeouts: ServerTimeouts::default(),
+            timeouts,
+            max_requests: DEFAULT_MAX_REQUESTS_PER_CONNECTION,
         }
     }
 
-    /// Create a new HTTP server with custom timeouts
+    /// Limit how many requests are served on one connection before it is closed
+    ///
+    /// Defaults to 100. A limit of `1` disables keep-alive.
     #[must_use]
-    pub fn with_timeouts(port: u16, timeouts: ServerTimeouts) -> Self {
-        Self { port, timeouts }
+    pub fn with_max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.max_requests = max_requests.max(1);
+        self
     }
 
     /// Start the HTTP server and handle incoming connections
     ///
     /// **Important**: This server only accepts plain HTTP connections.
     /// HTTPS/TLS is not supported by the server (only by the client).
-    pub async fn serve<H>(&mut self, stack: Stack<'_>, mut handler: H) -> !
+    ///
+    /// `handler` is an [`crate::HttpHandler`], or a [`ResponseHandler`] that builds its
+    /// responses in a `MAX_RESPONSE_SIZE` scratch buffer kept by the server.
+    pub async fn serve<H>(&mut self, stack: Stack<'_>, handler: H) -> !
+    where
+        H: ResponseHandler,
+    {
+        info!("HTTP server started on port {}", self.port);
+        self.worker(stack, handler).await
+    }
+
+    /// Start the HTTP server with `WORKERS` connections handled concurrently
+    ///
+    /// Each worker runs its own accept loop on the port with its own socket and request
+    /// buffers, so a slow client only occupies one of them. The buffers live in the returned
+    /// future; spawn it as a task to have them allocated statically. `factory` creates one
+    /// handler per worker, see [`HandlerFactory`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// let server = DefaultHttpServer::new(80);
+    /// server.serve_pool::<4, _>(stack, |_worker| MyHandler::new()).await;
+    /// ```
+    pub async fn serve_pool<const WORKERS: usize, F>(&self, stack: Stack<'_>, factory: F) -> !
     where
-        H: HttpHandler,
+        F: HandlerFactory,
+        F::Handler: ResponseHandler,
     {
-        defmt::info!("HTTP server started on port {}", self.port);
+        const { assert!(WORKERS > 0, "a worker pool needs at least one worker") };
+        info!(
+            "HTTP server started on port {} with {} workers",
+            self.port, WORKERS
+        );
+        let workers: [_; WORKERS] =
+            core::array::from_fn(|worker| self.worker(stack, factory.handler(worker)));
+        join_array(workers).await;
+        unreachable!("server workers never return")
+    }
 
+    /// Accept connections one at a time and serve the requests on them
+    async fn worker<H>(&self, stack: Stack<'_>, mut handler: H) -> !
+    where
+        H: ResponseHandler,
+    {
         let mut rx_buffer = [0; RX_SIZE];
         let mut tx_buffer = [0; TX_SIZE];
         let mut buf = [0; REQ_SIZE];
+        let mut scratch = [0; MAX_RESPONSE_SIZE];
 
         loop {
             let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
-            socket.set_timeout(Some(Duration::from_secs(self.timeouts.accept_timeout)));
-
-            if let Err(e) = socket.accept(self.port).await {
-                defmt::warn!("Accept error: {:?}", e);
-                Timer::after(Duration::from_millis(100)).await;
+            if !self.accept(&mut socket).await {
                 continue;
             }
 
-            let n = match with_timeout(
-                Duration::from_secs(self.timeouts.read_timeout),
-                socket.read(&mut buf),
-            )
-            .await
-            {
-                Ok(Ok(0)) => {
-                    // Connection closed
-                    continue;
+            // Bytes in `buf`, possibly the start of a pipelined request
+            let mut filled = 0;
+            for served in 1..=self.max_requests {
+                if served > 1
+                    && !self
+                        .wait_for_request(&mut socket, &mut buf, &mut filled)
+                        .await
+                {
+                    break;
                 }
-                Ok(Ok(n)) => n,
-                Ok(Err(e)) => {
-                    defmt::warn!("Read error: {:?}", e);
-                    continue;
+
+                let len = match with_timeout(
+                    Duration::from_secs(self.timeouts.read_timeout),
+                    read_request(&mut socket, &mut buf, &mut filled),
+                )
+                .await
+                {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(e)) => {
+                        reject::<_, MAX_RESPONSE_SIZE>(&mut socket, &e).await;
+                        break;
+                    }
+                    Err(_) => {
+                        warn!("Socket read timeout");
+                        break;
+                    }
+                };
+
+                // Parse the request
+                let result = self
+                    .handle_connection(
+                        &mut socket,
+                        &buf[..len],
+                        &mut scratch,
+                        served < self.max_requests,
+                        &mut handler,
+                    )
+                    .await;
+                if !finish_request::<MAX_RESPONSE_SIZE>(&mut socket, result).await {
+                    break;
                 }
-                Err(_) => {
-                    defmt::warn!("Socket read timeout");
-                    continue;
+                buf.copy_within(len..filled, 0);
+                filled -= len;
+            }
+            socket.close();
+        }
+    }
+
+    /// Start the HTTP server with a handler that reads request bodies incrementally
+    ///
+    /// Only the header section has to fit into `REQ_SIZE`. The handler reads the body from
+    /// the socket through a [`RequestBody`], within the handler timeout, so uploads can be
+    /// much larger than the request buffer. The connection is only kept open if the handler
+    /// read the whole body.
+    pub async fn serve_streaming<H>(&mut self, stack: Stack<'_>, handler: H) -> !
+    where
+        H: StreamingHandler,
+    {
+        info!("HTTP server started on port {}", self.port);
+        self.streaming_worker(stack, handler).await
+    }
+
+    /// Start the HTTP server with `WORKERS` concurrent workers and streaming handlers
+    ///
+    /// Combines [`HttpServer::serve_pool`] and [`HttpServer::serve_streaming`].
+    pub async fn serve_streaming_pool<const WORKERS: usize, F>(
+        &self,
+        stack: Stack<'_>,
+        factory: F,
+    ) -> !
+    where
+        F: HandlerFactory,
+        F::Handler: StreamingHandler,
+    {
+        const { assert!(WORKERS > 0, "a worker pool needs at least one worker") };
+        info!(
+            "HTTP server started on port {} with {} workers",
+            self.port, WORKERS
+        );
+        let workers: [_; WORKERS] =
+            core::array::from_fn(|worker| self.streaming_worker(stack, factory.handler(worker)));
+        join_array(workers).await;
+        unreachable!("server workers never return")
+    }
+
+    /// Like [`Self::worker`], with the request body read by the handler
+    async fn streaming_worker<H>(&self, stack: Stack<'_>, mut handler: H) -> !
+    where
+        H: StreamingHandler,
+    {
+        let mut rx_buffer = [0; RX_SIZE];
+        let mut tx_buffer = [0; TX_SIZE];
+        let mut buf = [0; REQ_SIZE];
+
+        loop {
+            let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+            if !self.accept(&mut socket).await {
+                continue;
+            }
+
+            let mut filled = 0;
+            for served in 1..=self.max_requests {
+                if served > 1
+                    && !self
+                        .wait_for_request(&mut socket, &mut buf, &mut filled)
+                        .await
+                {
+                    break;
                 }
-            };
 
-            // Parse the request
-            match self.handle_connection(&buf[..n], &mut handler).await {
-                Ok(response_bytes) => {
-                    if let Err(e) = socket.write_all(&response_bytes).await {
-                        defmt::warn!("Failed to write response: {:?}", e);
+                let head = match with_timeout(
+                    Duration::from_secs(self.timeouts.read_timeout),
+                    read_head(&mut socket, &mut buf, filled),
+                )
+                .await
+                {
+                    Ok(Ok(head)) => head,
+                    Ok(Err(e)) => {
+                        reject::<_, MAX_RESPONSE_SIZE>(&mut socket, &e).await;
+                        break;
+                    }
+                    Err(_) => {
+                        warn!("Socket read timeout");
+                        break;
                     }
+                };
+
+                let result = self
+                    .handle_streaming(
+                        &mut socket,
+                        &mut buf,
+                        &head,
+                        served < self.max_requests,
+                        &mut handler,
+                    )
+                    .await;
+                let unread = result.as_ref().ok().map(|(_, unread)| unread.clone());
+                if !finish_request::<MAX_RESPONSE_SIZE>(
+                    &mut socket,
+                    result.map(|(keep_alive, _)| keep_alive),
+                )
+                .await
+                {
+                    break;
                 }
-                Err(e) => {
-                    defmt::error!("Error handling request: {:?}", e);
-                    // Send a 500 error response
-                    let error_response = b"HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\nContent-Length: 21\r\n\r\nInternal Server Error";
-                    let _ = socket.write_all(error_response).await;
+                if let Some(unread) = unread {
+                    filled = unread.len();
+                    buf.copy_within(unread, 0);
                 }
             }
-
             socket.close();
         }
     }
 
+    /// Wait for a connection, returning `false` if none could be accepted
+    async fn accept(&self, socket: &mut TcpSocket<'_>) -> bool {
+        socket.set_timeout(Some(Duration::from_secs(self.timeouts.accept_timeout)));
+
+        if let Err(e) = socket.accept(self.port).await {
+            warn!("Accept error: {:?}", e);
+            Timer::after(Duration::from_millis(100)).await;
+            return false;
+        }
+        true
+    }
+
+    /// Wait on an idle keep-alive connection for the next request to begin
+    ///
+    /// Returns `false` if the client closed the connection or stayed idle for too long.
+    async fn wait_for_request(
+        &self,
+        socket: &mut TcpSocket<'_>,
+        buffer: &mut [u8],
+        filled: &mut usize,
+    ) -> bool {
+        if *filled > 0 {
+            // A pipelined request is already waiting
+            return true;
+        }
+        match with_timeout(
+            Duration::from_secs(self.timeouts.keep_alive_timeout),
+            read_some(socket, buffer),
+        )
+        .await
+        {
+            Ok(Ok(n)) => {
+                *filled = n;
+                true
+            }
+            Ok(Err(_)) | Err(_) => false,
+        }
+    }
+
     async fn handle_connection<H>(
-        &mut self,
+        &self,
+        socket: &mut TcpSocket<'_>,
         buffer: &[u8],
+        scratch: &mut [u8],
+        keep_alive: bool,
         handler: &mut H,
-    ) -> Result<Vec<u8, MAX_RESPONSE_SIZE>, Error>
+    ) -> Result<bool, Error>
     where
-        H: HttpHandler,
+        H: ResponseHandler,
     {
         // Parse the request
         let request = HttpRequest::try_from(buffer)?;
+        let keep_alive = keep_alive && self.timeouts.keep_alive_timeout > 0 && request.keep_alive();
 
         // Handle the request
-        let response = match with_timeout(
+        let outcome = with_timeout(
             Duration::from_secs(self.timeouts.handler_timeout),
-            handler.handle_request(&request),
+            handler.respond(&request, ResponseBuilder::new(scratch)),
         )
-        .await
-        {
+        .await;
+        let response = Self::handler_response(outcome);
+        Ok(send_response::<_, MAX_RESPONSE_SIZE>(
+            socket,
+            response,
+            keep_alive,
+            is_http11(&request),
+            request.method == HttpMethod::HEAD,
+        )
+        .await)
+    }
+
+    /// Handle a request whose body is read by the handler
+    ///
+    /// Also returns where in `buffer` the bytes received after the body are.
+    async fn handle_streaming<H>(
+        &self,
+        socket: &mut TcpSocket<'_>,
+        buffer: &mut [u8],
+        head: &Head,
+        keep_alive: bool,
+        handler: &mut H,
+    ) -> Result<(bool, Range<usize>), Error>
+    where
+        H: StreamingHandler,
+    {
+        let body_start = head.end + 4;
+        let (head_bytes, rest) = buffer.split_at_mut(body_start);
+        let request = HttpRequest::try_from(&*head_bytes)?;
+        let keep_alive = keep_alive && self.timeouts.keep_alive_timeout > 0 && request.keep_alive();
+        let mut body = RequestBody::new(&mut *socket, rest, head.filled - body_start, head.framing);
+
+        let outcome = with_timeout(
+            Duration::from_secs(self.timeouts.handler_timeout),
+            handler.handle_request(&request, &mut body),
+        )
+        .await;
+        // Unread body bytes would be taken for the next request
+        let keep_alive = keep_alive && body.is_finished();
+        let unread = body.unread();
+        let response = Self::handler_response(outcome);
+        let keep_alive = send_response::<_, MAX_RESPONSE_SIZE>(
+            socket,
+            response,
+            keep_alive,
+            is_http11(&request),
+            request.method == HttpMethod::HEAD,
+        )
+        .await;
+        Ok((
+            keep_alive,
+            body_start + unread.start..body_start + unread.end,
+        ))
+    }
+
+    /// The handler's response, or an error response if it failed or timed out
+    fn handler_response(
+        outcome: Result<Result<ServerResponse<'_>, Error>, TimeoutError>,
+    ) -> ServerResponse<'_> {
+        match outcome {
             Ok(Ok(response)) => response,
             Ok(Err(e)) => {
-                defmt::warn!("Handler error: {:?}", e);
+                warn!("Handler error: {:?}", e);
                 let mut headers = Vec::new();
                 let _ = headers.push(HttpHeader::new("Content-Type", "text/plain"));
-                let error_response = HttpResponse {
+                HttpResponse {
                     status_code: StatusCode::InternalServerError,
                     headers,
                     body: ResponseBody::Text("Internal Server Error"),
-                };
-                return Ok(error_response.build_bytes::<MAX_RESPONSE_SIZE>());
+                }
+                .into()
             }
             Err(_) => {
-                defmt::warn!("Request handling timed out");
+                warn!("Request handling timed out");
                 let mut headers = Vec::new();
                 let _ = headers.push(HttpHeader::new("Content-Type", "text/plain"));
-                let timeout_response = HttpResponse {
+                HttpResponse {
                     status_code: StatusCode::BadRequest,
                     headers,
                     body: ResponseBody::Text("Request Timeout"),
-                };
-                return Ok(timeout_response.build_bytes::<MAX_RESPONSE_SIZE>());
+                }
+                .into()
             }
-        };
+        }
+    }
+}
+
+/// Check whether a client speaks HTTP/1.1 and so understands chunked responses
+fn is_http11(request: &HttpRequest<'_>) -> bool {
+    request.version.eq_ignore_ascii_case("HTTP/1.1")
+}
+
+/// Send a response, producing a streamed body while it is written
+///
+/// `chunked` tells whether the client understands `Transfer-Encoding: chunked`. `head`
+/// leaves the body out, as the answer to a HEAD request, while keeping the headers that
+/// describe it. Returns whether the connection can stay open.
+async fn send_response<C, const MAX_RESPONSE_SIZE: usize>(
+    conn: &mut C,
+    response: ServerResponse<'_>,
+    keep_alive: bool,
+    chunked: bool,
+    head: bool,
+) -> bool
+where
+    C: EmbeddedWrite,
+    Error: From<C::Error>,
+{
+    let (mut response, stream) = response.into_parts();
+    let (keep_alive, chunked) = match &stream {
+        Some(stream) if stream.length().is_none() => {
+            // Without a length or chunking, closing the connection marks the end of the body
+            (
+                set_connection(&mut response, keep_alive && chunked, true),
+                chunked,
+            )
+        }
+        Some(_) => (set_connection(&mut response, keep_alive, true), false),
+        None => (set_connection(&mut response, keep_alive, false), false),
+    };
+    let mut out = ResponseWriter::<MAX_RESPONSE_SIZE>::new();
+    match &stream {
+        Some(stream) => write_stream_head(&mut out, &response, stream.length(), chunked),
+        None if head => response.write_header_section(&mut out),
+        None => response.write_to(&mut out),
+    }
+    let result = match (out.finish(), stream) {
+        (Ok(bytes), Some(stream)) if !head => {
+            send_stream(conn, bytes, stream, keep_alive, chunked).await
+        }
+        (Ok(bytes), _) => conn
+            .write_all(&bytes)
+            .await
+            .map(|()| keep_alive)
+            .map_err(Error::from),
+        (Err(needed), _) => {
+            warn!(
+                "Response needs {} bytes but MAX_RESPONSE_SIZE is {}",
+                needed, MAX_RESPONSE_SIZE
+            );
+            let response = plain_response::<MAX_RESPONSE_SIZE>(StatusCode::InternalServerError);
+            conn.write_all(&response)
+                .await
+                .map(|()| false)
+                .map_err(Error::from)
+        }
+    };
+    result.unwrap_or_else(|e| {
+        warn!("Failed to write response: {:?}", e);
+        false
+    })
+}
+
+/// Write the head of a streamed response, framing the body as the client allows
+fn write_stream_head<const MAX_RESPONSE_SIZE: usize>(
+    out: &mut ResponseWriter<MAX_RESPONSE_SIZE>,
+    response: &HttpResponse<'_>,
+    length: Option<usize>,
+    chunked: bool,
+) {
+    response.write_head(out);
+    if let Some(length) = length {
+        out.write(b"Content-Length: ");
+        write_decimal_to_buffer(out, length);
+        out.write(b"\r\n");
+    } else if chunked {
+        out.write(b"Transfer-Encoding: chunked\r\n");
+    }
+    out.write(b"\r\n");
+}
+
+/// Send the head of a response and then its body piece by piece
+///
+/// `bytes` holds the head and is then reused for each piece of the body.
+async fn send_stream<C, const MAX_RESPONSE_SIZE: usize>(
+    conn: &mut C,
+    mut bytes: Vec<u8, MAX_RESPONSE_SIZE>,
+    mut stream: BodyStream<'_>,
+    keep_alive: bool,
+    chunked: bool,
+) -> Result<bool, Error>
+where
+    C: EmbeddedWrite,
+    Error: From<C::Error>,
+{
+    conn.write_all(&bytes).await?;
+
+    let _ = bytes.resize(MAX_RESPONSE_SIZE, 0);
+    if chunked {
+        write_chunks(conn, &mut stream, &mut bytes).await?;
+        return Ok(keep_alive);
+    }
+    let mut remaining = stream.length();
+    loop {
+        let limit = remaining.map_or(bytes.len(), |remaining| remaining.min(bytes.len()));
+        if limit == 0 {
+            return Ok(keep_alive);
+        }
+        let n = stream.read(&mut bytes[..limit])?.min(limit);
+        if n == 0 {
+            return match remaining {
+                Some(_) => Err(Error::PrematureEof),
+                None => Ok(keep_alive),
+            };
+        }
+        conn.write_all(&bytes[..n]).await?;
+        if let Some(remaining) = &mut remaining {
+            *remaining -= n;
+        }
+    }
+}
+
+/// Write a streamed body with `Transfer-Encoding: chunked`, one chunk per piece
+///
+/// Each piece is produced into `scratch` behind room for its size line.
+async fn write_chunks<C>(
+    conn: &mut C,
+    stream: &mut BodyStream<'_>,
+    scratch: &mut [u8],
+) -> Result<(), Error>
+where
+    C: EmbeddedWrite,
+    Error: From<C::Error>,
+{
+    // Up to eight hex digits and CRLF
+    const SIZE_LINE: usize = 10;
 
-        Ok(response.build_bytes::<MAX_RESPONSE_SIZE>())
+    let data_end = scratch.len().saturating_sub(2);
+    if data_end <= SIZE_LINE {
+        return Err(Error::ResponseTooLarge);
+    }
+    loop {
+        let n = stream
+            .read(&mut scratch[SIZE_LINE..data_end])?
+            .min(data_end - SIZE_LINE);
+        if n == 0 {
+            conn.write_all(b"0\r\n\r\n").await?;
+            return Ok(());
+        }
+        let mut size = heapless::String::<SIZE_LINE>::new();
+        let _ = write!(size, "{n:X}\r\n");
+        let start = SIZE_LINE - size.len();
+        scratch[start..SIZE_LINE].copy_from_slice(size.as_bytes());
+        scratch[SIZE_LINE + n..SIZE_LINE + n + 2].copy_from_slice(b"\r\n");
+        conn.write_all(&scratch[start..SIZE_LINE + n + 2]).await?;
+    }
+}
+
+/// Add the `Connection` header to a response, and `Content-Length: 0` to an empty one
+///
+/// `streamed` tells that the body is produced while it is sent, so an empty `body` does not
+/// mean an empty response. Returns whether the connection can stay open. Handlers can close
+/// it by setting `Connection: close` themselves.
+fn set_connection(response: &mut HttpResponse<'_>, keep_alive: bool, streamed: bool) -> bool {
+    if let Some(value) = response.get_header(headers::CONNECTION) {
+        return keep_alive && !has_token(value, "close");
+    }
+    let mut keep_alive = keep_alive;
+    if keep_alive
+        && response.body.is_empty()
+        && !streamed
+        && response.get_header(headers::CONTENT_LENGTH).is_none()
+        && !matches!(
+            response.status_code,
+            StatusCode::NoContent | StatusCode::NotModified
+        )
+    {
+        // Without a length the client would wait for the connection to close
+        keep_alive = response
+            .headers
+            .push(HttpHeader::new(headers::CONTENT_LENGTH, "0"))
+            .is_ok();
+    }
+    let value = if keep_alive { "keep-alive" } else { "close" };
+    let pushed = response
+        .headers
+        .push(HttpHeader::new(headers::CONNECTION, value))
+        .is_ok();
+    keep_alive && pushed
+}
+
+/// Conclude a request, answering with a plain 500 if it could not be handled
+///
+/// A request that could not be parsed is answered like one that could not be read.
+/// Returns whether the connection can be used for another request.
+async fn finish_request<const MAX_RESPONSE_SIZE: usize>(
+    socket: &mut TcpSocket<'_>,
+    result: Result<bool, Error>,
+) -> bool {
+    match result {
+        Ok(keep_alive) => keep_alive,
+        Err(e) if framing_error_status(&e).is_some() => {
+            reject::<_, MAX_RESPONSE_SIZE>(socket, &e).await;
+            false
+        }
+        Err(e) => {
+            error!("Error handling request: {:?}", e);
+            // Send a 500 error response
+            let error_response = b"HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\nContent-Length: 21\r\nConnection: close\r\n\r\nInternal Server Error";
+            let _ = socket.write_all(error_response).await;
+            false
+        }
+    }
+}
+
+/// Answer a request that could not be read, if it deserves an answer
+async fn reject<C: EmbeddedWrite, const MAX_RESPONSE_SIZE: usize>(conn: &mut C, error: &Error) {
+    warn!("Read error: {:?}", error);
+    if let Some(status_code) = framing_error_status(error) {
+        let response = plain_response::<MAX_RESPONSE_SIZE>(status_code);
+        let _ = conn.write_all(&response).await;
+    }
+}
+
+/// Header section of a request as it sits in the request buffer
+#[derive(Debug)]
+struct Head {
+    /// Offset of the blank line ending the header section
+    end: usize,
+    /// Bytes read so far, including any part of the body
+    filled: usize,
+    framing: BodyFraming,
+}
+
+/// Read until the header section of a request is in `buffer`
+///
+/// The first `filled` bytes of `buffer` were already received.
+async fn read_head<C>(conn: &mut C, buffer: &mut [u8], mut filled: usize) -> Result<Head, Error>
+where
+    C: EmbeddedRead,
+    Error: From<C::Error>,
+{
+    loop {
+        if let Some(end) = find_double_crlf(&buffer[..filled]) {
+            let framing = BodyFraming::from_head(&buffer[..end])?;
+            return Ok(Head {
+                end,
+                filled,
+                framing,
+            });
+        }
+        if filled == buffer.len() {
+            return Err(Error::HeaderTooLong);
+        }
+        filled += read_some(conn, &mut buffer[filled..]).await?;
     }
 }
 
+/// Read one complete request into `buffer`
+///
+/// Reading continues across TCP segments until the header section and the whole body have
+/// arrived. A chunked body is decoded in place, so the request in the buffer carries the
+/// plain body right after its headers. Returns the length of the request.
+///
+/// `filled` counts the bytes in `buffer`: those already received when called, and on return
+/// the request plus anything received after it, such as a pipelined request.
+async fn read_request<C>(
+    conn: &mut C,
+    buffer: &mut [u8],
+    filled: &mut usize,
+) -> Result<usize, Error>
+where
+    C: EmbeddedRead,
+    Error: From<C::Error>,
+{
+    let head = read_head(conn, buffer, *filled).await?;
+    *filled = head.filled;
+    let body_start = head.end + 4;
+
+    match head.framing {
+        BodyFraming::Length(len) => {
+            let total = body_start
+                .checked_add(len)
+                .filter(|&total| total <= buffer.len())
+                .ok_or(Error::RequestTooLarge)?;
+            while *filled < total {
+                *filled += read_some(conn, &mut buffer[*filled..]).await?;
+            }
+            Ok(total)
+        }
+        BodyFraming::Chunked => {
+            let mut decoder = ChunkedDecoder::new();
+            let mut body_end = body_start;
+            loop {
+                let (read, written) = decoder.decode_in_place(&mut buffer[body_end..*filled])?;
+                if decoder.is_done() {
+                    // Keep whatever follows the request right behind it
+                    buffer.copy_within(body_end + read..*filled, body_end + written);
+                    *filled -= read - written;
+                    return Ok(body_end + written);
+                }
+                body_end += written;
+                *filled = body_end;
+                if *filled == buffer.len() {
+                    return Err(Error::RequestTooLarge);
+                }
+                *filled += read_some(conn, &mut buffer[*filled..]).await?;
+            }
+        }
+    }
+}
+
+/// Status to answer a request that could not be read or parsed with, if any
+///
+/// A connection that failed or was closed early is dropped without an answer.
+fn framing_error_status(error: &Error) -> Option<StatusCode> {
+    match error {
+        Error::RequestTooLarge | Error::HeaderTooLong | Error::TooManyHeaders => {
+            Some(StatusCode::RequestEntityTooLarge)
+        }
+        Error::InvalidResponse(_) | Error::MalformedRequestLine => Some(StatusCode::BadRequest),
+        _ => None,
+    }
+}
+
+/// A `text/plain` response carrying the status text, closing the connection
+fn plain_response<const MAX_RESPONSE_SIZE: usize>(
+    status_code: StatusCode,
+) -> Vec<u8, MAX_RESPONSE_SIZE> {
+    let mut headers = Vec::new();
+    let _ = headers.push(HttpHeader::new("Content-Type", "text/plain"));
+    let _ = headers.push(HttpHeader::new("Connection", "close"));
+    HttpResponse {
+        status_code,
+        headers,
+        body: ResponseBody::Text(status_code.text()),
+    }
+    .build_bytes()
+    // Only a response buffer too small for any response leaves nothing to send
+    .unwrap_or_default()
+}
+
 /// Type alias for `HttpServer` with default buffer sizes (4KB each)
 pub type DefaultHttpServer =
     HttpServer<SERVER_BUFFER_SIZE, SERVER_BUFFER_SIZE, MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE>;
@@ -218,9 +845,12 @@ mod tests {
         assert_eq!(server.timeouts.accept_timeout, 10);
         assert_eq!(server.timeouts.read_timeout, 30);
         assert_eq!(server.timeouts.handler_timeout, 60);
+        assert_eq!(server.timeouts.keep_alive_timeout, 5);
+        assert_eq!(server.max_requests, 100);
 
-        let server: SmallHttpServer = HttpServer::new(3000);
+        let server: SmallHttpServer = HttpServer::new(3000).with_max_requests_per_connection(0);
         assert_eq!(server.port, 3000);
+        assert_eq!(server.max_requests, 1);
     }
 
     #[test]
@@ -244,4 +874,344 @@ mod tests {
         assert_eq!(server.timeouts.read_timeout, 15);
         assert_eq!(server.timeouts.handler_timeout, 45);
     }
+
+    /// Hands out a request in fixed segments, as if they arrived in separate TCP packets
+    struct Segments<'a> {
+        segments: &'a [&'a [u8]],
+        offset: usize,
+    }
+
+    impl embedded_io_async::ErrorType for Segments<'_> {
+        type Error = embassy_net::tcp::Error;
+    }
+
+    impl EmbeddedRead for Segments<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let Some((first, rest)) = self.segments.split_first() else {
+                return Ok(0);
+            };
+            let first = &first[self.offset..];
+            let n = buf.len().min(first.len());
+            buf[..n].copy_from_slice(&first[..n]);
+            if n == first.len() {
+                self.segments = rest;
+                self.offset = 0;
+            } else {
+                self.offset += n;
+            }
+            Ok(n)
+        }
+    }
+
+    fn read(segments: &[&[u8]], buffer: &mut [u8]) -> Result<usize, Error> {
+        futures_lite::future::block_on(read_request(
+            &mut Segments {
+                segments,
+                offset: 0,
+            },
+            buffer,
+            &mut 0,
+        ))
+    }
+
+    #[test]
+    fn test_read_request_across_segments() {
+        let mut buffer = [0u8; 128];
+        let len = read(
+            &[
+                b"POST /data HTTP/1.1\r\nContent-",
+                b"Length: 11\r\n\r\n",
+                b"hello",
+                b" world",
+            ],
+            &mut buffer,
+        )
+        .unwrap();
+        assert_eq!(
+            HttpRequest::try_from(&buffer[..len]).unwrap().body,
+            b"hello world"
+        );
+
+        let len = read(&[b"GET / HTTP/1.1\r\n\r\n"], &mut buffer).unwrap();
+        assert_eq!(len, 18);
+    }
+
+    #[test]
+    fn test_read_chunked_request() {
+        let mut buffer = [0u8; 128];
+        let len = read(
+            &[
+                b"POST /up HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r",
+                b"\nhel",
+                b"lo\r\n6\r\n world\r\n0\r\n\r\n",
+            ],
+            &mut buffer,
+        )
+        .unwrap();
+        assert_eq!(
+            HttpRequest::try_from(&buffer[..len]).unwrap().body,
+            b"hello world"
+        );
+
+        let mut small = [0u8; 64];
+        let err = read(
+            &[
+                b"POST /up HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                b"20\r\n0123456789abcdef0123456789abcdef\r\n0\r\n\r\n",
+            ],
+            &mut small,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::RequestTooLarge));
+    }
+
+    #[test]
+    fn test_read_pipelined_requests() {
+        let mut conn = Segments {
+            segments: &[
+                b"POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiPOST /b HTTP/1.1\r\n",
+                b"Transfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\nGET /c HTTP/1.1\r\n\r\n",
+            ],
+            offset: 0,
+        };
+        let mut buffer = [0u8; 128];
+        let mut filled = 0;
+        let mut paths = std::vec::Vec::new();
+        for _ in 0..3 {
+            let len =
+                futures_lite::future::block_on(read_request(&mut conn, &mut buffer, &mut filled))
+                    .unwrap();
+            let request = HttpRequest::try_from(&buffer[..len]).unwrap();
+            paths.push((request.path.to_owned(), request.body.to_vec()));
+            drop(request);
+            buffer.copy_within(len..filled, 0);
+            filled -= len;
+        }
+        assert_eq!(
+            paths,
+            [
+                ("/a".to_owned(), b"hi".to_vec()),
+                ("/b".to_owned(), b"abc".to_vec()),
+                ("/c".to_owned(), b"".to_vec()),
+            ]
+        );
+        assert_eq!(filled, 0);
+    }
+
+    #[test]
+    fn test_set_connection() {
+        let response = |body| HttpResponse {
+            status_code: StatusCode::Ok,
+            headers: Vec::new(),
+            body,
+        };
+
+        let mut empty = response(ResponseBody::Empty);
+        assert!(set_connection(&mut empty, true, false));
+        assert_eq!(empty.get_header("Content-Length"), Some("0"));
+        assert_eq!(empty.get_header("Connection"), Some("keep-alive"));
+
+        let mut text = response(ResponseBody::Text("hi"));
+        assert!(!set_connection(&mut text, false, false));
+        assert_eq!(text.get_header("Connection"), Some("close"));
+
+        let mut closing = response(ResponseBody::Text("bye"));
+        let _ = closing.headers.push(HttpHeader::new("Connection", "close"));
+        assert!(!set_connection(&mut closing, true, false));
+        assert_eq!(closing.headers.len(), 1);
+    }
+
+    /// Collects everything written to it
+    struct Sink(std::vec::Vec<u8>);
+
+    impl embedded_io_async::ErrorType for Sink {
+        type Error = embassy_net::tcp::Error;
+    }
+
+    impl EmbeddedWrite for Sink {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn send_stream_response(length: Option<usize>, chunked: bool) -> (std::string::String, bool) {
+        let mut data: &[u8] = b"hello world";
+        let mut source = |buf: &mut [u8]| {
+            let n = buf.len().min(data.len()).min(4);
+            buf[..n].copy_from_slice(&data[..n]);
+            data = &data[n..];
+            Ok(n)
+        };
+        let stream = match length {
+            Some(length) => BodyStream::with_length(&mut source, length),
+            None => BodyStream::new(&mut source),
+        };
+        let response = ServerResponse::streamed(StatusCode::Ok, Vec::new(), stream);
+        let mut sink = Sink(std::vec::Vec::new());
+        let keep_alive = futures_lite::future::block_on(send_response::<_, 128>(
+            &mut sink, response, true, chunked, false,
+        ));
+        (std::string::String::from_utf8(sink.0).unwrap(), keep_alive)
+    }
+
+    #[test]
+    fn test_send_streamed_response() {
+        assert_eq!(
+            send_stream_response(None, true),
+            (
+                "HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 4\r\nhell\r\n4\r\no wo\r\n3\r\nrld\r\n0\r\n\r\n"
+                    .to_owned(),
+                true
+            )
+        );
+        assert_eq!(
+            send_stream_response(Some(11), true),
+            (
+                "HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 11\r\n\r\nhello world"
+                    .to_owned(),
+                true
+            )
+        );
+
+        // HTTP/1.0 clients get the body delimited by closing the connection
+        assert_eq!(
+            send_stream_response(None, false),
+            (
+                "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nhello world".to_owned(),
+                false
+            )
+        );
+
+        // A source that ends before the announced length forces the connection closed
+        let (_, keep_alive) = send_stream_response(Some(20), true);
+        assert!(!keep_alive);
+    }
+
+    #[test]
+    fn test_head_response_has_no_body() {
+        let mut conn = Segments {
+            segments: &[b"HEAD /a HTTP/1.1\r\n\r\nGET /a HTTP/1.1\r\n\r\n"],
+            offset: 0,
+        };
+        let mut buffer = [0u8; 128];
+        let mut filled = 0;
+        let mut sink = Sink(std::vec::Vec::new());
+        for _ in 0..2 {
+            let len =
+                futures_lite::future::block_on(read_request(&mut conn, &mut buffer, &mut filled))
+                    .unwrap();
+            let request = HttpRequest::try_from(&buffer[..len]).unwrap();
+            let response = HttpResponse {
+                status_code: StatusCode::Ok,
+                headers: Vec::new(),
+                body: ResponseBody::Text("hello"),
+            };
+            assert!(futures_lite::future::block_on(send_response::<_, 128>(
+                &mut sink,
+                response.into(),
+                true,
+                true,
+                request.method == HttpMethod::HEAD,
+            )));
+            drop(request);
+            buffer.copy_within(len..filled, 0);
+            filled -= len;
+        }
+        assert_eq!(
+            core::str::from_utf8(&sink.0).unwrap(),
+            "HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 5\r\n\r\n\
+             HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 5\r\n\r\nhello"
+        );
+
+        let mut source = |_: &mut [u8]| -> Result<usize, Error> { panic!("body was produced") };
+        let response = ServerResponse::streamed(
+            StatusCode::Ok,
+            Vec::new(),
+            BodyStream::with_length(&mut source, 11),
+        );
+        let mut sink = Sink(std::vec::Vec::new());
+        assert!(futures_lite::future::block_on(send_response::<_, 128>(
+            &mut sink, response, true, true, true,
+        )));
+        assert_eq!(
+            core::str::from_utf8(&sink.0).unwrap(),
+            "HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 11\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_oversized_response_becomes_500() {
+        let body = [b'x'; 200];
+        let response = HttpResponse {
+            status_code: StatusCode::Ok,
+            headers: Vec::new(),
+            body: ResponseBody::Binary(&body),
+        };
+        let mut sink = Sink(std::vec::Vec::new());
+        let keep_alive = futures_lite::future::block_on(send_response::<_, 128>(
+            &mut sink,
+            response.into(),
+            true,
+            true,
+            false,
+        ));
+        assert!(!keep_alive);
+        let sent = core::str::from_utf8(&sink.0).unwrap();
+        assert!(sent.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(sent.contains("Connection: close\r\n"));
+        assert!(sent.ends_with("\r\n\r\nInternal Server Error"));
+    }
+
+    #[test]
+    fn test_malformed_request_is_bad_request() {
+        for bad in [&b"GARBAGE\r\n\r\n"[..], b"FETCH / HTTP/1.1\r\n\r\n"] {
+            let mut buffer = [0u8; 64];
+            let len = read(&[bad], &mut buffer).unwrap();
+            let err = HttpRequest::try_from(&buffer[..len]).unwrap_err();
+
+            let mut sink = Sink(std::vec::Vec::new());
+            futures_lite::future::block_on(reject::<_, 128>(&mut sink, &err));
+            let sent = core::str::from_utf8(&sink.0).unwrap();
+            assert!(sent.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+            assert!(sent.contains("Connection: close\r\n"));
+        }
+    }
+
+    #[test]
+    fn test_read_request_framing_errors() {
+        let mut buffer = [0u8; 64];
+        let head = b"POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n";
+        let err = read(&[head], &mut buffer).unwrap_err();
+        assert!(matches!(err, Error::RequestTooLarge));
+        assert_eq!(
+            framing_error_status(&err),
+            Some(StatusCode::RequestEntityTooLarge)
+        );
+
+        for bad in [
+            &b"POST / HTTP/1.1\r\nContent-Length: ten\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+            b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+        ] {
+            let err = read(&[bad], &mut buffer).unwrap_err();
+            assert_eq!(framing_error_status(&err), Some(StatusCode::BadRequest));
+        }
+
+        let err = read(&[&[b'a'; 64]], &mut buffer).unwrap_err();
+        assert!(matches!(err, Error::HeaderTooLong));
+
+        let err = read(
+            &[b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab"],
+            &mut buffer,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::PrematureEof));
+        assert_eq!(framing_error_status(&err), None);
+    }
 }
@@ -0,0 +1,395 @@
+use crate::{
+    error::{Error, Socks5Error},
+    proxy::ProxyCredentials,
+};
+use core::net::{Ipv4Addr, Ipv6Addr};
+use embedded_io_async::{Read as EmbeddedRead, ReadExactError, Write as EmbeddedWrite};
+use embedded_io_async_07 as embedded_io_async;
+
+/// SOCKS protocol version 5
+const VERSION: u8 = 0x05;
+/// Version of the username/password sub-negotiation (RFC 1929)
+const AUTH_VERSION: u8 = 0x01;
+/// Authentication method: no authentication required
+const METHOD_NO_AUTH: u8 = 0x00;
+/// Authentication method: username/password
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+/// Server reply when none of the offered methods is acceptable
+const METHOD_NO_ACCEPTABLE: u8 = 0xFF;
+/// Command: establish a TCP/IP stream connection
+const CMD_CONNECT: u8 = 0x01;
+/// Address type: IPv4 address
+const ATYP_IPV4: u8 = 0x01;
+/// Address type: fully-qualified domain name, resolved by the proxy
+const ATYP_DOMAIN: u8 = 0x03;
+/// Address type: IPv6 address
+const ATYP_IPV6: u8 = 0x04;
+
+/// SOCKS5 proxy configuration
+///
+/// Requests are tunnelled through the proxy with a `CONNECT` command. Host names are
+/// passed to the proxy unresolved, so DNS lookups of the target happen on the proxy side.
+#[derive(Clone, Copy, Debug)]
+pub struct Socks5Proxy<'a> {
+    /// Host name or IP address of the proxy
+    pub host: &'a str,
+    /// TCP port of the proxy
+    pub port: u16,
+    /// Optional credentials for username/password authentication (RFC 1929)
+    pub credentials: Option<ProxyCredentials<'a>>,
+}
+
+impl<'a> Socks5Proxy<'a> {
+    /// Create a proxy configuration without authentication
+    #[must_use]
+    pub const fn new(host: &'a str, port: u16) -> Self {
+        Self {
+            host,
+            port,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate against the proxy with a username and password
+    #[must_use]
+    pub const fn with_credentials(mut self, username: &'a str, password: &'a str) -> Self {
+        self.credentials = Some(ProxyCredentials { username, password });
+        self
+    }
+}
+
+/// Read exactly `buf.len()` bytes, reporting a closed connection as [`Error::PrematureEof`]
+async fn read_exact<C>(conn: &mut C, buf: &mut [u8]) -> Result<(), Error>
+where
+    C: EmbeddedRead,
+    Error: From<C::Error>,
+{
+    conn.read_exact(buf).await.map_err(|e| match e {
+        ReadExactError::UnexpectedEof => Error::PrematureEof,
+        ReadExactError::Other(e) => Error::from(e),
+    })
+}
+
+/// Append a length-prefixed field as used by SOCKS5 for domain names and credentials
+fn push_prefixed(out: &mut heapless::Vec<u8, 513>, field: &[u8]) -> Result<(), Error> {
+    let len = u8::try_from(field.len()).map_err(|_| Socks5Error::NameTooLong)?;
+    // The buffer holds two maximum-length fields, so these cannot overflow
+    let _ = out.push(len);
+    let _ = out.extend_from_slice(field);
+    Ok(())
+}
+
+/// Negotiate a SOCKS5 `CONNECT` to `host:port`
+///
+/// `conn` must already be connected to the proxy. On success the connection carries
+/// raw bytes to the target.
+pub(crate) async fn socks5_connect<C>(
+    conn: &mut C,
+    proxy: &Socks5Proxy<'_>,
+    host: &str,
+    port: u16,
+) -> Result<(), Error>
+where
+    C: EmbeddedRead + EmbeddedWrite,
+    Error: From<C::Error>,
+{
+    let mut message = heapless::Vec::<u8, 513>::new();
+    let mut reply = [0u8; 2];
+
+    // Method selection
+    if proxy.credentials.is_some() {
+        conn.write_all(&[VERSION, 2, METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD])
+            .await?;
+    } else {
+        conn.write_all(&[VERSION, 1, METHOD_NO_AUTH]).await?;
+    }
+    conn.flush().await?;
+    read_exact(conn, &mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(Socks5Error::InvalidVersion.into());
+    }
+
+    match (reply[1], &proxy.credentials) {
+        (METHOD_NO_AUTH, _) => {}
+        (METHOD_USERNAME_PASSWORD, Some(credentials)) => {
+            let _ = message.push(AUTH_VERSION);
+            push_prefixed(&mut message, credentials.username.as_bytes())?;
+            push_prefixed(&mut message, credentials.password.as_bytes())?;
+            conn.write_all(&message).await?;
+            conn.flush().await?;
+
+            read_exact(conn, &mut reply).await?;
+            if reply[0] != AUTH_VERSION {
+                return Err(Socks5Error::InvalidVersion.into());
+            }
+            if reply[1] != 0 {
+                return Err(Socks5Error::AuthenticationFailed.into());
+            }
+            message.clear();
+        }
+        (METHOD_NO_ACCEPTABLE, _) => return Err(Socks5Error::NoAcceptableAuthMethod.into()),
+        // The proxy picked a method that was never offered
+        _ => return Err(Socks5Error::InvalidResponse.into()),
+    }
+
+    // Connect request
+    let _ = message.extend_from_slice(&[VERSION, CMD_CONNECT, 0x00]);
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        let _ = message.push(ATYP_IPV4);
+        let _ = message.extend_from_slice(&ip.octets());
+    } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        let _ = message.push(ATYP_IPV6);
+        let _ = message.extend_from_slice(&ip.octets());
+    } else {
+        let _ = message.push(ATYP_DOMAIN);
+        push_prefixed(&mut message, host.as_bytes())?;
+    }
+    let _ = message.extend_from_slice(&port.to_be_bytes());
+    conn.write_all(&message).await?;
+    conn.flush().await?;
+
+    // Reply header plus the first byte of the bound address, which is the length
+    // prefix for domain names
+    let mut head = [0u8; 5];
+    read_exact(conn, &mut head).await?;
+    if head[0] != VERSION {
+        return Err(Socks5Error::InvalidVersion.into());
+    }
+    if head[1] != 0 {
+        return Err(Socks5Error::from_reply(head[1]).into());
+    }
+
+    // Skip the rest of the bound address and port, which are not needed
+    let remaining = match head[3] {
+        ATYP_IPV4 => 4 - 1 + 2,
+        ATYP_IPV6 => 16 - 1 + 2,
+        ATYP_DOMAIN => usize::from(head[4]) + 2,
+        _ => return Err(Socks5Error::InvalidResponse.into()),
+    };
+    let mut bound = [0u8; 257];
+    read_exact(conn, &mut bound[..remaining]).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_net_08::tcp;
+    use std::{collections::VecDeque, string::String, vec::Vec};
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum Stage {
+        Greeting,
+        Auth,
+        Request,
+        Done,
+    }
+
+    /// In-process SOCKS5 server that answers the handshake as the client writes it
+    struct FakeSocksServer {
+        users: Option<(&'static str, &'static str)>,
+        reply: u8,
+        stage: Stage,
+        inbox: Vec<u8>,
+        outbox: VecDeque<u8>,
+        target: Option<(u8, Vec<u8>, u16)>,
+    }
+
+    impl FakeSocksServer {
+        fn new(users: Option<(&'static str, &'static str)>, reply: u8) -> Self {
+            Self {
+                users,
+                reply,
+                stage: Stage::Greeting,
+                inbox: Vec::new(),
+                outbox: VecDeque::new(),
+                target: None,
+            }
+        }
+
+        /// Consume complete client messages from `inbox` and queue the answers
+        fn process(&mut self) {
+            loop {
+                let data = &self.inbox[..];
+                let consumed = match self.stage {
+                    Stage::Greeting if data.len() >= 2 && data.len() >= 2 + data[1] as usize => {
+                        let methods = &data[2..2 + data[1] as usize];
+                        let method = match self.users {
+                            Some(_) if methods.contains(&METHOD_USERNAME_PASSWORD) => {
+                                self.stage = Stage::Auth;
+                                METHOD_USERNAME_PASSWORD
+                            }
+                            None if methods.contains(&METHOD_NO_AUTH) => {
+                                self.stage = Stage::Request;
+                                METHOD_NO_AUTH
+                            }
+                            _ => {
+                                self.stage = Stage::Done;
+                                METHOD_NO_ACCEPTABLE
+                            }
+                        };
+                        self.outbox.extend([VERSION, method]);
+                        2 + data[1] as usize
+                    }
+                    Stage::Auth if data.len() >= 2 => {
+                        let ulen = data[1] as usize;
+                        let Some(&plen) = data.get(2 + ulen) else {
+                            return;
+                        };
+                        let end = 3 + ulen + plen as usize;
+                        if data.len() < end {
+                            return;
+                        }
+                        let username = &data[2..2 + ulen];
+                        let password = &data[3 + ulen..end];
+                        let (u, p) = self.users.unwrap();
+                        let ok = username == u.as_bytes() && password == p.as_bytes();
+                        self.outbox.extend([AUTH_VERSION, u8::from(!ok)]);
+                        self.stage = if ok { Stage::Request } else { Stage::Done };
+                        end
+                    }
+                    Stage::Request if data.len() >= 5 => {
+                        let addr_len = match data[3] {
+                            ATYP_IPV4 => 4,
+                            ATYP_IPV6 => 16,
+                            _ => 1 + data[4] as usize,
+                        };
+                        let end = 4 + addr_len + 2;
+                        if data.len() < end {
+                            return;
+                        }
+                        let port = u16::from_be_bytes([data[end - 2], data[end - 1]]);
+                        self.target = Some((data[3], data[4..end - 2].to_vec(), port));
+                        // Bound address reported as a domain to exercise variable-length replies
+                        self.outbox.extend([
+                            VERSION,
+                            self.reply,
+                            0,
+                            ATYP_DOMAIN,
+                            5,
+                            b'p',
+                            b'r',
+                            b'o',
+                        ]);
+                        self.outbox.extend([b'x', b'y', 0x1f, 0x90]);
+                        self.stage = Stage::Done;
+                        end
+                    }
+                    _ => return,
+                };
+                self.inbox.drain(..consumed);
+            }
+        }
+    }
+
+    impl embedded_io_async::ErrorType for FakeSocksServer {
+        type Error = tcp::Error;
+    }
+
+    impl EmbeddedRead for FakeSocksServer {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            // Hand out at most a few bytes per call to exercise partial reads
+            let n = buf.len().min(self.outbox.len()).min(3);
+            for (dst, src) in buf.iter_mut().zip(self.outbox.drain(..n)) {
+                *dst = src;
+            }
+            Ok(n)
+        }
+    }
+
+    impl EmbeddedWrite for FakeSocksServer {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.inbox.extend_from_slice(buf);
+            self.process();
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn connect(
+        server: &mut FakeSocksServer,
+        proxy: &Socks5Proxy<'_>,
+        host: &str,
+    ) -> Result<(), Error> {
+        futures_lite::future::block_on(socks5_connect(server, proxy, host, 443))
+    }
+
+    #[test]
+    fn test_socks5_no_auth_remote_dns() {
+        let mut server = FakeSocksServer::new(None, 0);
+        connect(&mut server, &Socks5Proxy::new("gw", 1080), "example.com").unwrap();
+
+        let (atyp, addr, port) = server.target.unwrap();
+        assert_eq!(atyp, ATYP_DOMAIN);
+        assert_eq!(addr[0] as usize, "example.com".len());
+        assert_eq!(String::from_utf8_lossy(&addr[1..]), "example.com");
+        assert_eq!(port, 443);
+        assert!(server.outbox.is_empty());
+    }
+
+    #[test]
+    fn test_socks5_ip_address() {
+        let mut server = FakeSocksServer::new(None, 0);
+        connect(&mut server, &Socks5Proxy::new("gw", 1080), "192.168.1.7").unwrap();
+        assert_eq!(server.target.unwrap().1, [192, 168, 1, 7]);
+    }
+
+    #[test]
+    fn test_socks5_username_password() {
+        let proxy = Socks5Proxy::new("gw", 1080).with_credentials("user", "secret");
+
+        let mut server = FakeSocksServer::new(Some(("user", "secret")), 0);
+        connect(&mut server, &proxy, "example.com").unwrap();
+        assert!(server.target.is_some());
+
+        let mut server = FakeSocksServer::new(Some(("user", "other")), 0);
+        assert!(matches!(
+            connect(&mut server, &proxy, "example.com"),
+            Err(Error::Socks5(Socks5Error::AuthenticationFailed))
+        ));
+        assert!(server.target.is_none());
+    }
+
+    #[test]
+    fn test_socks5_no_acceptable_method() {
+        // The server requires credentials but the client has none to offer
+        let mut server = FakeSocksServer::new(Some(("user", "secret")), 0);
+        assert!(matches!(
+            connect(&mut server, &Socks5Proxy::new("gw", 1080), "example.com"),
+            Err(Error::Socks5(Socks5Error::NoAcceptableAuthMethod))
+        ));
+    }
+
+    #[test]
+    fn test_socks5_connect_refused() {
+        let mut server = FakeSocksServer::new(None, 5);
+        assert!(matches!(
+            connect(&mut server, &Socks5Proxy::new("gw", 1080), "example.com"),
+            Err(Error::Socks5(Socks5Error::ConnectionRefused))
+        ));
+    }
+
+    #[test]
+    fn test_socks5_host_name_too_long() {
+        let mut server = FakeSocksServer::new(None, 0);
+        let host = "a".repeat(256);
+        assert!(matches!(
+            connect(&mut server, &Socks5Proxy::new("gw", 1080), &host),
+            Err(Error::Socks5(Socks5Error::NameTooLong))
+        ));
+    }
+
+    #[test]
+    fn test_socks5_truncated_reply() {
+        let mut server = FakeSocksServer::new(None, 0);
+        server.stage = Stage::Done;
+        assert!(matches!(
+            connect(&mut server, &Socks5Proxy::new("gw", 1080), "example.com"),
+            Err(Error::PrematureEof)
+        ));
+    }
+}
@@ -78,6 +78,8 @@ pub enum StatusCode {
     RequestedRangeNotSatisfiable = 416,
     /// 417 Expectation Failed: The server cannot meet the requirements of the Expect request-header field.
     ExpectationFailed = 417,
+    /// 429 Too Many Requests: The user has sent too many requests in a given amount of time.
+    TooManyRequests = 429,
 
     // 5xx Server Error
     /// 500 Internal Server Error: The server has encountered a situation it doesn't know how to handle.
@@ -136,6 +138,7 @@ impl StatusCode {
             StatusCode::UnsupportedMediaType => 415,
             StatusCode::RequestedRangeNotSatisfiable => 416,
             StatusCode::ExpectationFailed => 417,
+            StatusCode::TooManyRequests => 429,
             StatusCode::InternalServerError => 500,
             StatusCode::NotImplemented => 501,
             StatusCode::BadGateway => 502,
@@ -187,6 +190,7 @@ impl StatusCode {
             StatusCode::UnsupportedMediaType => "Unsupported Media Type",
             StatusCode::RequestedRangeNotSatisfiable => "Requested Range Not Satisfiable",
             StatusCode::ExpectationFailed => "Expectation Failed",
+            StatusCode::TooManyRequests => "Too Many Requests",
             // 5xx
             StatusCode::InternalServerError => "Internal Server Error",
             StatusCode::NotImplemented => "Not Implemented",
@@ -257,6 +261,7 @@ impl From<u16> for StatusCode {
             415 => StatusCode::UnsupportedMediaType,
             416 => StatusCode::RequestedRangeNotSatisfiable,
             417 => StatusCode::ExpectationFailed,
+            429 => StatusCode::TooManyRequests,
             500 => StatusCode::InternalServerError,
             501 => StatusCode::NotImplemented,
             502 => StatusCode::BadGateway,